@@ -3,14 +3,47 @@ extern crate hack_hdsim_lib;
 #[macro_use]
 extern crate error_chain;
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use hack_hdsim_lib::aiger::Aig;
+use hack_hdsim_lib::chip::{BuiltinChips, Chip};
+use hack_hdsim_lib::computer;
+use hack_hdsim_lib::equivalence;
+use hack_hdsim_lib::lint::{LintConfig, LintRule};
+use hack_hdsim_lib::netlist::{Netlist, NetlistSimulator};
+use hack_hdsim_lib::random::Random;
+use hack_hdsim_lib::script::{self, PinSelect};
+use serde_json::json;
 use structopt::StructOpt;
 
+mod repl;
+
 mod errors {
     error_chain! {
         types {
             Error, ErrorKind, ResultExt, Result;
         }
-        errors { FileReadError(filepath: std::path::PathBuf) }
+        errors {
+            FileReadError(filepath: std::path::PathBuf)
+            FileWriteError(filepath: std::path::PathBuf)
+            ParseError(filepath: std::path::PathBuf)
+            NotFormatted(filepaths: Vec<std::path::PathBuf>)
+            LintWarnings(count: usize)
+            InvalidChips(count: usize)
+            ScriptError(filepath: std::path::PathBuf)
+            TestFailed(mismatches: usize)
+            BadAssignment(assignment: String)
+            NoExportFormat
+            TerminalError
+            TruthTableError(filepath: std::path::PathBuf)
+            EquivalenceError(filepath: std::path::PathBuf)
+            ExportError(filepath: std::path::PathBuf)
+            NotEquivalent
+            HackFileError(filepath: std::path::PathBuf)
+            NoMemory(filepath: std::path::PathBuf, part: String)
+            MemoryParts(filepath: std::path::PathBuf, part: String)
+        }
     }
 }
 
@@ -19,34 +52,1094 @@ pub use errors::{Error, ErrorKind, Result, ResultExt};
 /// Rust version of Nand2Tetris's hardware simulator
 #[derive(StructOpt, Debug)]
 pub struct Opt {
-    /// .hdl file to read
-    #[structopt(name = "HDLFILE", parse(from_os_str))]
-    pub file: std::path::PathBuf,
+    /// How results are printed, text or json
+    #[structopt(
+        long,
+        global = true,
+        default_value = "text",
+        possible_values = &["text", "json"]
+    )]
+    pub format: OutputFormat,
+    #[structopt(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Print the tokens of an .hdl file
+    Tokens {
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+    /// Reformat .hdl files in place
+    Fmt {
+        /// Only report files that are not formatted
+        #[structopt(long)]
+        check: bool,
+        /// .hdl files to format
+        #[structopt(name = "HDLFILE", parse(from_os_str), required = true)]
+        files: Vec<std::path::PathBuf>,
+    },
+    /// Warn about unused pins and parts and other likely mistakes
+    Lint {
+        /// Rule to turn off, can be given more than once
+        #[structopt(long, parse(try_from_str = parse_lint_rule))]
+        disable: Vec<LintRule>,
+        /// .hdl files to check
+        #[structopt(name = "HDLFILE", parse(from_os_str), required = true)]
+        files: Vec<std::path::PathBuf>,
+    },
+    /// Check that .hdl files parse and their parts connect
+    Check {
+        /// .hdl files to check
+        #[structopt(name = "HDLFILE", parse(from_os_str), required = true)]
+        files: Vec<std::path::PathBuf>,
+    },
+    /// Run a .tst script, writing its output file and comparing it
+    Test {
+        /// Simulate with 0, 1, X and Z so that registers that were never
+        /// loaded and pins nothing drives show up in the output
+        #[structopt(long)]
+        four_valued: bool,
+        /// .tst file to run
+        #[structopt(name = "TSTFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+    /// Set inputs and print the outputs once they settle
+    Eval {
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+        /// Input values like a=1, in=%B0101 or in[0..7]=%XFF
+        #[structopt(name = "ASSIGN")]
+        assignments: Vec<String>,
+    },
+    /// Clock the chip with inputs held and print the final outputs
+    Clock {
+        /// Number of clock cycles
+        #[structopt(long, default_value = "1")]
+        cycles: usize,
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+        /// Input values like a=1, in=%B0101 or in[0..7]=%XFF
+        #[structopt(name = "ASSIGN")]
+        assignments: Vec<String>,
+    },
+    /// Like clock but print all pins after every tick and tock
+    Trace {
+        /// Number of clock cycles
+        #[structopt(long, default_value = "1")]
+        cycles: usize,
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+        /// Input values like a=1, in=%B0101 or in[0..7]=%XFF
+        #[structopt(name = "ASSIGN")]
+        assignments: Vec<String>,
+    },
+    /// Load a .hack program into the chip's ROM32K, run it and print the
+    /// first words of its RAM16K
+    Run {
+        /// Number of clock cycles
+        #[structopt(long, default_value = "1000")]
+        cycles: usize,
+        /// Code of the key held down on the Keyboard
+        #[structopt(long, default_value = "0")]
+        key: u16,
+        /// Number of RAM words to print from address 0
+        #[structopt(long, default_value = "16")]
+        ram: usize,
+        /// .hdl file to read, like Computer.hdl
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+        /// .hack file with the program
+        #[structopt(name = "HACKFILE", parse(from_os_str))]
+        program: std::path::PathBuf,
+    },
+    /// Count pins, parts and builtin chips
+    Stats {
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+    /// Measure evaluations or clock cycles per second with random inputs
+    Bench {
+        /// Seconds to run each simulator for
+        #[structopt(long, default_value = "1")]
+        seconds: f64,
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+    /// Print the outputs for every input combination of an unclocked chip
+    TruthTable {
+        /// Comma separated decimal values
+        #[structopt(long, conflicts_with = "cmp")]
+        csv: bool,
+        /// A compare file for test scripts
+        #[structopt(long)]
+        cmp: bool,
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+    /// Prove two chips give the same outputs for every input, or print
+    /// inputs where they differ. Clocked chips are compared cycle by cycle
+    /// from reset, for a bounded number of cycles.
+    Equiv {
+        /// Clock cycles to look ahead when either chip is clocked
+        #[structopt(long, default_value = "8")]
+        cycles: usize,
+        /// Write a .tst script replaying the difference between clocked chips
+        #[structopt(long, parse(from_os_str))]
+        tst: Option<std::path::PathBuf>,
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+        /// .hdl file or name of a builtin chip to compare with
+        #[structopt(name = "OTHER", parse(from_os_str))]
+        other: std::path::PathBuf,
+    },
+    /// Interactively set inputs, clock and look at pins
+    Repl {
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+    /// Write the chip in another format
+    Export {
+        #[structopt(flatten)]
+        formats: ExportFormats,
+        /// Directory to write to
+        #[structopt(short, long, parse(from_os_str), default_value = ".")]
+        out_dir: std::path::PathBuf,
+        /// .hdl file to read
+        #[structopt(name = "HDLFILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+}
+
+/// What `export` writes, any number of them at once
+#[derive(Debug, Default, StructOpt)]
+pub struct ExportFormats {
+    /// Formatted .hdl of the chip and every part it uses from files
+    #[structopt(long)]
+    pub hdl: bool,
+    /// Verilog modules for the chip and all its parts, in one .v file
+    #[structopt(long)]
+    pub verilog: bool,
+    /// VHDL entities for the chip and all its parts, in one .vhd file
+    #[structopt(long)]
+    pub vhdl: bool,
+    /// Graphviz graph of the chip's parts and their wires, in a .dot file
+    #[structopt(long)]
+    pub dot: bool,
+    /// Schematic of the chip's parts and their wires, in an .svg file
+    #[structopt(long)]
+    pub svg: bool,
+    /// Flattened gates and latches for other tools, in a .blif file
+    #[structopt(long)]
+    pub blif: bool,
+    /// Flattened and-inverter graph, in a binary .aig file
+    #[structopt(long)]
+    pub aiger: bool,
+    /// Flattened and-inverter graph, in an ASCII .aag file
+    #[structopt(long)]
+    pub aiger_ascii: bool,
+    /// Levels of parts inside parts to draw in the graph
+    #[structopt(long, default_value = "0")]
+    pub levels: usize,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown format {}", s)),
+        }
+    }
+}
+
+fn parse_lint_rule(name: &str) -> std::result::Result<LintRule, String> {
+    LintRule::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = LintRule::ALL.iter().map(|r| r.name()).collect();
+        format!("unknown rule, expected one of {}", names.join(", "))
+    })
 }
 
 pub fn run(opt: Opt) -> Result<()> {
-    let filepath = opt.file.as_path();
-    let contents = std::fs::read_to_string(filepath)
-        .chain_err(|| ErrorKind::FileReadError(opt.file))?;
+    let format = opt.format;
+    match opt.cmd {
+        Command::Tokens { file } => tokens(file),
+        Command::Fmt { check, files } => fmt(check, files),
+        Command::Lint { disable, files } => lint(format, disable, files),
+        Command::Check { files } => check(format, files),
+        Command::Test { four_valued, file } => {
+            let mode = match four_valued {
+                true => script::Mode::FourValued,
+                false => script::Mode::Boolean,
+            };
+            test(format, file, mode)
+        }
+        Command::Eval { file, assignments } => {
+            clock_cycles(format, file, assignments, None)
+        }
+        Command::Clock {
+            cycles,
+            file,
+            assignments,
+        } => clock_cycles(format, file, assignments, Some(cycles)),
+        Command::Trace {
+            cycles,
+            file,
+            assignments,
+        } => trace(format, file, assignments, cycles),
+        Command::Run {
+            cycles,
+            key,
+            ram,
+            file,
+            program,
+        } => run_program(format, file, program, cycles, key, ram),
+        Command::Stats { file } => stats(format, file),
+        Command::Bench { seconds, file } => bench(format, file, seconds),
+        Command::TruthTable { csv, cmp, file } => {
+            truth_table(format, file, csv, cmp)
+        }
+        Command::Equiv {
+            cycles,
+            tst,
+            file,
+            other,
+        } => equiv(format, file, other, cycles, tst),
+        Command::Repl { file } => {
+            repl::run(load_chip(&file)?).chain_err(|| ErrorKind::TerminalError)
+        }
+        Command::Export {
+            formats,
+            out_dir,
+            file,
+        } => export(formats, out_dir, file),
+    }
+}
+
+/// 1 when chips were checked and found wanting, 2 when they couldn't be
+pub fn exit_code(e: &Error) -> i32 {
+    match e.kind() {
+        ErrorKind::NotFormatted(_)
+        | ErrorKind::LintWarnings(_)
+        | ErrorKind::TestFailed(_)
+        | ErrorKind::NotEquivalent => 1,
+        _ => 2,
+    }
+}
+
+fn read_file(filepath: &std::path::Path) -> Result<String> {
+    std::fs::read_to_string(filepath)
+        .chain_err(|| ErrorKind::FileReadError(filepath.to_path_buf()))
+}
+
+fn tokens(file: std::path::PathBuf) -> Result<()> {
+    let contents = read_file(file.as_path())?;
     let mut tokeniser =
         hack_hdsim_lib::tokeniser::Tokeniser::new(contents.as_str());
-    let tokens = tokeniser.tokenise_chip().unwrap();
-    println!("{:#?}", tokens);
+    tokeniser
+        .tokenise_chip()
+        .map_err(|e| Error::with_chain(e, ErrorKind::ParseError(file)))?;
+    println!("{:#?}", tokeniser.tokens());
+    Ok(())
+}
+
+fn fmt(check: bool, files: Vec<std::path::PathBuf>) -> Result<()> {
+    let mut not_formatted = Vec::new();
+    for file in files {
+        let contents = read_file(file.as_path())?;
+        let formatted = hack_hdsim_lib::format::format_chip(contents.as_str())
+            .map_err(|e| {
+                Error::with_chain(e, ErrorKind::ParseError(file.clone()))
+            })?;
+        if formatted == contents {
+            continue;
+        }
+        if check {
+            not_formatted.push(file);
+        } else {
+            std::fs::write(file.as_path(), formatted)
+                .chain_err(|| ErrorKind::FileWriteError(file))?;
+        }
+    }
+    if !not_formatted.is_empty() {
+        return Err(ErrorKind::NotFormatted(not_formatted).into());
+    }
+    Ok(())
+}
+
+fn lint(
+    format: OutputFormat,
+    disable: Vec<LintRule>,
+    files: Vec<std::path::PathBuf>,
+) -> Result<()> {
+    let mut config = LintConfig::default();
+    for rule in disable {
+        config.disable(rule);
+    }
+    let mut count = 0;
+    let mut reports = Vec::new();
+    for file in files {
+        let mut loader = hack_hdsim_lib::loader::ChipLoader::for_file(&file);
+        let parse_error =
+            |e| Error::with_chain(e, ErrorKind::ParseError(file.clone()));
+        let decl = loader.parse_file(&file).map_err(parse_error)?;
+        let chip = loader.build(&decl).map_err(parse_error)?;
+        let stem = file.file_stem().and_then(|s| s.to_str());
+        let warnings = hack_hdsim_lib::lint::lint(&decl, &chip, stem, &config);
+        count += warnings.len();
+        match format {
+            OutputFormat::Text => {
+                for warning in warnings {
+                    println!("{}:{}", file.display(), warning);
+                }
+            }
+            OutputFormat::Json => reports.push(json!({
+                "file": file,
+                "warnings": warnings,
+            })),
+        }
+    }
+    if format == OutputFormat::Json {
+        println!("{}", json!(reports));
+    }
+    if count > 0 {
+        return Err(ErrorKind::LintWarnings(count).into());
+    }
+    Ok(())
+}
+
+fn load_chip(file: &Path) -> Result<Chip> {
+    hack_hdsim_lib::loader::ChipLoader::for_file(file)
+        .load_file(file)
+        .map_err(|e| Error::with_chain(e, ErrorKind::ParseError(file.into())))
+}
+
+/// The chip flattened to gates, an error for chips with memory parts
+fn compile(chip: &Chip, file: &Path) -> Result<Netlist> {
+    match chip.memory_part() {
+        Some(part) => {
+            Err(ErrorKind::MemoryParts(file.into(), part.to_string()).into())
+        }
+        None => Ok(Netlist::compile(chip)),
+    }
+}
+
+/// `pin=value` pairs on the command line
+fn assign(chip: &mut Chip, assignments: &[String]) -> Result<()> {
+    for assignment in assignments {
+        let bad = || ErrorKind::BadAssignment(assignment.clone());
+        let equals = assignment.find('=').ok_or_else(bad)?;
+        let pin = PinSelect::parse(&assignment[..equals]).ok_or_else(bad)?;
+        let value =
+            script::parse_value(&assignment[equals + 1..]).ok_or_else(bad)?;
+        pin.write(chip, value).ok_or_else(bad)?;
+    }
+    Ok(())
+}
+
+/// Serialised error with its message added, for `--format json`
+pub fn diagnostic<E>(e: &E) -> serde_json::Value
+where
+    E: serde::Serialize + std::fmt::Display,
+{
+    let mut value = json!(e);
+    value["message"] = json!(e.to_string());
+    value
+}
+
+/// Location of the parse or script error behind `e`, if that's the cause
+pub fn error_diagnostic(e: &Error) -> Option<serde_json::Value> {
+    let cause = e.1.next_error.as_ref()?;
+    cause
+        .downcast_ref::<hack_hdsim_lib::parser::ParseError>()
+        .map(diagnostic)
+        .or_else(|| {
+            cause
+                .downcast_ref::<hack_hdsim_lib::loader::LoadError>()
+                .map(diagnostic)
+        })
+        .or_else(|| cause.downcast_ref::<script::ScriptError>().map(diagnostic))
+}
+
+fn bits(value: u64, width: usize) -> String {
+    format!("{:0width$b}", value, width = width)
+}
+
+fn check(format: OutputFormat, files: Vec<PathBuf>) -> Result<()> {
+    let mut invalid = 0;
+    let mut reports = Vec::new();
+    for file in files {
+        let error = hack_hdsim_lib::loader::ChipLoader::for_file(&file)
+            .load_file(&file)
+            .err();
+        if error.is_some() {
+            invalid += 1;
+        }
+        match format {
+            OutputFormat::Text => match &error {
+                Some(error) => println!("{}", error),
+                None => println!("{}: ok", file.display()),
+            },
+            OutputFormat::Json => reports.push(json!({
+                "file": file,
+                "ok": error.is_none(),
+                "error": error.as_ref().map(diagnostic),
+            })),
+        }
+    }
+    if format == OutputFormat::Json {
+        println!("{}", json!(reports));
+    }
+    if invalid > 0 {
+        return Err(ErrorKind::InvalidChips(invalid).into());
+    }
+    Ok(())
+}
+
+fn test(format: OutputFormat, file: PathBuf, mode: script::Mode) -> Result<()> {
+    let outcome = script::run_file_with(&file, mode).map_err(|e| {
+        Error::with_chain(e, ErrorKind::ScriptError(file.clone()))
+    })?;
+    if let Some(output_file) = &outcome.output_file {
+        let mut contents = outcome.output.join("\n");
+        contents.push('\n');
+        std::fs::write(output_file, contents)
+            .chain_err(|| ErrorKind::FileWriteError(output_file.clone()))?;
+    }
+    match format {
+        OutputFormat::Text => {
+            for line in &outcome.echo {
+                println!("{}", line);
+            }
+            for mismatch in &outcome.mismatches {
+                println!("{}", mismatch);
+            }
+            if outcome.passed() {
+                println!("{}: passed", file.display());
+            }
+        }
+        OutputFormat::Json => {
+            let mut json = json!(outcome);
+            json["file"] = json!(file);
+            json["passed"] = json!(outcome.passed());
+            println!("{}", json);
+        }
+    }
+    if !outcome.passed() {
+        return Err(ErrorKind::TestFailed(outcome.mismatches.len()).into());
+    }
+    Ok(())
+}
+
+/// Evaluates once when `cycles` is `None`
+fn clock_cycles(
+    format: OutputFormat,
+    file: PathBuf,
+    assignments: Vec<String>,
+    cycles: Option<usize>,
+) -> Result<()> {
+    let mut chip = load_chip(&file)?;
+    assign(&mut chip, &assignments)?;
+    // Flattened once, so long runs don't walk the part tree every cycle.
+    // Memory has no gates, so chips with it stay a tree.
+    let pinlines = match Netlist::try_compile(&chip) {
+        Ok(netlist) => {
+            let mut sim = NetlistSimulator::new(netlist);
+            for pinline in &chip.pinlines.input {
+                sim.set_input(
+                    pinline.name.as_str(),
+                    pinline.get_value() as i64,
+                );
+            }
+            sim.evaluate();
+            for _ in 0..cycles.unwrap_or(0) {
+                sim.tick();
+                sim.tock();
+            }
+            sim.pinlines()
+        }
+        Err(_) => {
+            chip.evaluate();
+            for _ in 0..cycles.unwrap_or(0) {
+                chip.tick();
+                chip.tock();
+            }
+            chip.pinlines
+        }
+    };
+    match format {
+        OutputFormat::Text => {
+            if let Some(cycles) = cycles {
+                println!("time = {}", cycles);
+            }
+            for pinline in &pinlines.output {
+                println!(
+                    "{} = {}",
+                    pinline.name,
+                    bits(pinline.get_value(), pinline.pins.len())
+                );
+            }
+        }
+        OutputFormat::Json => println!(
+            "{}",
+            json!({"time": cycles.unwrap_or(0), "pinlines": pinlines})
+        ),
+    }
+    Ok(())
+}
+
+fn run_program(
+    format: OutputFormat,
+    file: PathBuf,
+    program_file: PathBuf,
+    cycles: usize,
+    key: u16,
+    ram: usize,
+) -> Result<()> {
+    let mut chip = load_chip(&file)?;
+    let text = read_file(&program_file)?;
+    let program = computer::parse_hack(&text).map_err(|e| {
+        Error::with_chain(e, ErrorKind::HackFileError(program_file))
+    })?;
+    let missing = |part: &str| ErrorKind::NoMemory(file.clone(), part.into());
+    if !chip.load_rom(&program) {
+        return Err(missing("ROM32K").into());
+    }
+    if chip.memory_word("RAM16K", 0).is_none() {
+        return Err(missing("RAM16K").into());
+    }
+    // A computer without a Keyboard just never sees the key
+    chip.set_key(key);
+    chip.evaluate();
+    for _ in 0..cycles {
+        chip.tick();
+        chip.tock();
+    }
+    // Signed, like the values programs work with
+    let words: Vec<i16> = (0..ram)
+        .map_while(|address| chip.memory_word("RAM16K", address))
+        .map(|word| word as i16)
+        .collect();
+    match format {
+        OutputFormat::Text => {
+            println!("time = {}", cycles);
+            for (address, word) in words.iter().enumerate() {
+                println!("RAM[{}] = {}", address, word);
+            }
+        }
+        OutputFormat::Json => {
+            println!("{}", json!({"time": cycles, "ram": words}))
+        }
+    }
+    Ok(())
+}
+
+fn trace(
+    format: OutputFormat,
+    file: PathBuf,
+    assignments: Vec<String>,
+    cycles: usize,
+) -> Result<()> {
+    let mut chip = load_chip(&file)?;
+    assign(&mut chip, &assignments)?;
+    chip.evaluate();
+    let pinlines: Vec<_> = chip
+        .pinlines
+        .input
+        .iter()
+        .chain(chip.pinlines.output.iter())
+        .map(|p| (p.name.clone(), p.pins.len()))
+        .collect();
+    let mut rows = Vec::new();
+    let mut row = |time: String, chip: &Chip| {
+        let values: Vec<u64> = pinlines
+            .iter()
+            .map(|(name, _)| chip.pinlines.get_pinline(name).unwrap())
+            .map(|p| p.get_value())
+            .collect();
+        rows.push((time, values));
+    };
+    row("0".to_string(), &chip);
+    for time in 0..cycles {
+        chip.tick();
+        row(format!("{}+", time), &chip);
+        chip.tock();
+        row((time + 1).to_string(), &chip);
+    }
+    match format {
+        OutputFormat::Text => {
+            let widths: Vec<usize> = pinlines
+                .iter()
+                .map(|(name, width)| name.len().max(*width))
+                .collect();
+            let mut header = String::from("| time |");
+            for ((name, _), width) in pinlines.iter().zip(widths.iter()) {
+                header.push_str(format!(" {:^w$} |", name, w = width).as_str());
+            }
+            println!("{}", header);
+            for (time, values) in rows {
+                let mut line = format!("| {:<4} |", time);
+                for (((_, bit_count), width), value) in
+                    pinlines.iter().zip(widths.iter()).zip(values)
+                {
+                    let value = bits(value, *bit_count);
+                    line.push_str(
+                        format!(" {:^w$} |", value, w = width).as_str(),
+                    );
+                }
+                println!("{}", line);
+            }
+        }
+        OutputFormat::Json => {
+            let rows: Vec<_> = rows
+                .into_iter()
+                .map(|(time, values)| {
+                    let mut row = serde_json::Map::new();
+                    row.insert("time".to_string(), json!(time));
+                    for ((name, _), value) in pinlines.iter().zip(values) {
+                        row.insert(name.clone(), json!(value));
+                    }
+                    row
+                })
+                .collect();
+            println!("{}", json!(rows));
+        }
+    }
+    Ok(())
+}
+
+/// Pins and parts of the chip, down to builtins, and its size in Nand gates
+fn stats(format: OutputFormat, file: PathBuf) -> Result<()> {
+    let chip = load_chip(&file)?;
+    let mut parts = BTreeMap::new();
+    for part in &chip.parts {
+        *parts.entry(part.chip.name.clone()).or_insert(0) += 1;
+    }
+    let expanded = chip.stats();
+    let pins = |pinlines: &[hack_hdsim_lib::chip::Pinline]| {
+        (
+            pinlines.len(),
+            pinlines.iter().map(|p| p.pins.len()).sum::<usize>(),
+        )
+    };
+    let groups = [
+        ("input", pins(&chip.pinlines.input)),
+        ("output", pins(&chip.pinlines.output)),
+        ("internal", pins(&chip.pinlines.internal)),
+    ];
+    match format {
+        OutputFormat::Text => {
+            println!("chip {}", chip.name);
+            for (group, (count, bit_count)) in &groups {
+                println!("{} pins: {} ({} bits)", group, count, bit_count);
+            }
+            println!("parts: {}", chip.parts.len());
+            for (name, count) in &parts {
+                println!("  {}: {}", name, count);
+            }
+            println!("all parts: {}", expanded.parts.values().sum::<usize>());
+            for (name, count) in &expanded.parts {
+                println!("  {}: {}", name, count);
+            }
+            println!(
+                "builtin chips: {}",
+                expanded.primitives.values().sum::<usize>()
+            );
+            for (name, count) in &expanded.primitives {
+                println!("  {}: {}", name, count);
+            }
+            println!("nand gates: {}", expanded.nand_count);
+            println!("longest path: {} nand gates", expanded.depth);
+        }
+        OutputFormat::Json => {
+            let mut json = json!({
+                "chip": chip.name,
+                "parts": parts,
+                "builtins": expanded.primitives,
+                "all_parts": expanded.parts,
+                "nand_count": expanded.nand_count,
+                "depth": expanded.depth,
+            });
+            for (group, (count, bit_count)) in &groups {
+                json[format!("{}_pins", group)] =
+                    json!({"count": count, "bits": bit_count});
+            }
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+
+/// Runs `step` until `seconds` have passed, returning how many times it ran
+/// and how long that took
+fn measure(seconds: f64, mut step: impl FnMut()) -> (u64, f64) {
+    let start = std::time::Instant::now();
+    let mut count = 0;
+    loop {
+        step();
+        count += 1;
+        let elapsed = start.elapsed().as_secs_f64();
+        if elapsed >= seconds {
+            return (count, elapsed);
+        }
+    }
+}
+
+fn bench(format: OutputFormat, file: PathBuf, seconds: f64) -> Result<()> {
+    let mut chip = load_chip(&file)?;
+    let mut sim = NetlistSimulator::new(compile(&chip, &file)?);
+    let names: Vec<String> =
+        chip.pinlines.input.iter().map(|p| p.name.clone()).collect();
+    let clocked = chip.clocked;
+    let unit = if clocked { "cycles" } else { "evaluations" };
+
+    // New inputs every step, so that nothing can be skipped
+    let mut random = Random::new(1);
+    let tree = measure(seconds, || {
+        for pinline in &mut chip.pinlines.input {
+            pinline.set_value(random.next_u64());
+        }
+        if clocked {
+            chip.tick();
+            chip.tock();
+        } else {
+            chip.evaluate();
+        }
+    });
+    let mut random = Random::new(1);
+    let netlist = measure(seconds, || {
+        for name in &names {
+            sim.set_input(name, random.next_u64() as i64);
+        }
+        if clocked {
+            sim.tick();
+            sim.tock();
+        } else {
+            sim.evaluate();
+        }
+    });
+
+    let results = [("tree", tree), ("netlist", netlist)];
+    match format {
+        OutputFormat::Text => {
+            println!("chip {}", chip.name);
+            for (name, (count, elapsed)) in &results {
+                println!(
+                    "{}: {:.0} {}/s ({} in {:.2} s)",
+                    name,
+                    *count as f64 / elapsed,
+                    unit,
+                    count,
+                    elapsed
+                );
+            }
+        }
+        OutputFormat::Json => {
+            let mut json = json!({"chip": chip.name, "unit": unit});
+            for (name, (count, elapsed)) in &results {
+                json[*name] = json!({
+                    "count": count,
+                    "seconds": elapsed,
+                    "per_second": *count as f64 / elapsed,
+                });
+            }
+            println!("{}", json);
+        }
+    }
+    Ok(())
+}
+
+fn truth_table(
+    format: OutputFormat,
+    file: PathBuf,
+    csv: bool,
+    cmp: bool,
+) -> Result<()> {
+    let chip = load_chip(&file)?;
+    let table = chip
+        .truth_table()
+        .map_err(|e| Error::with_chain(e, ErrorKind::TruthTableError(file)))?;
+    match format {
+        OutputFormat::Json => println!("{}", json!(table)),
+        OutputFormat::Text if csv => print!("{}", table.to_csv()),
+        OutputFormat::Text if cmp => print!("{}", table.to_cmp()),
+        OutputFormat::Text => print!("{}", table.to_text()),
+    }
+    Ok(())
+}
+
+fn equiv(
+    format: OutputFormat,
+    file: PathBuf,
+    other: PathBuf,
+    cycles: usize,
+    tst: Option<PathBuf>,
+) -> Result<()> {
+    let chip = load_chip(&file)?;
+    let builtin = other.to_str().and_then(BuiltinChips::from_name);
+    let other_chip = match builtin {
+        Some(id) if !other.exists() => Chip::new_builtin(id),
+        _ => load_chip(&other)?,
+    };
+    let names = format!("{} and {}", chip.name, other_chip.name);
+    let load = tst.as_ref().map(|tst| script_path(&file, tst));
+    let compare_error =
+        |e| Error::with_chain(e, ErrorKind::EquivalenceError(file));
+    let differ = if chip.clocked || other_chip.clocked {
+        let trace = equivalence::check_sequential(&chip, &other_chip, cycles)
+            .map_err(compare_error)?;
+        if let (Some(trace), Some(tst)) = (&trace, &tst) {
+            std::fs::write(tst, trace.to_tst(&chip.name, load.as_deref()))
+                .chain_err(|| ErrorKind::FileWriteError(tst.clone()))?;
+        }
+        match format {
+            OutputFormat::Text => match &trace {
+                Some(trace) => {
+                    println!(
+                        "{} differ after {} cycle(s):",
+                        names,
+                        trace.steps.len()
+                    );
+                    print_trace(trace);
+                }
+                None => println!(
+                    "{} are equivalent for {} cycle(s) from reset",
+                    names, cycles
+                ),
+            },
+            OutputFormat::Json => println!(
+                "{}",
+                json!({"equivalent": trace.is_none(), "cycles": cycles, "trace": trace})
+            ),
+        }
+        trace.is_some()
+    } else {
+        let found =
+            equivalence::check(&chip, &other_chip).map_err(compare_error)?;
+        match format {
+            OutputFormat::Text => match &found {
+                Some(counterexample) => {
+                    println!("{} differ: {}", names, counterexample)
+                }
+                None => println!("{} are equivalent", names),
+            },
+            OutputFormat::Json => println!(
+                "{}",
+                json!({"equivalent": found.is_none(), "counterexample": found})
+            ),
+        }
+        found.is_some()
+    };
+    if differ {
+        return Err(ErrorKind::NotEquivalent.into());
+    }
+    Ok(())
+}
+
+/// How a script written to `tst` names `file`: scripts load files from
+/// their own directory, so just the name there and the full path otherwise
+fn script_path(file: &Path, tst: &Path) -> String {
+    let dir = |path: &Path| {
+        path.parent()
+            .map(|p| {
+                if p.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    p
+                }
+            })
+            .and_then(|p| p.canonicalize().ok())
+    };
+    match (dir(file), dir(tst), file.file_name()) {
+        (Some(a), Some(b), Some(name)) if a == b => {
+            name.to_string_lossy().into_owned()
+        }
+        _ => file
+            .canonicalize()
+            .unwrap_or_else(|_| file.to_path_buf())
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+/// One line per cycle with the inputs and both chips' outputs
+fn print_trace(trace: &equivalence::Trace) {
+    let pins = |pins: &[(String, usize)], values: &[u64]| {
+        pins.iter()
+            .zip(values.iter())
+            .map(|((name, width), value)| {
+                format!("{}={}", name, bits(*value, *width))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    for (time, step) in trace.steps.iter().enumerate() {
+        println!(
+            "{}: {} | {} | {}",
+            time,
+            pins(&trace.inputs, &step.inputs),
+            pins(&trace.outputs, &step.left),
+            pins(&trace.outputs, &step.right)
+        );
+    }
+}
+
+/// Names of chips that were loaded from .hdl files, the chip itself included
+fn custom_chips(chip: &Chip, names: &mut BTreeSet<String>) {
+    if chip.is_builtin() {
+        return;
+    }
+    names.insert(chip.name.clone());
+    for part in &chip.parts {
+        custom_chips(&part.chip, names);
+    }
+}
+
+fn export(
+    formats: ExportFormats,
+    out_dir: PathBuf,
+    file: PathBuf,
+) -> Result<()> {
+    if !(formats.hdl
+        || formats.verilog
+        || formats.vhdl
+        || formats.dot
+        || formats.svg
+        || formats.blif
+        || formats.aiger
+        || formats.aiger_ascii)
+    {
+        return Err(ErrorKind::NoExportFormat.into());
+    }
+    let chip = load_chip(&file)?;
+    let write = |name: String, contents: Vec<u8>| -> Result<()> {
+        let target = out_dir.join(name);
+        std::fs::write(&target, contents)
+            .chain_err(|| ErrorKind::FileWriteError(target.clone()))?;
+        println!("{}", target.display());
+        Ok(())
+    };
+    if formats.hdl {
+        let mut names = BTreeSet::new();
+        custom_chips(&chip, &mut names);
+        for name in names {
+            // A chip read from BLIF has no HDL of its own to format
+            if name == chip.name
+                && file.extension().is_some_and(|e| e == "blif")
+            {
+                write(format!("{}.hdl", name), chip.to_hdl().into_bytes())?;
+                continue;
+            }
+            let source = if name == chip.name {
+                file.clone()
+            } else {
+                file.with_file_name(format!("{}.hdl", name))
+            };
+            let contents = read_file(&source)?;
+            let formatted =
+                hack_hdsim_lib::format::format_chip(contents.as_str())
+                    .map_err(|e| {
+                        Error::with_chain(e, ErrorKind::ParseError(source))
+                    })?;
+            write(format!("{}.hdl", name), formatted.into_bytes())?;
+        }
+    }
+    if formats.verilog {
+        write(format!("{}.v", chip.name), chip.to_verilog().into_bytes())?;
+    }
+    if formats.vhdl {
+        write(format!("{}.vhd", chip.name), chip.to_vhdl().into_bytes())?;
+    }
+    if formats.dot {
+        write(
+            format!("{}.dot", chip.name),
+            chip.to_dot(formats.levels).into_bytes(),
+        )?;
+    }
+    if formats.svg {
+        write(format!("{}.svg", chip.name), chip.to_svg().into_bytes())?;
+    }
+    if formats.blif || formats.aiger || formats.aiger_ascii {
+        let netlist = compile(&chip, &file)?;
+        if formats.blif {
+            write(
+                format!("{}.blif", chip.name),
+                netlist.to_blif().into_bytes(),
+            )?;
+        }
+        if formats.aiger || formats.aiger_ascii {
+            let aig = Aig::from_netlist(&netlist)
+                .chain_err(|| ErrorKind::ExportError(file.clone()))?;
+            if formats.aiger {
+                write(format!("{}.aig", chip.name), aig.to_binary())?;
+            }
+            if formats.aiger_ascii {
+                write(
+                    format!("{}.aag", chip.name),
+                    aig.to_ascii().into_bytes(),
+                )?;
+            }
+        }
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    /// A directory of its own under the system temp dir, tests run in
+    /// parallel and would otherwise write over each other's files
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hack-hdsim-cli-{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// test-hdl's chips, scripts and compare files copied to `temp_dir`, for
+    /// commands that write next to them
+    fn copy_test_hdl(name: &str) -> PathBuf {
+        let dir = temp_dir(name);
+        for entry in std::fs::read_dir("../test-hdl").unwrap() {
+            let path = entry.unwrap().path();
+            let extension = path.extension().and_then(|e| e.to_str());
+            if let Some("hdl") | Some("tst") | Some("cmp") = extension {
+                std::fs::copy(&path, dir.join(path.file_name().unwrap()))
+                    .unwrap();
+            }
+        }
+        dir
+    }
+
     #[test]
     fn run_fails_no_file() {
         let opt_no_file = Opt {
-            file: std::path::PathBuf::from(r"./no_such_file"),
+            format: OutputFormat::Text,
+            cmd: Command::Tokens {
+                file: std::path::PathBuf::from(r"./no_such_file"),
+            },
         };
         let run_no_file = run(opt_no_file);
-        matches!(run_no_file, Err(_));
+        assert!(run_no_file.is_err());
         let err_no_file = run_no_file.unwrap_err();
-        matches!(err_no_file, Error(ErrorKind::FileReadError(_), _));
+        assert!(matches!(err_no_file, Error(ErrorKind::FileReadError(_), _)));
         if let ErrorKind::FileReadError(file) = err_no_file.kind() {
             assert_eq!(
                 file.as_path(),
@@ -54,4 +1147,251 @@ mod tests {
             );
         }
     }
+    #[test]
+    fn fmt_check_reports_unformatted() {
+        let opt = Opt {
+            format: OutputFormat::Text,
+            cmd: Command::Fmt {
+                check: true,
+                files: vec![std::path::PathBuf::from(r"../test-hdl/And.hdl")],
+            },
+        };
+        let err = run(opt).unwrap_err();
+        if let ErrorKind::NotFormatted(files) = err.kind() {
+            assert_eq!(
+                files,
+                &vec![std::path::PathBuf::from(r"../test-hdl/And.hdl")]
+            );
+        } else {
+            panic!("expected NotFormatted, got {:?}", err.kind());
+        }
+    }
+    #[test]
+    fn lint_counts_warnings() {
+        let opt = Opt {
+            format: OutputFormat::Text,
+            cmd: Command::Lint {
+                disable: vec![],
+                files: vec![std::path::PathBuf::from(r"../test-hdl/Bit.hdl")],
+            },
+        };
+        let err = run(opt).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::LintWarnings(1)));
+        let opt = Opt {
+            format: OutputFormat::Text,
+            cmd: Command::Lint {
+                disable: vec![LintRule::ShadowedBuiltin],
+                files: vec![std::path::PathBuf::from(r"../test-hdl/Bit.hdl")],
+            },
+        };
+        assert!(run(opt).is_ok());
+    }
+    #[test]
+    fn test_runs_script() {
+        let dir = copy_test_hdl("test-runs-script");
+        let opt = Opt {
+            format: OutputFormat::Json,
+            cmd: Command::Test {
+                four_valued: false,
+                file: dir.join("Bit.tst"),
+            },
+        };
+        assert!(run(opt).is_ok());
+        let output = std::fs::read_to_string(dir.join("Bit.out")).unwrap();
+        let expected = std::fs::read_to_string(dir.join("Bit.cmp")).unwrap();
+        assert_eq!(output, expected);
+    }
+    #[test]
+    fn exit_codes() {
+        let opt = Opt {
+            format: OutputFormat::Text,
+            cmd: Command::Eval {
+                file: std::path::PathBuf::from(r"../test-hdl/Mux.hdl"),
+                assignments: vec!["a=1".to_string(), "out=1".to_string()],
+            },
+        };
+        let err = run(opt).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::BadAssignment(_)));
+        assert_eq!(exit_code(&err), 2);
+        assert_eq!(exit_code(&ErrorKind::TestFailed(1).into()), 1);
+        assert_eq!(exit_code(&ErrorKind::LintWarnings(1).into()), 1);
+    }
+    #[test]
+    fn truth_table_needs_combinational_chip() {
+        let opt = Opt {
+            format: OutputFormat::Text,
+            cmd: Command::TruthTable {
+                csv: false,
+                cmp: false,
+                file: std::path::PathBuf::from(r"../test-hdl/Bit.hdl"),
+            },
+        };
+        let err = run(opt).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::TruthTableError(_)));
+    }
+    #[test]
+    fn bench_runs() {
+        let opt = Opt {
+            format: OutputFormat::Json,
+            cmd: Command::Bench {
+                seconds: 0.01,
+                file: std::path::PathBuf::from(r"../test-hdl/Bit.hdl"),
+            },
+        };
+        assert!(run(opt).is_ok());
+    }
+    #[test]
+    fn run_loads_program() {
+        let run_on = |chip: &str, program: &str| {
+            run(Opt {
+                format: OutputFormat::Json,
+                cmd: Command::Run {
+                    cycles: 20,
+                    key: 65,
+                    ram: 19,
+                    file: PathBuf::from(format!("../test-hdl/{}", chip)),
+                    program: PathBuf::from(format!("../test-hdl/{}", program)),
+                },
+            })
+        };
+        assert!(run_on("Computer.hdl", "Store.hack").is_ok());
+        let err = run_on("CPU.hdl", "Store.hack").unwrap_err();
+        assert!(
+            matches!(err.kind(), ErrorKind::NoMemory(_, part) if part == "ROM32K")
+        );
+        let err = run_on("Computer.hdl", "Bit.hdl").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::HackFileError(_)));
+
+        let bench = Opt {
+            format: OutputFormat::Text,
+            cmd: Command::Bench {
+                seconds: 0.01,
+                file: PathBuf::from(r"../test-hdl/Memory.hdl"),
+            },
+        };
+        let err = run(bench).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::MemoryParts(_, _)));
+    }
+    #[test]
+    fn equiv_compares_with_builtin() {
+        let equiv = |other: &str| {
+            run(Opt {
+                format: OutputFormat::Json,
+                cmd: Command::Equiv {
+                    cycles: 8,
+                    tst: None,
+                    file: std::path::PathBuf::from(r"../test-hdl/Mux.hdl"),
+                    other: std::path::PathBuf::from(other),
+                },
+            })
+        };
+        assert!(equiv("Mux").is_ok());
+        let err = equiv("../test-hdl/And.hdl").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EquivalenceError(_)));
+        assert_eq!(exit_code(&err), 2);
+        let err = equiv("../test-hdl/Xor.hdl").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EquivalenceError(_)));
+    }
+    #[test]
+    fn equiv_clocked_chips() {
+        let equiv = |other: &str| {
+            run(Opt {
+                format: OutputFormat::Json,
+                cmd: Command::Equiv {
+                    cycles: 4,
+                    tst: None,
+                    file: std::path::PathBuf::from(r"../test-hdl/Bit.hdl"),
+                    other: std::path::PathBuf::from(other),
+                },
+            })
+        };
+        assert!(equiv("Bit").is_ok());
+        let err = equiv("DFF").unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::EquivalenceError(_)));
+    }
+    #[test]
+    fn equiv_writes_runnable_tst() {
+        let dir = copy_test_hdl("equiv-writes-runnable-tst");
+        let tst = dir.join("BitVsLatch.tst");
+        std::fs::write(
+            dir.join("Latch.hdl"),
+            "CHIP Latch { IN in, load; OUT out; PARTS: DFF(in=in, out=out); }",
+        )
+        .unwrap();
+        let err = run(Opt {
+            format: OutputFormat::Json,
+            cmd: Command::Equiv {
+                cycles: 4,
+                tst: Some(tst.clone()),
+                file: dir.join("Bit.hdl"),
+                other: dir.join("Latch.hdl"),
+            },
+        })
+        .unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::NotEquivalent));
+        let script = std::fs::read_to_string(&tst).unwrap();
+        assert!(script.contains("\nload Bit.hdl,\n"));
+        assert!(script.contains("\noutput-file Bit.out,\n"));
+        let opt = Opt {
+            format: OutputFormat::Json,
+            cmd: Command::Test {
+                four_valued: false,
+                file: tst,
+            },
+        };
+        assert!(run(opt).is_ok());
+        assert!(dir.join("Bit.out").exists());
+    }
+    #[test]
+    fn export_netlists() {
+        let out_dir = temp_dir("export-netlists");
+        let export = |formats: ExportFormats, file: PathBuf| {
+            run(Opt {
+                format: OutputFormat::Text,
+                cmd: Command::Export {
+                    formats,
+                    out_dir: out_dir.clone(),
+                    file,
+                },
+            })
+        };
+        let bit = PathBuf::from(r"../test-hdl/Bit.hdl");
+        let err = export(ExportFormats::default(), bit.clone()).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::NoExportFormat));
+        export(
+            ExportFormats {
+                verilog: true,
+                vhdl: true,
+                dot: true,
+                svg: true,
+                blif: true,
+                aiger: true,
+                ..ExportFormats::default()
+            },
+            bit,
+        )
+        .unwrap();
+        let verilog = std::fs::read_to_string(out_dir.join("Bit.v")).unwrap();
+        assert!(verilog.contains("module DFF("));
+        let vhdl = std::fs::read_to_string(out_dir.join("Bit.vhd")).unwrap();
+        assert!(vhdl.contains("entity DFF is"));
+        let dot = std::fs::read_to_string(out_dir.join("Bit.dot")).unwrap();
+        assert!(dot.starts_with("digraph \"Bit\" {"));
+        let svg = std::fs::read_to_string(out_dir.join("Bit.svg")).unwrap();
+        assert!(svg.contains("<rect class=\"part clocked\""));
+        let blif = std::fs::read_to_string(out_dir.join("Bit.blif")).unwrap();
+        assert!(blif.contains(".latch "));
+        let aig = std::fs::read(out_dir.join("Bit.aig")).unwrap();
+        assert!(aig.starts_with(b"aig "));
+        assert!(aig.ends_with(b"i0 in\ni1 load\no0 out\nc\nBit\n"));
+        // A chip read from BLIF gets HDL written from its parts
+        let hdl = ExportFormats {
+            hdl: true,
+            ..ExportFormats::default()
+        };
+        export(hdl, out_dir.join("Bit.blif")).unwrap();
+        let hdl = std::fs::read_to_string(out_dir.join("Bit.hdl")).unwrap();
+        assert!(hdl.starts_with("CHIP Bit {\n    IN  in, load;\n"));
+        assert!(hdl.contains("    DFF(in="));
+    }
 }
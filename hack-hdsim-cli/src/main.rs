@@ -1,21 +1,123 @@
+use structopt::clap;
 use structopt::StructOpt;
 
-use hack_hdsim_cli::{run, ErrorKind, Opt};
+use hack_hdsim_cli::{
+    error_diagnostic, exit_code, run, Error, ErrorKind, Opt, OutputFormat,
+};
 
 fn main() {
-    if let Err(e) = run(Opt::from_args()) {
-        match e.kind() {
-            ErrorKind::FileReadError(filepath) => {
-                eprintln!("Could not read '{}'", filepath.as_path().display())
+    let opt = match Opt::from_iter_safe(std::env::args_os()) {
+        Ok(opt) => opt,
+        Err(e) => match e.kind {
+            clap::ErrorKind::HelpDisplayed
+            | clap::ErrorKind::VersionDisplayed => {
+                println!("{}", e.message);
+                std::process::exit(0);
+            }
+            _ => {
+                eprintln!("{}", e.message);
+                std::process::exit(2);
+            }
+        },
+    };
+    let format = opt.format;
+    if let Err(e) = run(opt) {
+        let message = message(&e);
+        let reported = matches!(
+            e.kind(),
+            ErrorKind::LintWarnings(_)
+                | ErrorKind::InvalidChips(_)
+                | ErrorKind::TestFailed(_)
+                | ErrorKind::NotEquivalent
+        );
+        match format {
+            // The results printed already say what went wrong
+            OutputFormat::Json if reported => {}
+            OutputFormat::Json => {
+                let causes: Vec<String> =
+                    e.iter().skip(1).map(|e| e.to_string()).collect();
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "error": message,
+                        "causes": causes,
+                        "diagnostic": error_diagnostic(&e),
+                    })
+                );
+            }
+            OutputFormat::Text => {
+                eprintln!("{}", message);
+                for e in e.iter().skip(1) {
+                    eprintln!("Caused by: {}", e);
+                }
             }
-            _ => eprintln!("Application error: {}", e),
-        }
-        for e in e.iter().skip(1) {
-            eprintln!("Caused by: {}", e);
         }
         if let Some(backtrace) = e.backtrace() {
             eprintln!("backtrace:\n{:?}", backtrace);
         }
-        std::process::exit(1);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+fn message(e: &Error) -> String {
+    match e.kind() {
+        ErrorKind::FileReadError(filepath) => {
+            format!("Could not read '{}'", filepath.as_path().display())
+        }
+        ErrorKind::FileWriteError(filepath) => {
+            format!("Could not write '{}'", filepath.as_path().display())
+        }
+        ErrorKind::ParseError(filepath) => {
+            format!("Could not parse '{}'", filepath.as_path().display())
+        }
+        ErrorKind::NotFormatted(filepaths) => filepaths
+            .iter()
+            .map(|f| format!("'{}' is not formatted", f.as_path().display()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ErrorKind::LintWarnings(count) => {
+            format!("{} warning(s) emitted", count)
+        }
+        ErrorKind::InvalidChips(count) => {
+            format!("{} chip(s) could not be loaded", count)
+        }
+        ErrorKind::ScriptError(filepath) => {
+            format!("Could not run '{}'", filepath.as_path().display())
+        }
+        ErrorKind::TestFailed(count) => {
+            format!("{} line(s) differ from the compare file", count)
+        }
+        ErrorKind::BadAssignment(assignment) => format!(
+            "Bad assignment '{}', expected an input pin and value like a=1",
+            assignment
+        ),
+        ErrorKind::TruthTableError(filepath) => format!(
+            "Could not list the truth table of '{}'",
+            filepath.as_path().display()
+        ),
+        ErrorKind::EquivalenceError(filepath) => {
+            format!("Could not compare '{}'", filepath.as_path().display())
+        }
+        ErrorKind::ExportError(filepath) => {
+            format!("Could not export '{}'", filepath.as_path().display())
+        }
+        ErrorKind::NotEquivalent => "The chips are not equivalent".to_string(),
+        ErrorKind::HackFileError(filepath) => format!(
+            "Could not read the program in '{}'",
+            filepath.as_path().display()
+        ),
+        ErrorKind::NoMemory(filepath, part) => {
+            format!("'{}' has no {}", filepath.as_path().display(), part)
+        }
+        ErrorKind::MemoryParts(filepath, part) => format!(
+            "'{}' has a {}, which can't be flattened to gates",
+            filepath.as_path().display(),
+            part
+        ),
+        ErrorKind::TerminalError => "Could not use the terminal".to_string(),
+        ErrorKind::NoExportFormat => {
+            "Nothing to export, give a format like --hdl or --vhdl".to_string()
+        }
+        _ => format!("Application error: {}", e),
     }
 }
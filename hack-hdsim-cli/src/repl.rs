@@ -0,0 +1,326 @@
+use hack_hdsim_lib::chip::{Chip, Pinline};
+use hack_hdsim_lib::script::{self, PinSelect};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+const COMMANDS: &[&str] = &[
+    "set", "eval", "tick", "tock", "step", "show", "probe", "reset", "help",
+    "quit",
+];
+
+const HELP: &str = "\
+set PIN VALUE   set an input, VALUE like 1, -1, %B0101 or %X00FF
+eval            propagate inputs to outputs
+tick, tock      first and second half of a clock cycle
+step [N]        N full clock cycles, 1 by default
+show out|in|internal|PIN
+probe PATH      pin of a part, like Mux/And:1/out
+reset           back to the chip as it was loaded
+quit";
+
+/// A loaded chip and the clock, independent of the terminal
+pub struct Session {
+    chip: Chip,
+    initial: Chip,
+    time: usize,
+    /// Between a tick and its tock
+    ticked: bool,
+}
+
+/// Completes commands first and then pin names and part paths
+struct ReplHelper {
+    pins: Vec<String>,
+    chip: Chip,
+}
+
+// ============================================================================
+
+pub fn run(chip: Chip) -> rustyline::Result<()> {
+    let mut editor: Editor<ReplHelper, rustyline::history::DefaultHistory> =
+        Editor::new()?;
+    editor.set_helper(Some(ReplHelper::new(&chip)));
+    let mut session = Session::new(chip);
+    println!("{} loaded, type help for commands", session.chip.name);
+    loop {
+        let line = match editor.readline(session.prompt().as_str()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        let _ = editor.add_history_entry(line.as_str());
+        match line.trim() {
+            "quit" | "exit" => break,
+            _ => match session.execute(line.as_str()) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{}", output),
+                Err(e) => eprintln!("{}", e),
+            },
+        }
+    }
+    Ok(())
+}
+
+impl Session {
+    pub fn new(chip: Chip) -> Self {
+        Self {
+            initial: chip.clone(),
+            chip,
+            time: 0,
+            ticked: false,
+        }
+    }
+    pub fn prompt(&self) -> String {
+        let plus = if self.ticked { "+" } else { "" };
+        format!("{} {}{}> ", self.chip.name, self.time, plus)
+    }
+    /// Runs one line, returning what to print
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["set", pin, value] => {
+                let pin = PinSelect::parse(pin)
+                    .ok_or_else(|| format!("bad pin {}", pin))?;
+                let value = script::parse_value(value)
+                    .ok_or_else(|| format!("bad value {}", value))?;
+                pin.write(&mut self.chip, value)
+                    .ok_or_else(|| format!("no input pin {}", pin))?;
+                Ok(String::new())
+            }
+            ["eval"] => {
+                self.chip.evaluate();
+                Ok(show(&self.chip.pinlines.output))
+            }
+            ["tick"] => {
+                self.tick();
+                Ok(show(&self.chip.pinlines.output))
+            }
+            ["tock"] => {
+                self.tock();
+                Ok(show(&self.chip.pinlines.output))
+            }
+            ["step"] => self.step(1),
+            ["step", n] => {
+                self.step(n.parse().map_err(|_| format!("bad count {}", n))?)
+            }
+            ["show", "out"] => Ok(show(&self.chip.pinlines.output)),
+            ["show", "in"] => Ok(show(&self.chip.pinlines.input)),
+            ["show", "internal"] => Ok(show(&self.chip.pinlines.internal)),
+            ["show", pin] => self
+                .chip
+                .pinlines
+                .get_pinline(pin)
+                .map(|p| show(std::slice::from_ref(p)))
+                .ok_or_else(|| format!("no pin {}", pin)),
+            ["probe", path] => self
+                .chip
+                .probe(path)
+                .map(|p| show(std::slice::from_ref(p)))
+                .ok_or_else(|| format!("nothing at {}", path)),
+            ["reset"] => {
+                self.chip = self.initial.clone();
+                self.time = 0;
+                self.ticked = false;
+                Ok(String::new())
+            }
+            ["help"] => Ok(HELP.to_string()),
+            _ => Err(format!("can't run '{}', type help for commands", line)),
+        }
+    }
+    fn tick(&mut self) {
+        self.chip.tick();
+        self.ticked = true;
+    }
+    fn tock(&mut self) {
+        self.chip.tock();
+        self.time += 1;
+        self.ticked = false;
+    }
+    fn step(&mut self, n: usize) -> Result<String, String> {
+        for _ in 0..n {
+            if !self.ticked {
+                self.tick();
+            }
+            self.tock();
+        }
+        Ok(show(&self.chip.pinlines.output))
+    }
+}
+
+/// `name = bits`, with the decimal value for buses
+fn show(pinlines: &[Pinline]) -> String {
+    pinlines
+        .iter()
+        .map(|p| {
+            let bits: String = p
+                .pins
+                .iter()
+                .rev()
+                .map(|b| if *b { '1' } else { '0' })
+                .collect();
+            if p.pins.len() == 1 {
+                format!("{} = {}", p.name, bits)
+            } else {
+                format!("{} = {} ({})", p.name, bits, p.get_value())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl ReplHelper {
+    fn new(chip: &Chip) -> Self {
+        let pins = chip
+            .pinlines
+            .input
+            .iter()
+            .chain(chip.pinlines.internal.iter())
+            .chain(chip.pinlines.output.iter())
+            .map(|p| p.name.clone())
+            .collect();
+        Self {
+            pins,
+            chip: chip.clone(),
+        }
+    }
+    /// Candidates for the word being typed, given the words before it
+    fn candidates(&self, before: &[&str], word: &str) -> Vec<String> {
+        let options: Vec<String> = match before {
+            [] => COMMANDS.iter().map(|c| c.to_string()).collect(),
+            ["set"] => self
+                .chip
+                .pinlines
+                .input
+                .iter()
+                .map(|p| p.name.clone())
+                .collect(),
+            ["show"] => ["out", "in", "internal"]
+                .iter()
+                .map(|s| s.to_string())
+                .chain(self.pins.iter().cloned())
+                .collect(),
+            ["probe"] => self.paths(word),
+            _ => Vec::new(),
+        };
+        options
+            .into_iter()
+            .filter(|o| o.starts_with(word))
+            .collect()
+    }
+    /// Parts and pins one level below what `word` already names
+    fn paths(&self, word: &str) -> Vec<String> {
+        let (prefix, chip) = match word.rfind('/') {
+            Some(slash) => {
+                let mut chip = &self.chip;
+                for segment in word[..slash].split('/') {
+                    match chip.get_part(segment) {
+                        Some(part) => chip = &part.chip,
+                        None => return Vec::new(),
+                    }
+                }
+                (&word[..=slash], chip)
+            }
+            None => ("", &self.chip),
+        };
+        let pins = chip
+            .pinlines
+            .input
+            .iter()
+            .chain(chip.pinlines.internal.iter())
+            .chain(chip.pinlines.output.iter())
+            .map(|p| format!("{}{}", prefix, p.name));
+        let parts = chip
+            .part_selectors()
+            .into_iter()
+            .map(|s| format!("{}{}/", prefix, s));
+        pins.chain(parts).collect()
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let before: Vec<&str> = line[..start].split_whitespace().collect();
+        let candidates = self
+            .candidates(&before, &line[start..])
+            .into_iter()
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hack_hdsim_lib::loader::ChipLoader;
+
+    fn load(name: &str) -> Chip {
+        let path =
+            std::path::PathBuf::from(format!("../test-hdl/{}.hdl", name));
+        ChipLoader::for_file(&path).load_file(&path).unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let mut session = Session::new(load("Bit"));
+        assert_eq!(session.execute("set in 1"), Ok(String::new()));
+        session.execute("set load 1").unwrap();
+        assert_eq!(session.execute("tick"), Ok("out = 0".to_string()));
+        assert_eq!(session.prompt(), "Bit 0+> ");
+        assert_eq!(session.execute("tock"), Ok("out = 1".to_string()));
+        assert_eq!(session.execute("probe DFF/in"), Ok("in = 1".to_string()));
+        session.execute("set load 0").unwrap();
+        session.execute("set in 0").unwrap();
+        assert_eq!(session.execute("step 3"), Ok("out = 1".to_string()));
+        assert_eq!(session.prompt(), "Bit 4> ");
+        assert_eq!(
+            session.execute("show in"),
+            Ok("in = 0\nload = 0".to_string())
+        );
+        session.execute("reset").unwrap();
+        assert_eq!(session.execute("show out"), Ok("out = 0".to_string()));
+        assert!(session.execute("set out 1").is_err());
+        assert!(session.execute("frobnicate").is_err());
+    }
+    #[test]
+    fn completion() {
+        let helper = ReplHelper::new(&load("Mux"));
+        assert_eq!(helper.candidates(&[], "s"), vec!["set", "step", "show"]);
+        assert_eq!(helper.candidates(&["set"], ""), vec!["a", "b", "sel"]);
+        assert_eq!(
+            helper.candidates(&["probe"], "And"),
+            vec!["And:0/", "And:1/"]
+        );
+        assert_eq!(
+            helper.candidates(&["probe"], "Xor/Or/o"),
+            vec!["Xor/Or/out"]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use hack_hdsim_lib::chip::Chip;
+use hack_hdsim_lib::loader::ChipLoader;
+use hack_hdsim_lib::netlist::{Netlist, NetlistSimulator};
+use hack_hdsim_lib::random::Random;
+use std::path::{Path, PathBuf};
+
+fn test_hdl() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../test-hdl")
+}
+
+fn load(dir: &Path, name: &str) -> Chip {
+    let path = dir.join(format!("{}.hdl", name));
+    ChipLoader::for_file(&path).load_file(&path).unwrap()
+}
+
+/// The memory chips with builtin Bit and Mux underneath. With test-hdl's own
+/// Bit and Mux a RAM4K takes gigabytes.
+fn memory_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join("hack-hdsim-bench-memory");
+    std::fs::create_dir_all(&dir).unwrap();
+    let chips = [
+        "And",
+        "Or",
+        "Not16",
+        "And16",
+        "Mux16",
+        "Register",
+        "DMux",
+        "DMux4Way",
+        "DMux8Way",
+        "Mux4Way16",
+        "Mux8Way16",
+        "RAM8",
+        "RAM64",
+        "RAM512",
+        "RAM4K",
+    ];
+    for chip in chips.iter() {
+        let file = format!("{}.hdl", chip);
+        std::fs::copy(test_hdl().join(&file), dir.join(&file)).unwrap();
+    }
+    dir
+}
+
+/// New inputs every iteration so that no part can be skipped
+fn randomise(chip: &mut Chip, random: &mut Random) {
+    for pinline in &mut chip.pinlines.input {
+        pinline.set_value(random.next_u64());
+    }
+}
+
+fn evaluate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluate");
+    group.throughput(Throughput::Elements(1));
+    for name in &["And", "Xor", "Mux", "Add16", "ALU"] {
+        let mut chip = load(&test_hdl(), name);
+        let mut random = Random::new(1);
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                randomise(&mut chip, &mut random);
+                chip.evaluate();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn tick(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick");
+    group.throughput(Throughput::Elements(1));
+    let chips = vec![
+        load(&test_hdl(), "Bit"),
+        load(&test_hdl(), "Register"),
+        load(&test_hdl(), "PC"),
+        load(&test_hdl(), "CPU"),
+        load(&memory_dir(), "RAM4K"),
+    ];
+    for mut chip in chips {
+        let mut random = Random::new(1);
+        group.bench_function(chip.name.clone(), |b| {
+            b.iter(|| {
+                randomise(&mut chip, &mut random);
+                chip.tick();
+                chip.tock();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn netlist(c: &mut Criterion) {
+    let mut group = c.benchmark_group("netlist tick");
+    group.throughput(Throughput::Elements(1));
+    for name in &["ALU", "CPU"] {
+        let chip = load(&test_hdl(), name);
+        let mut sim = NetlistSimulator::new(Netlist::compile(&chip));
+        let names: Vec<String> =
+            chip.pinlines.input.iter().map(|p| p.name.clone()).collect();
+        let mut random = Random::new(1);
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for name in &names {
+                    sim.set_input(name, random.next_u64() as i64);
+                }
+                sim.tick();
+                sim.tock();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, evaluate, tick, netlist);
+criterion_main!(benches);
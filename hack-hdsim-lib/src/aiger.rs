@@ -0,0 +1,562 @@
+use std::collections::HashMap;
+
+use crate::netlist::{
+    bit_names, group_bits, topological_order, Dff, Gate, Netlist, FALSE_NET,
+    TRUE_NET,
+};
+
+/// An and-inverter graph as AIGER has it. Literal `2v` is variable `v` and
+/// `2v + 1` its negation, 0 is false and 1 true. Inputs are variables 1 up,
+/// then latches, then and gates, each gate numbered above its operands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aig {
+    pub name: String,
+    /// Input bits, named like `a` or `b[3]`
+    pub inputs: Vec<String>,
+    /// Literal each latch reads on the clock, all of them starting at 0
+    pub latches: Vec<u32>,
+    pub outputs: Vec<(String, u32)>,
+    /// Operands of each and gate, larger first
+    pub ands: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AigerError {
+    /// A gate reads its own output without a DFF in between
+    CombinationalLoop,
+    Invalid(String),
+}
+
+/// Nets of a netlist being built from an `Aig`, with a Not gate for every
+/// negated literal used
+struct Nets {
+    count: usize,
+    gates: Vec<Gate>,
+    negated: HashMap<u32, usize>,
+}
+
+// ============================================================================
+
+impl Aig {
+    /// Nand and Not become and gates and negations and Mux three and gates.
+    /// DFFs become latches. Nets nothing drives read as false.
+    pub fn from_netlist(netlist: &Netlist) -> Result<Self, AigerError> {
+        let mut driven = vec![false; netlist.net_count];
+        for gate in &netlist.gates {
+            driven[gate.output()] = true;
+        }
+        let mut lits: Vec<Option<u32>> = vec![None; netlist.net_count];
+        lits[FALSE_NET] = Some(0);
+        lits[TRUE_NET] = Some(1);
+        let lit = |lits: &[Option<u32>], net: usize| match lits[net] {
+            Some(lit) => Ok(lit),
+            None if driven[net] => Err(AigerError::CombinationalLoop),
+            None => Ok(0),
+        };
+        let mut aig = Aig {
+            name: netlist.name.clone(),
+            inputs: Vec::new(),
+            latches: Vec::new(),
+            outputs: Vec::new(),
+            ands: Vec::new(),
+        };
+        for pinline in &netlist.input {
+            for (net, name) in pinline.nets.iter().zip(bit_names(pinline)) {
+                aig.inputs.push(name);
+                lits[*net] = Some(2 * aig.variables());
+            }
+        }
+        // Latch variables come before any gate's, what they read comes later
+        for dff in &netlist.dffs {
+            aig.latches.push(0);
+            lits[dff.out] = Some(2 * aig.variables());
+        }
+        for gate in &netlist.gates {
+            let out = match *gate {
+                Gate::Nand { a, b, .. } => {
+                    aig.and(lit(&lits, a)?, lit(&lits, b)?) ^ 1
+                }
+                Gate::Not { input, .. } => lit(&lits, input)? ^ 1,
+                Gate::Mux { a, b, sel, .. } => {
+                    let sel = lit(&lits, sel)?;
+                    let high = aig.and(sel, lit(&lits, b)?);
+                    let low = aig.and(sel ^ 1, lit(&lits, a)?);
+                    aig.and(high ^ 1, low ^ 1) ^ 1
+                }
+            };
+            lits[gate.output()] = Some(out);
+        }
+        for (k, dff) in netlist.dffs.iter().enumerate() {
+            aig.latches[k] = lit(&lits, dff.input)?;
+        }
+        for pinline in &netlist.output {
+            for (net, name) in pinline.nets.iter().zip(bit_names(pinline)) {
+                aig.outputs.push((name, lit(&lits, *net)?));
+            }
+        }
+        Ok(aig)
+    }
+    /// The `aag` format
+    pub fn to_ascii(&self) -> String {
+        let mut text = self.header("aag");
+        let (inputs, latches) = (self.inputs.len(), self.latches.len());
+        for i in 0..inputs {
+            text.push_str(&format!("{}\n", 2 * (i + 1)));
+        }
+        for (k, next) in self.latches.iter().enumerate() {
+            text.push_str(&format!("{} {}\n", 2 * (inputs + k + 1), next));
+        }
+        for (_, lit) in &self.outputs {
+            text.push_str(&format!("{}\n", lit));
+        }
+        for (k, (left, right)) in self.ands.iter().enumerate() {
+            let lhs = 2 * (inputs + latches + k + 1);
+            text.push_str(&format!("{} {} {}\n", lhs, left, right));
+        }
+        text + &self.symbols()
+    }
+    /// The `aig` format, with and gates as differences in variable-length
+    /// bytes
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut text = self.header("aig");
+        for next in &self.latches {
+            text.push_str(&format!("{}\n", next));
+        }
+        for (_, lit) in &self.outputs {
+            text.push_str(&format!("{}\n", lit));
+        }
+        let mut bytes = text.into_bytes();
+        let first = self.inputs.len() + self.latches.len() + 1;
+        for (k, (left, right)) in self.ands.iter().enumerate() {
+            let lhs = 2 * (first + k) as u32;
+            encode(&mut bytes, lhs - left);
+            encode(&mut bytes, left - right);
+        }
+        bytes.extend(self.symbols().into_bytes());
+        bytes
+    }
+    /// Either format. Variables are numbered afresh, so ASCII files can have
+    /// them in any order.
+    pub fn parse(bytes: &[u8]) -> Result<Self, AigerError> {
+        let mut reader = Reader { bytes, position: 0 };
+        let header = reader.line()?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        let binary = match fields.first() {
+            Some(&"aag") => false,
+            Some(&"aig") => true,
+            _ => return Err(invalid("expected an aag or aig header")),
+        };
+        let numbers = fields[1..]
+            .iter()
+            .map(|f| f.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("bad number in the header"))?;
+        if numbers.len() < 5 || numbers[5..].iter().any(|n| *n != 0) {
+            return Err(invalid("expected M I L O A and only outputs"));
+        }
+        let (inputs, latches, outputs, ands) = (
+            numbers[1] as usize,
+            numbers[2] as usize,
+            numbers[3] as usize,
+            numbers[4] as usize,
+        );
+
+        let mut input_vars = Vec::new();
+        for i in 0..inputs {
+            input_vars.push(match binary {
+                true => i as u32 + 1,
+                false => reader.numbers(1)?[0] / 2,
+            });
+        }
+        let mut latch_defs = Vec::new();
+        for k in 0..latches {
+            let (var, rest) = match binary {
+                true => ((inputs + k) as u32 + 1, reader.numbers(1)?),
+                false => {
+                    let numbers = reader.numbers(2)?;
+                    (numbers[0] / 2, numbers[1..].to_vec())
+                }
+            };
+            match rest.get(1) {
+                None | Some(0) => {}
+                Some(reset) if *reset == 2 * var => {}
+                Some(_) => {
+                    return Err(invalid("only latches starting at 0 work"))
+                }
+            }
+            latch_defs.push((var, rest[0]));
+        }
+        let mut output_lits = Vec::new();
+        for _ in 0..outputs {
+            output_lits.push(reader.numbers(1)?[0]);
+        }
+        let mut and_defs = HashMap::new();
+        let mut and_order = Vec::new();
+        for k in 0..ands {
+            let (lhs, left, right) = if binary {
+                let lhs = 2 * (inputs + latches + k + 1) as u32;
+                let left = lhs
+                    .checked_sub(reader.decode()?)
+                    .ok_or_else(|| invalid("and gate below its operands"))?;
+                let right = left
+                    .checked_sub(reader.decode()?)
+                    .ok_or_else(|| invalid("and gate below its operands"))?;
+                (lhs, left, right)
+            } else {
+                let numbers = reader.numbers(3)?;
+                (numbers[0], numbers[1], numbers[2])
+            };
+            and_defs.insert(lhs / 2, (left, right));
+            and_order.push(lhs / 2);
+        }
+
+        let mut names: HashMap<(char, usize), String> = HashMap::new();
+        let mut name = "Aig".to_string();
+        while let Ok(line) = reader.line() {
+            if line == "c" {
+                if let Ok(comment) = reader.line() {
+                    name = comment;
+                }
+                break;
+            }
+            let kind = line.chars().next().unwrap_or(' ');
+            let mut words = line[kind.len_utf8()..].splitn(2, ' ');
+            if let (Ok(index), Some(symbol)) =
+                (words.next().unwrap_or("").parse(), words.next())
+            {
+                names.insert((kind, index), symbol.to_string());
+            }
+        }
+        let symbol = |kind: char, index: usize| {
+            names
+                .get(&(kind, index))
+                .cloned()
+                .unwrap_or_else(|| format!("{}{}", kind, index))
+        };
+
+        let mut aig = Aig {
+            name,
+            inputs: (0..inputs).map(|i| symbol('i', i)).collect(),
+            latches: vec![0; latches],
+            outputs: Vec::new(),
+            ands: Vec::new(),
+        };
+        let mut map: HashMap<u32, u32> = HashMap::new();
+        map.insert(0, 0);
+        for (i, var) in input_vars.iter().enumerate() {
+            map.insert(*var, 2 * (i as u32 + 1));
+        }
+        for (k, (var, _)) in latch_defs.iter().enumerate() {
+            map.insert(*var, 2 * (inputs + k + 1) as u32);
+        }
+        for var in and_order {
+            aig.define(var, &and_defs, &mut map)?;
+        }
+        let translate = |map: &HashMap<u32, u32>, lit: u32| {
+            map.get(&(lit / 2))
+                .map(|mapped| mapped ^ (lit & 1))
+                .ok_or_else(|| {
+                    invalid(&format!("literal {} is undefined", lit))
+                })
+        };
+        for (k, (_, next)) in latch_defs.iter().enumerate() {
+            aig.latches[k] = translate(&map, *next)?;
+        }
+        for (k, lit) in output_lits.iter().enumerate() {
+            aig.outputs.push((symbol('o', k), translate(&map, *lit)?));
+        }
+        Ok(aig)
+    }
+    /// Nand and Not gates, two per and gate, and a DFF per latch. Bits named
+    /// like `b[3]` go back together into buses.
+    pub fn to_netlist(&self) -> Netlist {
+        let mut nets = Nets {
+            count: self.variables() as usize + 2,
+            gates: Vec::new(),
+            negated: HashMap::new(),
+        };
+        let first = self.inputs.len() + self.latches.len() + 1;
+        for (k, (left, right)) in self.ands.iter().enumerate() {
+            let (a, b) = (nets.net(*left), nets.net(*right));
+            let nand = nets.new_net();
+            nets.gates.push(Gate::Nand { a, b, out: nand });
+            nets.gates.push(Gate::Not {
+                input: nand,
+                out: first + k + 1,
+            });
+        }
+        let dffs = self
+            .latches
+            .iter()
+            .enumerate()
+            .map(|(k, next)| Dff {
+                input: nets.net(*next),
+                out: self.inputs.len() + k + 2,
+            })
+            .collect();
+        let input = group_bits(
+            self.inputs
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.clone(), i + 2))
+                .collect(),
+            &mut nets.count,
+        );
+        let bits = self
+            .outputs
+            .iter()
+            .map(|(name, lit)| (name.clone(), nets.net(*lit)))
+            .collect();
+        let output = group_bits(bits, &mut nets.count);
+        Netlist {
+            name: self.name.clone(),
+            input,
+            internal: Vec::new(),
+            output,
+            gates: topological_order(nets.gates, nets.count),
+            dffs,
+            net_count: nets.count,
+        }
+    }
+    fn variables(&self) -> u32 {
+        (self.inputs.len() + self.latches.len() + self.ands.len()) as u32
+    }
+    /// Literal for `a` and `b`, a new gate unless it simplifies
+    fn and(&mut self, a: u32, b: u32) -> u32 {
+        match (a, b) {
+            (0, _) | (_, 0) => 0,
+            (1, x) | (x, 1) => x,
+            _ if a == b => a,
+            _ if a == b ^ 1 => 0,
+            _ => {
+                self.ands.push((a.max(b), a.min(b)));
+                2 * self.variables()
+            }
+        }
+    }
+    /// Adds the gate for an and variable of a file, and what it uses first
+    fn define(
+        &mut self,
+        var: u32,
+        defs: &HashMap<u32, (u32, u32)>,
+        map: &mut HashMap<u32, u32>,
+    ) -> Result<(), AigerError> {
+        let mut visiting = HashMap::new();
+        let mut stack = vec![var];
+        while let Some(&var) = stack.last() {
+            if map.contains_key(&var) {
+                stack.pop();
+                continue;
+            }
+            let (left, right) = *defs.get(&var).ok_or_else(|| {
+                invalid(&format!("variable {} is undefined", var))
+            })?;
+            let pending: Vec<u32> = [left / 2, right / 2]
+                .iter()
+                .cloned()
+                .filter(|v| !map.contains_key(v))
+                .collect();
+            if pending.is_empty() {
+                let lit = self.and(
+                    map[&(left / 2)] ^ (left & 1),
+                    map[&(right / 2)] ^ (right & 1),
+                );
+                map.insert(var, lit);
+                stack.pop();
+            } else if visiting.insert(var, ()).is_some() {
+                return Err(AigerError::CombinationalLoop);
+            } else {
+                stack.extend(pending);
+            }
+        }
+        Ok(())
+    }
+    fn header(&self, format: &str) -> String {
+        format!(
+            "{} {} {} {} {} {}\n",
+            format,
+            self.variables(),
+            self.inputs.len(),
+            self.latches.len(),
+            self.outputs.len(),
+            self.ands.len()
+        )
+    }
+    fn symbols(&self) -> String {
+        let mut text = String::new();
+        for (i, name) in self.inputs.iter().enumerate() {
+            text.push_str(&format!("i{} {}\n", i, name));
+        }
+        for (k, (name, _)) in self.outputs.iter().enumerate() {
+            text.push_str(&format!("o{} {}\n", k, name));
+        }
+        format!("{}c\n{}\n", text, self.name)
+    }
+}
+
+impl Nets {
+    fn new_net(&mut self) -> usize {
+        self.count += 1;
+        self.count - 1
+    }
+    fn net(&mut self, lit: u32) -> usize {
+        match lit {
+            0 => FALSE_NET,
+            1 => TRUE_NET,
+            _ if lit & 1 == 0 => lit as usize / 2 + 1,
+            _ => {
+                if let Some(net) = self.negated.get(&(lit / 2)) {
+                    return *net;
+                }
+                let out = self.new_net();
+                self.gates.push(Gate::Not {
+                    input: lit as usize / 2 + 1,
+                    out,
+                });
+                self.negated.insert(lit / 2, out);
+                out
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn line(&mut self) -> Result<String, AigerError> {
+        let rest = &self.bytes[self.position..];
+        if rest.is_empty() {
+            return Err(invalid("unexpected end of file"));
+        }
+        let end = rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
+        self.position += (end + 1).min(rest.len());
+        String::from_utf8(rest[..end].to_vec())
+            .map_err(|_| invalid("expected text"))
+    }
+    /// A line of at least `count` numbers
+    fn numbers(&mut self, count: usize) -> Result<Vec<u32>, AigerError> {
+        let line = self.line()?;
+        let numbers = line
+            .split_whitespace()
+            .map(|n| n.parse())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|_| {
+                invalid(&format!("expected numbers, got '{}'", line))
+            })?;
+        if numbers.len() < count {
+            return Err(invalid(&format!("expected {} numbers", count)));
+        }
+        Ok(numbers)
+    }
+    fn decode(&mut self) -> Result<u32, AigerError> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or_else(|| invalid("unexpected end of and gates"))?;
+            self.position += 1;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("number too long"))
+    }
+}
+
+/// Seven bits per byte, lowest first, the top bit set on all but the last
+fn encode(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn invalid(message: &str) -> AigerError {
+    AigerError::Invalid(message.to_string())
+}
+
+impl std::fmt::Display for AigerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AigerError::CombinationalLoop => {
+                write!(f, "gates in a loop without a DFF")
+            }
+            AigerError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for AigerError {}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Chip;
+    use crate::loader::fixtures::{load, same_behaviour};
+
+    #[test]
+    fn mux_gates() {
+        let aig = Aig::from_netlist(&Netlist::compile(&load("Mux"))).unwrap();
+        assert_eq!(aig.inputs, vec!["a", "b", "sel"]);
+        assert!(aig.to_ascii().starts_with("aag "));
+        let aig = Aig::from_netlist(&Netlist::compile(&Chip::new_builtin(
+            crate::chip::BuiltinChips::Mux,
+        )))
+        .unwrap();
+        assert_eq!(
+            aig.to_ascii(),
+            "aag 6 3 0 1 3\n2\n4\n6\n13\n8 6 4\n10 7 2\n12 11 9\n\
+             i0 a\ni1 b\ni2 sel\no0 out\nc\nMux\n"
+        );
+    }
+    #[test]
+    fn round_trips() {
+        for name in &["Xor", "ALU", "Bit", "PC", "CPU"] {
+            let chip = load(name);
+            let aig = Aig::from_netlist(&Netlist::compile(&chip)).unwrap();
+            let binary = Aig::parse(&aig.to_binary()).unwrap();
+            assert_eq!(binary, aig);
+            let ascii = Aig::parse(aig.to_ascii().as_bytes()).unwrap();
+            assert_eq!(ascii, aig);
+            same_behaviour(chip, binary.to_netlist());
+        }
+    }
+    #[test]
+    fn ascii_in_any_order() {
+        // A latch reading !!(a & b), gates listed before their operands
+        let text = "aag 5 2 1 1 2\n2\n4\n6 11\n6\n10 9 1\n8 2 4\n";
+        let aig = Aig::parse(text.as_bytes()).unwrap();
+        assert_eq!(aig.ands, vec![(4, 2)]);
+        assert_eq!(aig.latches, vec![8]);
+        assert_eq!(aig.outputs, vec![("o0".to_string(), 6)]);
+        let looped = "aag 3 1 0 1 2\n2\n6\n4 6 2\n6 4 2\n";
+        assert_eq!(
+            Aig::parse(looped.as_bytes()),
+            Err(AigerError::CombinationalLoop)
+        );
+        assert!(Aig::parse(b"aag 1 1 0 0 0 1 0 0 0\n2\n").is_err());
+    }
+    #[test]
+    fn variable_length_numbers() {
+        let mut bytes = Vec::new();
+        for value in &[0, 127, 128, 16383, 16384, u32::MAX] {
+            encode(&mut bytes, *value);
+        }
+        assert_eq!(&bytes[..4], &[0, 0x7f, 0x80, 0x01]);
+        let mut reader = Reader {
+            bytes: &bytes,
+            position: 0,
+        };
+        for value in &[0, 127, 128, 16383, 16384, u32::MAX] {
+            assert_eq!(reader.decode(), Ok(*value));
+        }
+    }
+}
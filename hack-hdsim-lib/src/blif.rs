@@ -0,0 +1,816 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::chip::{
+    BuiltinChips, Child, ChildConnection, Chip, Pinline, PinlineConnection,
+};
+use crate::netlist::{
+    bit_names, group_bits, topological_order, Dff, Gate, NetPinline, Netlist,
+    FALSE_NET, TRUE_NET,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlifError {
+    /// Line and what is wrong with it
+    Syntax(usize, String),
+    /// Line and the construct, like `.subckt`
+    Unsupported(usize, String),
+    /// Signal read but never driven
+    Undriven(String),
+    /// Signal driven by more than one cover or latch
+    MultipleDrivers(String),
+    /// Signal that only passes through covers back to itself
+    CombinationalLoop(String),
+    /// Nothing to make a chip's parts from
+    NoLogic,
+    /// Signal in both `.inputs` and `.outputs`, which a chip can't have as
+    /// one IN and one OUT pin
+    InputAndOutput(String),
+}
+
+/// Primitive parts of a chip read from BLIF, over nets of a netlist
+#[derive(Debug, Clone, Copy)]
+enum Part {
+    Nand(usize, usize, usize),
+    Not(usize, usize),
+    Dff(usize, usize),
+}
+
+/// Rows of a cover, an input pattern like `1-0` and the output it gives
+type Rows = Vec<(String, char)>;
+
+/// A netlist being read, gates writing to fresh nets and each signal either
+/// the net of an input or latch or an alias of another net
+#[derive(Default)]
+struct Builder {
+    nets: HashMap<String, usize>,
+    aliases: HashMap<usize, usize>,
+    net_count: usize,
+    gates: Vec<Gate>,
+    negated: HashMap<usize, usize>,
+}
+
+// ============================================================================
+
+impl Netlist {
+    /// A BLIF model with a `.names` cover per gate and a `.latch` starting at
+    /// 0 per DFF. Bits of buses are named like `b[3]` and other nets `$n` and
+    /// their number, nets nothing drives are `$false`.
+    pub fn to_blif(&self) -> String {
+        let mut names = vec![None; self.net_count];
+        names[FALSE_NET] = Some("$false".to_string());
+        names[TRUE_NET] = Some("$true".to_string());
+        let mut inputs = Vec::new();
+        for pinline in &self.input {
+            for (net, name) in pinline.nets.iter().zip(bit_names(pinline)) {
+                names[*net] = Some(name.clone());
+                inputs.push(name);
+            }
+        }
+        let internal = |net: usize| Some(format!("$n{}", net));
+        for gate in &self.gates {
+            if names[gate.output()].is_none() {
+                names[gate.output()] = internal(gate.output());
+            }
+        }
+        for dff in &self.dffs {
+            names[dff.out] = internal(dff.out);
+        }
+        let name = |net: usize| names[net].as_deref().unwrap_or("$false");
+
+        let outputs: Vec<String> =
+            self.output.iter().flat_map(bit_names).collect();
+        let mut text = format!(
+            ".model {}\n.inputs {}\n.outputs {}\n\
+             .names $false\n.names $true\n1\n",
+            self.name,
+            inputs.join(" "),
+            outputs.join(" ")
+        );
+        for dff in &self.dffs {
+            text.push_str(&format!(
+                ".latch {} {} 0\n",
+                name(dff.input),
+                name(dff.out)
+            ));
+        }
+        for gate in &self.gates {
+            let out = name(gate.output());
+            text.push_str(&match *gate {
+                Gate::Nand { a, b, .. } => format!(
+                    ".names {} {} {}\n0- 1\n-0 1\n",
+                    name(a),
+                    name(b),
+                    out
+                ),
+                Gate::Not { input, .. } => {
+                    format!(".names {} {}\n0 1\n", name(input), out)
+                }
+                Gate::Mux { a, b, sel, .. } => format!(
+                    ".names {} {} {} {}\n1-0 1\n-11 1\n",
+                    name(a),
+                    name(b),
+                    name(sel),
+                    out
+                ),
+            });
+        }
+        let nets = self.output.iter().flat_map(|p| p.nets.iter());
+        for (net, output) in nets.zip(outputs.iter()) {
+            text.push_str(&format!(".names {} {}\n1 1\n", name(*net), output));
+        }
+        text + ".end\n"
+    }
+    /// The first model of a BLIF file. Covers become Nand and Not gates and
+    /// latches DFFs. Bits named like `b[3]` go together into buses.
+    pub fn from_blif(text: &str) -> Result<Self, BlifError> {
+        let mut name = "Blif".to_string();
+        let mut inputs: Vec<String> = Vec::new();
+        let mut outputs: Vec<String> = Vec::new();
+        // Signals and rows of each cover
+        let mut covers: Vec<(Vec<String>, Rows)> = Vec::new();
+        // Input, output and initial value of each latch
+        let mut latches: Vec<(String, String, bool)> = Vec::new();
+        let mut in_cover = false;
+        for (number, line) in logical_lines(text) {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let syntax = |message: &str| {
+                Err(BlifError::Syntax(number, message.to_string()))
+            };
+            if !words[0].starts_with('.') {
+                let cover = match covers.last_mut() {
+                    Some(cover) if in_cover => cover,
+                    _ => return syntax("cover row outside .names"),
+                };
+                let (pattern, value) = match words.as_slice() {
+                    [value] => ("", *value),
+                    [pattern, value] => (*pattern, *value),
+                    _ => return syntax("expected an input pattern and value"),
+                };
+                if pattern.len() + 1 != cover.0.len()
+                    || pattern.chars().any(|c| !"01-".contains(c))
+                {
+                    return syntax("pattern doesn't fit the signals");
+                }
+                let value = match value {
+                    "1" => '1',
+                    "0" => '0',
+                    _ => return syntax("expected 0 or 1 after the pattern"),
+                };
+                if cover.1.iter().any(|(_, v)| *v != value) {
+                    return syntax("rows give both 0 and 1");
+                }
+                cover.1.push((pattern.to_string(), value));
+                continue;
+            }
+            in_cover = false;
+            match words[0] {
+                ".model" => name = words.get(1).unwrap_or(&"Blif").to_string(),
+                ".inputs" => {
+                    inputs.extend(words[1..].iter().map(|w| w.to_string()))
+                }
+                ".outputs" => {
+                    outputs.extend(words[1..].iter().map(|w| w.to_string()))
+                }
+                ".names" if words.len() > 1 => {
+                    let signals = words[1..].iter().map(|w| w.to_string());
+                    covers.push((signals.collect(), Vec::new()));
+                    in_cover = true;
+                }
+                ".latch" if words.len() >= 3 => {
+                    let initial = match words.len() {
+                        4 => words[3],
+                        6 => words[5],
+                        _ => "0",
+                    };
+                    let initial = match initial {
+                        "1" => true,
+                        "0" | "2" | "3" => false,
+                        _ => return syntax("expected an initial value 0 to 3"),
+                    };
+                    latches.push((
+                        words[1].to_string(),
+                        words[2].to_string(),
+                        initial,
+                    ));
+                }
+                ".names" | ".latch" => return syntax("missing signals"),
+                ".end" | ".exdc" => break,
+                ".clock" => {}
+                other => {
+                    return Err(BlifError::Unsupported(
+                        number,
+                        other.to_string(),
+                    ))
+                }
+            }
+        }
+
+        let mut builder = Builder {
+            net_count: 2,
+            ..Builder::default()
+        };
+        let mut driven: HashSet<String> = HashSet::new();
+        let mut drive = |signal: &str| match driven.insert(signal.to_string()) {
+            true => Ok(()),
+            false => Err(BlifError::MultipleDrivers(signal.to_string())),
+        };
+        for input in &inputs {
+            drive(input)?;
+            builder.net(input);
+        }
+        let mut dffs = Vec::new();
+        for (input, output, initial) in &latches {
+            drive(output)?;
+            let (input, output) = (builder.net(input), builder.net(output));
+            if *initial {
+                // Keeps the negation, which starts at 0
+                let state = builder.new_net();
+                let next = builder.not(input);
+                dffs.push(Dff {
+                    input: next,
+                    out: state,
+                });
+                let value = builder.not(state);
+                builder.aliases.insert(output, value);
+            } else {
+                dffs.push(Dff { input, out: output });
+            }
+        }
+        for (signals, rows) in &covers {
+            let (output, inputs) = signals.split_last().unwrap();
+            drive(output)?;
+            let inputs: Vec<usize> =
+                inputs.iter().map(|s| builder.net(s)).collect();
+            let value = builder.cover(&inputs, rows);
+            let output = builder.net(output);
+            builder.aliases.insert(output, value);
+        }
+        // In the order signals first appear
+        let mut signals: Vec<(usize, &String)> =
+            builder.nets.iter().map(|(s, net)| (*net, s)).collect();
+        signals.sort();
+        let mut resolved: Vec<usize> = (0..builder.net_count).collect();
+        for (net, signal) in signals {
+            if !driven.contains(signal) {
+                return Err(BlifError::Undriven(signal.clone()));
+            }
+            resolved[net] = builder
+                .resolve(net)
+                .ok_or_else(|| BlifError::CombinationalLoop(signal.clone()))?;
+        }
+        let map = |net: usize| resolved[net];
+        let gates = builder
+            .gates
+            .iter()
+            .map(|gate| match *gate {
+                Gate::Nand { a, b, out } => Gate::Nand {
+                    a: map(a),
+                    b: map(b),
+                    out,
+                },
+                Gate::Not { input, out } => Gate::Not {
+                    input: map(input),
+                    out,
+                },
+                Gate::Mux { a, b, sel, out } => Gate::Mux {
+                    a: map(a),
+                    b: map(b),
+                    sel: map(sel),
+                    out,
+                },
+            })
+            .collect();
+        let dffs = dffs
+            .iter()
+            .map(|dff| Dff {
+                input: map(dff.input),
+                out: dff.out,
+            })
+            .collect();
+        for output in &outputs {
+            if !driven.contains(output) {
+                return Err(BlifError::Undriven(output.clone()));
+            }
+        }
+        let bits = |signals: &[String]| {
+            signals
+                .iter()
+                .map(|s| (s.clone(), map(builder.nets[s.as_str()])))
+                .collect()
+        };
+        let (input, output) = (bits(&inputs), bits(&outputs));
+        let mut net_count = builder.net_count;
+        let input = group_bits(input, &mut net_count);
+        let output = group_bits(output, &mut net_count);
+        Ok(Netlist {
+            name,
+            input,
+            internal: Vec::new(),
+            output,
+            gates: topological_order(gates, net_count),
+            dffs,
+            net_count,
+        })
+    }
+}
+
+impl Chip {
+    /// A chip from the first model of a BLIF file, built from Nand, Not and
+    /// DFF parts like `Netlist::from_blif` reads it. Signals between parts
+    /// become internal pins named `n` and a number.
+    pub fn from_blif(text: &str) -> Result<Chip, BlifError> {
+        let netlist = Netlist::from_blif(text)?;
+        let is_input =
+            |name: &str| netlist.input.iter().any(|p| p.name == name);
+        if let Some(pinline) = netlist.output.iter().find(|p| is_input(&p.name))
+        {
+            return Err(BlifError::InputAndOutput(pinline.name.clone()));
+        }
+        let mut net_count = netlist.net_count;
+        let mut new_net = || {
+            net_count += 1;
+            net_count - 1
+        };
+        let mut parts = Vec::new();
+        for gate in &netlist.gates {
+            match *gate {
+                Gate::Nand { a, b, out } => parts.push(Part::Nand(a, b, out)),
+                Gate::Not { input, out } => parts.push(Part::Not(input, out)),
+                Gate::Mux { a, b, sel, out } => {
+                    let (not_sel, low, high) =
+                        (new_net(), new_net(), new_net());
+                    parts.push(Part::Not(sel, not_sel));
+                    parts.push(Part::Nand(a, not_sel, low));
+                    parts.push(Part::Nand(b, sel, high));
+                    parts.push(Part::Nand(low, high, out));
+                }
+            }
+        }
+        for dff in &netlist.dffs {
+            parts.push(Part::Dff(dff.input, dff.out));
+        }
+        let driven: HashSet<usize> = parts.iter().map(|p| p.output()).collect();
+
+        // Output bits each net drives, through Not gates when no part does
+        let mut drives: HashMap<usize, Vec<(&str, usize)>> = HashMap::new();
+        let inputs: HashSet<usize> =
+            netlist.input.iter().flat_map(|p| p.nets.clone()).collect();
+        for pinline in &netlist.output {
+            for (i, net) in pinline.nets.iter().enumerate() {
+                let net = if driven.contains(net) {
+                    *net
+                } else if inputs.contains(net) {
+                    let (negated, out) = (new_net(), new_net());
+                    parts.push(Part::Not(*net, negated));
+                    parts.push(Part::Not(negated, out));
+                    out
+                } else {
+                    let out = new_net();
+                    match *net {
+                        TRUE_NET => parts.push(Part::Not(FALSE_NET, out)),
+                        _ => parts.push(Part::Not(TRUE_NET, out)),
+                    }
+                    out
+                };
+                drives.entry(net).or_default().push((&pinline.name, i));
+            }
+        }
+        if parts.is_empty() {
+            return Err(BlifError::NoLogic);
+        }
+        let driven: HashSet<usize> = parts.iter().map(|p| p.output()).collect();
+
+        let taken: HashSet<&str> = netlist
+            .input
+            .iter()
+            .chain(netlist.output.iter())
+            .map(|p| p.name.as_str())
+            .collect();
+        let internal = |net: usize| {
+            let base = format!("n{}", net);
+            let mut name = base.clone();
+            let mut n = 1;
+            while taken.contains(name.as_str()) {
+                n += 1;
+                name = format!("{}_{}", base, n);
+            }
+            name
+        };
+        let mut sources: HashMap<usize, PinlineConnection> = HashMap::new();
+        for pinline in &netlist.input {
+            for (i, net) in pinline.nets.iter().enumerate() {
+                sources.insert(
+                    *net,
+                    PinlineConnection::new(&pinline.name, vec![i]),
+                );
+            }
+        }
+        let read: HashSet<usize> =
+            parts.iter().flat_map(|p| p.inputs()).collect();
+        let source = |net: usize| match sources.get(&net) {
+            Some(source) => source.clone(),
+            None if net == TRUE_NET => PinlineConnection::new("true", vec![]),
+            None if driven.contains(&net) => {
+                PinlineConnection::new(&internal(net), vec![0])
+            }
+            None => PinlineConnection::new("false", vec![]),
+        };
+        let pin = |name: &str| PinlineConnection::new(name, vec![0]);
+        let children = parts
+            .iter()
+            .map(|part| {
+                let (id, pins): (_, &[&str]) = match part {
+                    Part::Nand(..) => (BuiltinChips::Nand, &["a", "b"]),
+                    Part::Not(..) => (BuiltinChips::Not, &["in"]),
+                    Part::Dff(..) => (BuiltinChips::DFF, &["in"]),
+                };
+                let mut connections: Vec<ChildConnection> = pins
+                    .iter()
+                    .zip(part.inputs())
+                    .map(|(own, net)| {
+                        ChildConnection::new(pin(own), source(net))
+                    })
+                    .collect();
+                let out = part.output();
+                if read.contains(&out) {
+                    connections.push(ChildConnection::new(
+                        pin("out"),
+                        pin(&internal(out)),
+                    ));
+                }
+                for (name, i) in drives.get(&out).into_iter().flatten() {
+                    connections.push(ChildConnection::new(
+                        pin("out"),
+                        PinlineConnection::new(name, vec![*i]),
+                    ));
+                }
+                Child::new(Chip::new_builtin(id), connections)
+            })
+            .collect();
+        let pinlines = |pinlines: &[NetPinline]| {
+            pinlines
+                .iter()
+                .map(|p| Pinline::with_capacity(&p.name, p.nets.len()))
+                .collect()
+        };
+        Ok(Chip::new_custom(
+            &netlist.name,
+            pinlines(&netlist.input),
+            pinlines(&netlist.output),
+            children,
+        ))
+    }
+}
+
+impl Part {
+    fn inputs(&self) -> Vec<usize> {
+        match *self {
+            Part::Nand(a, b, _) => vec![a, b],
+            Part::Not(input, _) | Part::Dff(input, _) => vec![input],
+        }
+    }
+    fn output(&self) -> usize {
+        match *self {
+            Part::Nand(_, _, out) | Part::Not(_, out) | Part::Dff(_, out) => {
+                out
+            }
+        }
+    }
+}
+
+impl Builder {
+    fn new_net(&mut self) -> usize {
+        self.net_count += 1;
+        self.net_count - 1
+    }
+    /// Net of a signal, new the first time it is named
+    fn net(&mut self, signal: &str) -> usize {
+        if let Some(net) = self.nets.get(signal) {
+            return *net;
+        }
+        let net = self.new_net();
+        self.nets.insert(signal.to_string(), net);
+        net
+    }
+    fn not(&mut self, input: usize) -> usize {
+        match input {
+            FALSE_NET => TRUE_NET,
+            TRUE_NET => FALSE_NET,
+            _ => {
+                if let Some(out) = self.negated.get(&input) {
+                    return *out;
+                }
+                let out = self.new_net();
+                self.gates.push(Gate::Not { input, out });
+                self.negated.insert(input, out);
+                out
+            }
+        }
+    }
+    fn nand(&mut self, a: usize, b: usize) -> usize {
+        let out = self.new_net();
+        self.gates.push(Gate::Nand { a, b, out });
+        out
+    }
+    /// Or of the rows, each the and of its literals, negated for a cover of
+    /// 0 rows. No rows at all is false.
+    fn cover(&mut self, inputs: &[usize], rows: &[(String, char)]) -> usize {
+        let mut any: Option<usize> = None;
+        for (pattern, _) in rows {
+            let mut all: Option<usize> = None;
+            for (c, input) in pattern.chars().zip(inputs.iter()) {
+                let literal = match c {
+                    '1' => *input,
+                    '0' => self.not(*input),
+                    _ => continue,
+                };
+                all = Some(match all {
+                    None => literal,
+                    Some(all) => {
+                        let nand = self.nand(all, literal);
+                        self.not(nand)
+                    }
+                });
+            }
+            let term = all.unwrap_or(TRUE_NET);
+            any = Some(match any {
+                None => term,
+                Some(any) => {
+                    let (a, b) = (self.not(any), self.not(term));
+                    self.nand(a, b)
+                }
+            });
+        }
+        match (any, rows.first()) {
+            (Some(value), Some((_, '0'))) => self.not(value),
+            (Some(value), _) => value,
+            (None, _) => FALSE_NET,
+        }
+    }
+    /// Net a signal ends up as after following aliases, `None` in a loop
+    fn resolve(&self, mut net: usize) -> Option<usize> {
+        for _ in 0..=self.aliases.len() {
+            match self.aliases.get(&net) {
+                Some(next) => net = *next,
+                None => return Some(net),
+            }
+        }
+        None
+    }
+}
+
+/// Lines without comments, continuations joined, with the number of the
+/// line each starts on
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let (line, continues) = match line.trim_end().strip_suffix('\\') {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        let (number, mut joined) =
+            current.take().unwrap_or((i + 1, String::new()));
+        joined.push(' ');
+        joined.push_str(line);
+        if continues {
+            current = Some((number, joined));
+        } else if !joined.trim().is_empty() {
+            lines.push((number, joined.trim().to_string()));
+        }
+    }
+    lines.extend(current.filter(|(_, line)| !line.trim().is_empty()));
+    lines
+}
+
+impl BlifError {
+    /// Line of the file the error is on, 0 when it is about a signal
+    pub fn line(&self) -> usize {
+        match self {
+            BlifError::Syntax(line, _) | BlifError::Unsupported(line, _) => {
+                *line
+            }
+            _ => 0,
+        }
+    }
+    /// What is wrong, without the line
+    pub fn message(&self) -> String {
+        match self {
+            BlifError::Syntax(_, message) => message.clone(),
+            BlifError::Unsupported(_, construct) => {
+                format!("{} is not supported", construct)
+            }
+            BlifError::Undriven(signal) => format!("nothing drives {}", signal),
+            BlifError::MultipleDrivers(signal) => {
+                format!("{} is driven more than once", signal)
+            }
+            BlifError::CombinationalLoop(signal) => {
+                format!("{} only drives itself", signal)
+            }
+            BlifError::NoLogic => "there are no gates or latches".to_string(),
+            BlifError::InputAndOutput(signal) => {
+                format!("{} is both an input and an output", signal)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for BlifError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.line() {
+            0 => write!(f, "{}", self.message()),
+            line => write!(f, "line {}: {}", line, self.message()),
+        }
+    }
+}
+
+impl std::error::Error for BlifError {}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Chip;
+    use crate::equivalence;
+    use crate::loader::fixtures::{load, load_str, same_behaviour, temp_dir};
+    use crate::loader::{ChipLoader, LoadError};
+    use crate::netlist::NetlistSimulator;
+    use crate::script;
+
+    fn outputs(sim: &mut NetlistSimulator, inputs: &[(&str, i64)]) -> u64 {
+        for (name, value) in inputs {
+            sim.set_input(name, *value).unwrap();
+        }
+        sim.evaluate();
+        sim.get_value("out").unwrap()
+    }
+
+    #[test]
+    fn writes_covers() {
+        let blif = Netlist::compile(&load("Not16")).to_blif();
+        assert!(blif.starts_with(".model Not16\n.inputs in[0] in[1] "));
+        assert!(blif.contains("\n.outputs out[0] out[1] "));
+        assert!(blif.contains(".names in[15] $n"));
+        assert!(blif.ends_with(" out[15]\n1 1\n.end\n"));
+        let blif = Netlist::compile(&load("Bit")).to_blif();
+        assert_eq!(blif.matches(".latch ").count(), 1);
+    }
+    #[test]
+    fn round_trips() {
+        for name in &["Xor", "ALU", "Bit", "PC", "CPU"] {
+            let chip = load(name);
+            let blif = Netlist::compile(&chip).to_blif();
+            same_behaviour(chip, Netlist::from_blif(&blif).unwrap());
+        }
+    }
+    #[test]
+    fn reads_covers() {
+        // Majority with an off-set cover, a continuation and a comment
+        let blif = "\
+            .model Majority\n\
+            .inputs a b \\\n c  # three inputs\n\
+            .outputs out\n\
+            .names a b c out\n\
+            00- 0\n\
+            0-0 0\n\
+            -00 0\n\
+            .end\n";
+        let netlist = Netlist::from_blif(blif).unwrap();
+        assert_eq!(netlist.name, "Majority");
+        let mut sim = NetlistSimulator::new(netlist);
+        for i in 0..8 {
+            let bits = [("a", i & 1), ("b", (i >> 1) & 1), ("c", i >> 2)];
+            let majority = (i as u32).count_ones() >= 2;
+            assert_eq!(outputs(&mut sim, &bits), majority as u64, "{}", i);
+        }
+    }
+    #[test]
+    fn latches_and_buses() {
+        // Toggles from 1 each clock, out[1] follows in[1]
+        let blif = "\
+            .inputs in[1]\n\
+            .outputs out[0] out[1]\n\
+            .latch next out[0] re clk 1\n\
+            .names out[0] next\n\
+            0 1\n\
+            .names in[1] out[1]\n\
+            1 1\n";
+        let netlist = Netlist::from_blif(blif).unwrap();
+        assert_eq!(netlist.input[0].nets.len(), 2);
+        let mut sim = NetlistSimulator::new(netlist);
+        let mut values = Vec::new();
+        for _ in 0..3 {
+            values.push(outputs(&mut sim, &[("in", 2)]));
+            sim.tick();
+            sim.tock();
+        }
+        assert_eq!(values, vec![3, 2, 3]);
+    }
+    #[test]
+    fn unused_inputs() {
+        let blif = ".inputs a b\n.outputs out\n.names a out\n1 1\n";
+        let netlist = Netlist::from_blif(blif).unwrap();
+        assert_eq!(netlist.input.len(), 2);
+        let mut sim = NetlistSimulator::new(netlist);
+        sim.set_input("b", 1).unwrap();
+        assert_eq!(outputs(&mut sim, &[("a", 1)]), 1);
+    }
+    #[test]
+    fn errors() {
+        let read = |text: &str| Netlist::from_blif(text).unwrap_err();
+        assert_eq!(
+            read(".inputs a\n.outputs b\n.subckt x a=a\n"),
+            BlifError::Unsupported(3, ".subckt".to_string())
+        );
+        assert_eq!(
+            read(".outputs b\n.names a b\n1 1\n"),
+            BlifError::Undriven("a".to_string())
+        );
+        assert_eq!(
+            read(".inputs a\n.outputs a\n.names a\n1\n"),
+            BlifError::MultipleDrivers("a".to_string())
+        );
+        assert_eq!(
+            read(".outputs b\n.names b c\n1 1\n.names c b\n1 1\n"),
+            BlifError::CombinationalLoop("b".to_string())
+        );
+        assert_eq!(
+            read(".inputs a\n.outputs b\n.names a b\n11 1\n"),
+            BlifError::Syntax(4, "pattern doesn't fit the signals".to_string())
+        );
+    }
+    #[test]
+    fn chips_of_primitive_parts() {
+        let alu = load("ALU");
+        let chip = Chip::from_blif(&Netlist::compile(&alu).to_blif()).unwrap();
+        assert_eq!(chip.name, "ALU");
+        assert!(chip.parts.iter().all(|part| part.chip.is_builtin()));
+        assert_eq!(equivalence::check(&chip, &alu), Ok(None));
+        assert!(chip
+            .pinlines
+            .internal
+            .iter()
+            .all(|p| p.name.starts_with('n')));
+        // Written out as HDL it loads back as the same chip
+        let hdl = load_str("ALU", &chip.to_hdl(), &[]);
+        assert_eq!(equivalence::check(&hdl, &alu), Ok(None));
+
+        // Internal pins don't take the name of a pin from the file
+        let chip = Chip::from_blif(
+            ".inputs n3\n.outputs b\n.names n3 c\n0 1\n.names c b\n0 1\n",
+        )
+        .unwrap();
+        let names: Vec<&str> = chip
+            .pinlines
+            .internal
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["n3_2"]);
+
+        // Outputs straight from an input or a constant still get a part
+        let chip = Chip::from_blif(
+            ".inputs a\n.outputs c b\n.names a c\n1 1\n.names b\n1\n",
+        )
+        .unwrap();
+        assert_eq!(chip.pinlines.output.len(), 2);
+        assert_eq!(chip.parts.len(), 3);
+        assert_eq!(
+            Chip::from_blif(".inputs a\n.outputs\n").unwrap_err(),
+            BlifError::NoLogic
+        );
+        // A chip can't have an IN and an OUT pin of the same name
+        assert_eq!(
+            Chip::from_blif(".inputs a\n.outputs a b\n.names b\n1\n")
+                .unwrap_err(),
+            BlifError::InputAndOutput("a".to_string())
+        );
+    }
+    #[test]
+    fn loads_and_runs_scripts() {
+        let dir = temp_dir("blif");
+        let blif = Netlist::compile(&load("Bit")).to_blif();
+        std::fs::write(dir.join("Bit.blif"), blif).unwrap();
+        std::fs::copy("../test-hdl/Bit.cmp", dir.join("Bit.cmp")).unwrap();
+        let script = std::fs::read_to_string("../test-hdl/Bit.tst").unwrap();
+        let script = script.replace("load Bit.hdl", "load Bit.blif");
+        std::fs::write(dir.join("Bit.tst"), script).unwrap();
+        let outcome = script::run_file(&dir.join("Bit.tst")).unwrap();
+        assert!(outcome.mismatches.is_empty());
+
+        let path = dir.join("Bad.blif");
+        std::fs::write(&path, ".inputs a\n.outputs b\n.subckt x a=a\n")
+            .unwrap();
+        let e = ChipLoader::for_file(&path).load_file(&path).unwrap_err();
+        assert!(e.to_string().ends_with(":3:0: .subckt is not supported"));
+        assert_eq!(
+            e,
+            LoadError::Blif {
+                file: path,
+                error: BlifError::Unsupported(3, ".subckt".into())
+            }
+        );
+    }
+}
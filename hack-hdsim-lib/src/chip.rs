@@ -1,13 +1,31 @@
-#[derive(Debug)]
+/// Saved with its pin values. What `new_custom` works out from the parts is
+/// worked out again when loading, rather than trusted from the file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SavedChip"))]
 pub struct Chip {
     pub name: String,
     pub pinlines: ChipPinlines,
     pub parts: Vec<Child>,
     pub clocked: bool,
     pub builtin_id: Option<BuiltinChips>,
+    /// Indices into `parts` so that every part comes after the parts whose
+    /// outputs it reads
+    #[cfg_attr(feature = "serde", serde(skip))]
+    order: Vec<usize>,
+    /// Per part, whether a part at or before it in `order` reads its
+    /// outputs, so that a change means another pass
+    #[cfg_attr(feature = "serde", serde(skip))]
+    feeds_back: Vec<bool>,
+    /// Whether any output depends on an input without going through a clock
+    #[cfg_attr(feature = "serde", serde(skip))]
+    combinational: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    counts: EvaluationCounts,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChipPinlines {
     pub input: Pinlines,
     pub internal: Pinlines,
@@ -17,6 +35,7 @@ pub struct ChipPinlines {
 pub type Pinlines = Vec<Pinline>;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pinline {
     pub name: String,
     pub pins: Vec<Pin>,
@@ -24,47 +43,149 @@ pub struct Pinline {
 
 pub type Pin = bool;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "SavedChild"))]
 pub struct Child {
     pub chip: Chip,
     pub input_connections: Vec<ChildConnection>,
     pub output_connections: Vec<ChildConnection>,
+    /// Has to be evaluated even if its inputs haven't changed
+    pub(crate) stale: bool,
+}
+
+/// Part evaluations run and skipped because the part's inputs were the same
+/// as last time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EvaluationCounts {
+    pub evaluated: u64,
+    pub skipped: u64,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChildConnection {
     pub own: PinlineConnection,
     pub foreign: PinlineConnection,
+    /// Resolved by `Child::new`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    own_id: Option<PinId>,
+    /// Resolved by `Chip::new_custom`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    foreign_id: Option<PinId>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PinlineConnection {
     pub name: String,
     pub indices: Vec<usize>,
 }
 
+/// A pinline by position in `ChipPinlines` rather than by name, so
+/// propagation doesn't compare strings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PinId {
+    Input(usize),
+    Internal(usize),
+    Output(usize),
+    True,
+    False,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BuiltinChips {
     Nand,
     Not,
     DFF,
     Mux,
     Bit,
+    ROM32K,
+    RAM16K,
+    Screen,
+    Keyboard,
+}
+
+/// Bits in a word of the memory builtins
+pub const WORD: usize = 16;
+
+/// The fields of `Chip` that are saved, in the same order so that formats
+/// without field names read them back
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SavedChip {
+    name: String,
+    pinlines: ChipPinlines,
+    parts: Vec<Child>,
+    #[allow(dead_code)]
+    clocked: bool,
+    builtin_id: Option<BuiltinChips>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct SavedChild {
+    chip: Chip,
+    input_connections: Vec<ChildConnection>,
+    output_connections: Vec<ChildConnection>,
+    stale: bool,
 }
 
 // ============================================================================
 
+impl BuiltinChips {
+    pub fn from_name(name: &str) -> Option<Self> {
+        use BuiltinChips::*;
+        match name {
+            "Nand" => Some(Nand),
+            "Not" => Some(Not),
+            "DFF" => Some(DFF),
+            "Mux" => Some(Mux),
+            "Bit" => Some(Bit),
+            "ROM32K" => Some(ROM32K),
+            "RAM16K" => Some(RAM16K),
+            "Screen" => Some(Screen),
+            "Keyboard" => Some(Keyboard),
+            _ => None,
+        }
+    }
+    /// ROM32K, RAM16K, Screen and Keyboard, which hold words rather than
+    /// being made of gates
+    pub fn is_memory(&self) -> bool {
+        use BuiltinChips::*;
+        matches!(self, ROM32K | RAM16K | Screen | Keyboard)
+    }
+}
+
 impl Chip {
+    /// Panics where `try_new_custom` returns an error
     pub fn new_custom(
         name: &str,
         input: Pinlines,
         output: Pinlines,
         parts: Vec<Child>,
     ) -> Self {
+        Self::try_new_custom(name, input, output, parts)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// Pins parts connect to that aren't inputs or outputs become internal
+    /// pins. An error when there are no parts or a connection reads bits
+    /// past the end of a pin.
+    pub fn try_new_custom(
+        name: &str,
+        input: Pinlines,
+        output: Pinlines,
+        mut parts: Vec<Child>,
+    ) -> Result<Self, String> {
         if parts.is_empty() {
-            panic!(
-                "chips with no children must be built-in, so call Chip::builtin"
-            )
+            return Err(format!(
+                "chip {} has no parts, chips with no children must be \
+                 built-in, so call Chip::new_builtin",
+                name
+            ));
         }
         let mut clocked = false;
         let mut internal = Vec::<Pinline>::new();
@@ -81,7 +202,8 @@ impl Chip {
             {
                 // Any name not already present somewhere should be added
                 let name = connection.foreign.name.as_str();
-                if input.get_pinline(name).is_none()
+                if !connection.foreign.is_constant()
+                    && input.get_pinline(name).is_none()
                     && output.get_pinline(name).is_none()
                     && internal.get_pinline(name).is_none()
                 {
@@ -92,13 +214,43 @@ impl Chip {
                 }
             }
         }
-        Self {
+        let order = evaluation_order(&parts);
+        let feeds_back = feeds_back(&parts, &order);
+        let combinational = has_combinational_path(&input, &output, &parts);
+        let pinlines = ChipPinlines::new(input, internal, output);
+        for part in &mut parts {
+            for connection in part
+                .input_connections
+                .iter_mut()
+                .chain(part.output_connections.iter_mut())
+            {
+                let foreign = &connection.foreign;
+                let id = pinlines.get_id(foreign.name.as_str());
+                let fits = match id {
+                    Some(PinId::True) | Some(PinId::False) => true,
+                    Some(id) => foreign.fits(pinlines.get(id)),
+                    None => false,
+                };
+                if !fits {
+                    return Err(format!(
+                        "chip {} has no pin {}{:?}",
+                        name, foreign.name, foreign.indices
+                    ));
+                }
+                connection.foreign_id = id;
+            }
+        }
+        Ok(Self {
             name: name.to_string(),
-            pinlines: ChipPinlines::new(input, internal, output),
+            pinlines,
             parts,
             clocked,
             builtin_id: None,
-        }
+            order,
+            feeds_back,
+            combinational,
+            counts: EvaluationCounts::default(),
+        })
     }
     pub fn new_builtin(id: BuiltinChips) -> Self {
         use BuiltinChips::*;
@@ -159,18 +311,114 @@ impl Chip {
                     Pinline::with_capacity("buffer2", 1),
                 ];
             }
+            // Words are kept in `memory`, address 0 first
+            ROM32K => {
+                name = "ROM32K";
+                input = vec![Pinline::with_capacity("address", 15)];
+                output = vec![Pinline::with_capacity("out", WORD)];
+                clocked = false;
+                internal = vec![Pinline::with_capacity("memory", WORD << 15)];
+            }
+            // A write is read on tick into the `write_` pins and made on tock
+            RAM16K | Screen => {
+                let address_width = if matches!(id, RAM16K) { 14 } else { 13 };
+                name = if matches!(id, RAM16K) {
+                    "RAM16K"
+                } else {
+                    "Screen"
+                };
+                input = vec![
+                    Pinline::with_capacity("in", WORD),
+                    Pinline::with_capacity("load", 1),
+                    Pinline::with_capacity("address", address_width),
+                ];
+                output = vec![Pinline::with_capacity("out", WORD)];
+                clocked = true;
+                internal = vec![
+                    Pinline::with_capacity("memory", WORD << address_width),
+                    Pinline::with_capacity("write_in", WORD),
+                    Pinline::with_capacity("write_load", 1),
+                    Pinline::with_capacity("write_address", address_width),
+                ];
+            }
+            // The code of the key held down, 0 for none
+            Keyboard => {
+                name = "Keyboard";
+                input = Vec::with_capacity(0);
+                output = vec![Pinline::with_capacity("out", WORD)];
+                clocked = false;
+                internal = vec![Pinline::with_capacity("key", WORD)];
+            }
         }
         Self {
             name: name.to_string(),
             pinlines: ChipPinlines::new(input, internal, output),
             parts: Vec::with_capacity(0),
             clocked,
+            // Memory reads its address without waiting for the clock
+            combinational: !clocked || id.is_memory(),
             builtin_id: Some(id),
+            order: Vec::with_capacity(0),
+            feeds_back: Vec::with_capacity(0),
+            counts: EvaluationCounts::default(),
         }
     }
     pub fn is_builtin(&self) -> bool {
         self.parts.is_empty()
     }
+    /// Name of a ROM32K, RAM16K, Screen or Keyboard in the chip or any part
+    /// of it, or the chip itself
+    pub fn memory_part(&self) -> Option<&str> {
+        match &self.builtin_id {
+            Some(id) if id.is_memory() => Some(&self.name),
+            _ => self.parts.iter().find_map(|p| p.chip.memory_part()),
+        }
+    }
+    pub fn has_combinational_path(&self) -> bool {
+        self.combinational
+    }
+    /// Part by name or, when there are several of that name, `Name:n`
+    /// counting from 0 in declaration order
+    pub fn get_part(&self, selector: &str) -> Option<&Child> {
+        let (name, n) = match selector.find(':') {
+            Some(colon) => {
+                (&selector[..colon], selector[colon + 1..].parse().ok()?)
+            }
+            None => (selector, 0),
+        };
+        self.parts.iter().filter(|p| p.chip.name == name).nth(n)
+    }
+    /// Selectors `get_part` accepts, `Name:n` only where needed
+    pub fn part_selectors(&self) -> Vec<String> {
+        self.parts
+            .iter()
+            .map(|part| {
+                let same_name: Vec<_> = self
+                    .parts
+                    .iter()
+                    .filter(|p| p.chip.name == part.chip.name)
+                    .collect();
+                if same_name.len() == 1 {
+                    return part.chip.name.clone();
+                }
+                let n = same_name
+                    .iter()
+                    .position(|p| std::ptr::eq(*p, part))
+                    .unwrap();
+                format!("{}:{}", part.chip.name, n)
+            })
+            .collect()
+    }
+    /// Pin of a part at any depth, `Mux/And:1/out`
+    pub fn probe(&self, path: &str) -> Option<&Pinline> {
+        let mut chip = self;
+        let mut segments: Vec<&str> = path.split('/').collect();
+        let pin = segments.pop()?;
+        for segment in segments {
+            chip = &chip.get_part(segment)?.chip;
+        }
+        chip.pinlines.get_pinline(pin)
+    }
     /// Tick for clocked chips
     pub fn read_input(&mut self) {
         if !self.clocked {
@@ -180,20 +428,12 @@ impl Chip {
             return self.read_input_builtin();
         }
 
-        // Assume unclocked children are in the right order
-
-        // Unclocked should stabilize first
-        for unclocked_part in self.parts.iter_mut().filter(|p| !p.chip.clocked)
-        {
-            self.pinlines.send_input(unclocked_part);
-            unclocked_part.chip.evaluate();
-            self.pinlines.receive_output(unclocked_part);
-        }
-
-        // Then clocked can read input
-        for clocked_part in self.parts.iter_mut().filter(|p| p.chip.clocked) {
-            self.pinlines.send_input(clocked_part);
-            clocked_part.chip.read_input();
+        // Everything stabilizes, then clocked parts read their input. Their
+        // outputs only change on tock.
+        self.evaluate();
+        for part in self.parts.iter_mut().filter(|p| p.chip.clocked) {
+            self.pinlines.send_input(part);
+            part.chip.read_input();
         }
     }
     /// Tock for clocked chips
@@ -205,38 +445,76 @@ impl Chip {
             return self.produce_output_builtin();
         }
 
-        // Assume unclocked children are in the right order
-
-        // Clocked should produce their input first
+        // Clocked should produce their output first
         for clocked_part in self.parts.iter_mut().filter(|p| p.chip.clocked) {
             clocked_part.chip.produce_output();
             self.pinlines.receive_output(clocked_part);
         }
 
-        // Unclocked can then stabilize
-        for unclocked_part in self.parts.iter_mut().filter(|p| !p.chip.clocked)
-        {
-            self.pinlines.send_input(unclocked_part);
-            unclocked_part.chip.evaluate();
-            self.pinlines.receive_output(unclocked_part);
+        // Everything can then stabilize
+        self.evaluate()
+    }
+    /// First half of a clock cycle, unclocked chips just evaluate
+    pub fn tick(&mut self) {
+        if self.clocked {
+            self.read_input();
+        } else {
+            self.evaluate();
         }
-
-        &self.pinlines.output
     }
-    /// For unclocked chips
-    pub fn evaluate(&mut self) -> &Pinlines {
+    /// Second half of a clock cycle
+    pub fn tock(&mut self) -> &Pinlines {
         if self.clocked {
-            panic!("evaluate is only for unclocked chips");
+            self.produce_output()
+        } else {
+            self.evaluate()
         }
+    }
+    /// Part evaluations in the whole tree since the last reset
+    pub fn evaluation_counts(&self) -> EvaluationCounts {
+        self.parts.iter().map(|p| p.chip.evaluation_counts()).fold(
+            self.counts,
+            |total, counts| EvaluationCounts {
+                evaluated: total.evaluated + counts.evaluated,
+                skipped: total.skipped + counts.skipped,
+            },
+        )
+    }
+    pub fn reset_evaluation_counts(&mut self) {
+        self.counts = EvaluationCounts::default();
+        for part in &mut self.parts {
+            part.chip.reset_evaluation_counts();
+        }
+    }
+    /// Propagates inputs to outputs. Clocked chips keep their state, only
+    /// the parts between their inputs and outputs are re-evaluated.
+    pub fn evaluate(&mut self) -> &Pinlines {
         if self.is_builtin() {
             return self.evaluate_builtin();
         }
 
-        // Assume children are in the right order
-        for part in &mut self.parts {
-            self.pinlines.send_input(part);
-            part.chip.evaluate();
-            self.pinlines.receive_output(part);
+        // Parts whose inputs didn't change still have the same outputs. Parts
+        // that read each other, like a CPU and its memory, take more passes
+        // until nothing changes, or as many as there are parts for loops
+        // that never settle.
+        for _ in 0..=self.parts.len() {
+            let mut again = false;
+            for i in &self.order {
+                let part = &mut self.parts[*i];
+                let changed = self.pinlines.send_input(part);
+                if !changed && !part.stale {
+                    self.counts.skipped += 1;
+                    continue;
+                }
+                part.chip.evaluate();
+                part.stale = false;
+                self.counts.evaluated += 1;
+                let changed = self.pinlines.receive_output(part);
+                again |= changed && self.feeds_back[*i];
+            }
+            if !again {
+                break;
+            }
         }
         &self.pinlines.output
     }
@@ -253,38 +531,49 @@ impl Chip {
                 self.pinlines.output[0].pins[0] = res;
             }
             Mux => {
-                let res: Pin;
-                if self.pinlines.input[2].pins[0] {
-                    res = self.pinlines.input[1].pins[0]
+                let res: Pin = if self.pinlines.input[2].pins[0] {
+                    self.pinlines.input[1].pins[0]
                 } else {
-                    res = self.pinlines.input[0].pins[0]
-                }
+                    self.pinlines.input[0].pins[0]
+                };
                 self.pinlines.output[0].pins[0] = res;
             }
-            _ => panic!(
-                "builtin chip {:?} is clocked, can't evaluate",
-                self.builtin_id.clone().unwrap()
-            ),
+            ROM32K | RAM16K | Screen => self.read_memory(),
+            Keyboard => {
+                let key = &self.pinlines.internal[0].pins;
+                self.pinlines.output[0].pins.copy_from_slice(key);
+            }
+            // Output only changes on tock
+            DFF | Bit => {}
         }
         &self.pinlines.output
     }
+    /// The word at the `address` input, the last one, onto `out`
+    fn read_memory(&mut self) {
+        let address = self.pinlines.input.last().unwrap().get_value() as usize;
+        let word = &self.pinlines.internal[0].pins
+            [address * WORD..(address + 1) * WORD];
+        self.pinlines.output[0].pins.copy_from_slice(word);
+    }
     fn read_input_builtin(&mut self) {
         use BuiltinChips::*;
         match self.builtin_id.as_ref().unwrap() {
             DFF => {
-                self.pinlines.internal[1].pins[0] =
-                    self.pinlines.internal[0].pins[0];
                 self.pinlines.internal[0].pins[0] =
                     self.pinlines.input[0].pins[0];
             }
             Bit => {
-                self.pinlines.internal[1].pins[0] =
-                    self.pinlines.internal[0].pins[0];
                 if self.pinlines.input[1].pins[0] {
                     self.pinlines.internal[0].pins[0] =
                         self.pinlines.input[0].pins[0];
                 }
             }
+            RAM16K | Screen => {
+                for i in 0..3 {
+                    let value = self.pinlines.input[i].pins.clone();
+                    self.pinlines.internal[i + 1].pins = value;
+                }
+            }
             _ => panic!(
                 "builtin chip {:?} is unclocked, can't read input",
                 self.builtin_id.clone().unwrap()
@@ -294,13 +583,23 @@ impl Chip {
     fn produce_output_builtin(&mut self) -> &Pinlines {
         use BuiltinChips::*;
         match self.builtin_id.as_ref().unwrap() {
-            DFF => {
+            // What was read on tick is shown on tock
+            DFF | Bit => {
+                self.pinlines.internal[1].pins[0] =
+                    self.pinlines.internal[0].pins[0];
                 self.pinlines.output[0].pins[0] =
                     self.pinlines.internal[1].pins[0];
             }
-            Bit => {
-                self.pinlines.output[0].pins[0] =
-                    self.pinlines.internal[1].pins[0];
+            RAM16K | Screen => {
+                if self.pinlines.internal[2].pins[0] {
+                    let address =
+                        self.pinlines.internal[3].get_value() as usize;
+                    let word = self.pinlines.internal[1].pins.clone();
+                    self.pinlines.internal[0].pins
+                        [address * WORD..(address + 1) * WORD]
+                        .copy_from_slice(&word);
+                }
+                self.read_memory();
             }
             _ => panic!(
                 "builtin chip {:?} is unclocked, can't produce output",
@@ -311,6 +610,53 @@ impl Chip {
     }
 }
 
+/// Built again from the parts, the saved pins are then put back
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<SavedChip> for Chip {
+    type Error = String;
+
+    fn try_from(saved: SavedChip) -> Result<Self, Self::Error> {
+        let pinlines = saved.pinlines;
+        let mut chip = match saved.builtin_id {
+            Some(id) => Chip::new_builtin(id),
+            None => Chip::try_new_custom(
+                &saved.name,
+                pinlines.input.clone(),
+                pinlines.output.clone(),
+                saved.parts,
+            )?,
+        };
+        // Only pins the chip has, as wide as it has them
+        let restore = |pinlines: &mut Pinlines, saved: Pinlines| {
+            for pinline in saved {
+                match pinlines.get_pinline_mut(&pinline.name) {
+                    Some(p) if p.pins.len() == pinline.pins.len() => {
+                        p.pins = pinline.pins
+                    }
+                    _ => {}
+                }
+            }
+        };
+        restore(&mut chip.pinlines.input, pinlines.input);
+        restore(&mut chip.pinlines.internal, pinlines.internal);
+        restore(&mut chip.pinlines.output, pinlines.output);
+        Ok(chip)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<SavedChild> for Child {
+    type Error = String;
+
+    fn try_from(saved: SavedChild) -> Result<Self, Self::Error> {
+        let mut connections = saved.input_connections;
+        connections.extend(saved.output_connections);
+        let mut child = Child::try_new(saved.chip, connections)?;
+        child.stale = saved.stale;
+        Ok(child)
+    }
+}
+
 impl ChipPinlines {
     pub fn new(input: Pinlines, internal: Pinlines, output: Pinlines) -> Self {
         Self {
@@ -319,41 +665,104 @@ impl ChipPinlines {
             output,
         }
     }
-    pub fn send_input(&self, part: &mut Child) {
+    pub fn get_id(&self, name: &str) -> Option<PinId> {
+        let position =
+            |pinlines: &Pinlines| pinlines.iter().position(|p| p.name == name);
+        match name {
+            "true" => Some(PinId::True),
+            "false" => Some(PinId::False),
+            _ => position(&self.input)
+                .map(PinId::Input)
+                .or_else(|| position(&self.internal).map(PinId::Internal))
+                .or_else(|| position(&self.output).map(PinId::Output)),
+        }
+    }
+    /// Panics for the constants, which have no pinline
+    pub fn get(&self, id: PinId) -> &Pinline {
+        match id {
+            PinId::Input(i) => &self.input[i],
+            PinId::Internal(i) => &self.internal[i],
+            PinId::Output(i) => &self.output[i],
+            PinId::True | PinId::False => panic!("constants have no pinline"),
+        }
+    }
+    pub fn get_mut(&mut self, id: PinId) -> &mut Pinline {
+        match id {
+            PinId::Input(i) => &mut self.input[i],
+            PinId::Internal(i) => &mut self.internal[i],
+            PinId::Output(i) => &mut self.output[i],
+            PinId::True | PinId::False => panic!("constants have no pinline"),
+        }
+    }
+    /// Input, internal or output pinline
+    pub fn get_pinline(&self, name: &str) -> Option<&Pinline> {
+        self.input
+            .iter()
+            .chain(self.internal.iter())
+            .chain(self.output.iter())
+            .find(|p| p.name == name)
+    }
+    pub fn get_pinline_mut(&mut self, name: &str) -> Option<&mut Pinline> {
+        self.input
+            .iter_mut()
+            .chain(self.internal.iter_mut())
+            .chain(self.output.iter_mut())
+            .find(|p| p.name == name)
+    }
+    /// Connections only touch the pins they name, so several connections
+    /// can feed different parts of one pinline. Returns whether any input of
+    /// the part changed.
+    pub fn send_input(&self, part: &mut Child) -> bool {
+        let mut changed = false;
         for connection in &part.input_connections {
-            let name_to_find = connection.foreign.name.as_str();
-            let relevant_pinline = self
-                .input
+            let own_pinline = part.chip.pinlines.get_mut(connection.own_id());
+            let foreign_id = connection.foreign_id();
+            if foreign_id == PinId::True || foreign_id == PinId::False {
+                let value = foreign_id == PinId::True;
+                for own_i in &connection.own.indices {
+                    changed |= own_pinline.pins[*own_i] != value;
+                    own_pinline.pins[*own_i] = value;
+                }
+                continue;
+            }
+            let relevant_pinline = self.get(foreign_id);
+            for (own_i, foreign_i) in connection
+                .own
+                .indices
                 .iter()
-                .chain(self.internal.iter())
-                .find(|p| p.name == name_to_find)
-                .unwrap();
-            let input_pinline = relevant_pinline.clone().into_own(connection);
-            part.chip.pinlines.input.set_pinline(input_pinline);
+                .zip(connection.foreign.indices.iter())
+            {
+                let value = relevant_pinline.pins[*foreign_i];
+                changed |= own_pinline.pins[*own_i] != value;
+                own_pinline.pins[*own_i] = value;
+            }
         }
+        changed
     }
-    pub fn receive_output(&mut self, part: &Child) {
+    /// Returns whether any of our pins changed
+    pub fn receive_output(&mut self, part: &Child) -> bool {
+        let mut changed = false;
         for connection in &part.output_connections {
-            let relevant_pinline = part
-                .chip
-                .pinlines
-                .output
-                .get_pinline(connection.own.name.as_str())
-                .unwrap();
-            let our_pinline = relevant_pinline.clone().into_foreign(connection);
-            let pinline_to_replace = self
-                .internal
-                .iter_mut()
-                .chain(self.output.iter_mut())
-                .find(|p| p.name == connection.foreign.name.as_str())
-                .unwrap();
-            let _ = std::mem::replace(pinline_to_replace, our_pinline);
+            let relevant_pinline = part.chip.pinlines.get(connection.own_id());
+            let our_pinline = self.get_mut(connection.foreign_id());
+            for (own_i, foreign_i) in connection
+                .own
+                .indices
+                .iter()
+                .zip(connection.foreign.indices.iter())
+            {
+                let value = relevant_pinline.pins[*own_i];
+                changed |= our_pinline.pins[*foreign_i] != value;
+                our_pinline.pins[*foreign_i] = value;
+            }
         }
+        changed
     }
 }
 
 pub trait PinlinesMethods {
     fn get_pinline(&self, name: &str) -> Option<&Pinline>;
+    fn get_pinline_mut(&mut self, name: &str) -> Option<&mut Pinline>;
     fn set_pinline(&mut self, pinline: Pinline);
     fn set_pinlines(&mut self, pinline: Pinlines);
 }
@@ -362,6 +771,9 @@ impl PinlinesMethods for Pinlines {
     fn get_pinline(&self, name: &str) -> Option<&Pinline> {
         self.iter().find(|p| p.name == name)
     }
+    fn get_pinline_mut(&mut self, name: &str) -> Option<&mut Pinline> {
+        self.iter_mut().find(|p| p.name == name)
+    }
     fn set_pinline(&mut self, pinline: Pinline) {
         let i = self.iter().position(|p| p.name == pinline.name).unwrap();
         self[i].pins = pinline.pins;
@@ -421,11 +833,45 @@ impl Pinline {
         // Bounds check here?
         self.pins[index]
     }
+    /// Pins as an unsigned number, `pins[0]` is the least significant bit
+    pub fn get_value(&self) -> u64 {
+        self.pins
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, pin)| acc | ((*pin as u64) << i))
+    }
+    /// Bits that don't fit are dropped
+    pub fn set_value(&mut self, value: u64) {
+        for (i, pin) in self.pins.iter_mut().enumerate() {
+            *pin = i < 64 && (value >> i) & 1 == 1;
+        }
+    }
 }
 
 impl Child {
+    /// Panics where `try_new` returns an error
     pub fn new(chip: Chip, connections: Vec<ChildConnection>) -> Self {
-        // Verify that connection names make sense?
+        Self::try_new(chip, connections).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// An error when a connection names bits the chip's inputs and outputs
+    /// don't have
+    pub fn try_new(
+        chip: Chip,
+        connections: Vec<ChildConnection>,
+    ) -> Result<Self, String> {
+        for connection in &connections {
+            let own = &connection.own;
+            let pinline =
+                chip.pinlines.input.get_pinline(own.name.as_str()).or_else(
+                    || chip.pinlines.output.get_pinline(own.name.as_str()),
+                );
+            if !pinline.is_some_and(|p| own.fits(p)) {
+                return Err(format!(
+                    "chip {} has no input or output pin {}{:?}",
+                    chip.name, own.name, own.indices
+                ));
+            }
+        }
         let input_connections = connections
             .iter()
             .filter(|c| {
@@ -446,17 +892,39 @@ impl Child {
             })
             .cloned()
             .collect();
-        Self {
+        let mut child = Self {
             chip,
             input_connections,
             output_connections,
+            stale: true,
+        };
+        for connection in child
+            .input_connections
+            .iter_mut()
+            .chain(child.output_connections.iter_mut())
+        {
+            connection.own_id =
+                child.chip.pinlines.get_id(connection.own.name.as_str());
         }
+        Ok(child)
     }
 }
 
 impl ChildConnection {
     pub fn new(own: PinlineConnection, foreign: PinlineConnection) -> Self {
-        Self { own, foreign }
+        Self {
+            own,
+            foreign,
+            own_id: None,
+            foreign_id: None,
+        }
+    }
+    fn own_id(&self) -> PinId {
+        self.own_id.expect("own pin is resolved by Child::new")
+    }
+    fn foreign_id(&self) -> PinId {
+        self.foreign_id
+            .expect("foreign pin is resolved by Chip::new_custom")
     }
 }
 
@@ -467,11 +935,99 @@ impl PinlineConnection {
             indices,
         }
     }
+    /// Whether every index is a pin of `pinline`
+    fn fits(&self, pinline: &Pinline) -> bool {
+        self.indices.iter().all(|i| *i < pinline.pins.len())
+    }
+    /// `true` and `false` can be connected to any input
+    pub fn is_constant(&self) -> bool {
+        self.name == "true" || self.name == "false"
+    }
     pub fn get_pin_count(&self) -> usize {
         self.indices.len()
     }
 }
 
+/// Parts in an order where whatever they read has already been computed.
+/// Only parts with a combinational path pass values along, so edges out of
+/// clocked parts don't count. Parts in combinational loops keep their
+/// declaration order at the end.
+fn evaluation_order(parts: &[Child]) -> Vec<usize> {
+    let writes = |part: &Child, name: &str| {
+        part.chip.has_combinational_path()
+            && part
+                .output_connections
+                .iter()
+                .any(|c| c.foreign.name == name)
+    };
+    let depends_on = |reader: &Child, writer: &Child| {
+        reader
+            .input_connections
+            .iter()
+            .any(|c| writes(writer, c.foreign.name.as_str()))
+    };
+    let mut order = Vec::with_capacity(parts.len());
+    let mut placed = vec![false; parts.len()];
+    loop {
+        let ready = (0..parts.len()).filter(|i| !placed[*i]).find(|i| {
+            (0..parts.len())
+                .filter(|j| !placed[*j] && j != i)
+                .all(|j| !depends_on(&parts[*i], &parts[j]))
+        });
+        match ready {
+            Some(i) => {
+                placed[i] = true;
+                order.push(i);
+            }
+            None => break,
+        }
+    }
+    order.extend((0..parts.len()).filter(|i| !placed[*i]));
+    order
+}
+
+fn feeds_back(parts: &[Child], order: &[usize]) -> Vec<bool> {
+    let reads = |reader: &Child, writer: &Child| {
+        reader.input_connections.iter().any(|c| {
+            writer
+                .output_connections
+                .iter()
+                .any(|o| o.foreign.name == c.foreign.name)
+        })
+    };
+    let mut feeds_back = vec![false; parts.len()];
+    for (position, writer) in order.iter().enumerate() {
+        feeds_back[*writer] = order[..=position]
+            .iter()
+            .any(|reader| reads(&parts[*reader], &parts[*writer]));
+    }
+    feeds_back
+}
+
+fn has_combinational_path(
+    input: &[Pinline],
+    output: &[Pinline],
+    parts: &[Child],
+) -> bool {
+    let mut reached: Vec<&str> =
+        input.iter().map(|p| p.name.as_str()).collect();
+    let mut visited = vec![false; parts.len()];
+    while let Some(i) = (0..parts.len()).find(|i| {
+        !visited[*i]
+            && parts[*i].chip.has_combinational_path()
+            && parts[*i]
+                .input_connections
+                .iter()
+                .any(|c| reached.contains(&c.foreign.name.as_str()))
+    }) {
+        visited[i] = true;
+        for connection in &parts[i].output_connections {
+            reached.push(connection.foreign.name.as_str());
+        }
+    }
+    output.iter().any(|p| reached.contains(&p.name.as_str()))
+}
+
 // ============================================================================
 
 #[cfg(test)]
@@ -528,11 +1084,17 @@ mod tests {
         chip.pinlines
             .input
             .set_pinline(Pinline::new("in", vec![true]));
+        // Output doesn't change before tock
+        assert_eq!(chip.evaluate(), &res_expected);
         chip.read_input();
+        assert_eq!(&chip.pinlines.output, &res_expected);
+
+        res_expected[0].pins[0] = true;
+
         let mut res_actual = chip.produce_output();
         assert_eq!(res_actual, &res_expected);
 
-        res_expected[0].pins[0] = true;
+        res_expected[0].pins[0] = false;
 
         chip.pinlines
             .input
@@ -541,7 +1103,6 @@ mod tests {
         res_actual = chip.produce_output();
         assert_eq!(res_actual, &res_expected);
 
-        res_expected[0].pins[0] = false;
         chip.read_input();
         res_actual = chip.produce_output();
         assert_eq!(res_actual, &res_expected);
@@ -579,10 +1140,14 @@ mod tests {
             Pinline::new("load", vec![true]),
         ]);
         chip.read_input();
+        assert_eq!(&chip.pinlines.output, &res_expected);
+
+        res_expected[0].pins[0] = true;
+
         let mut res_actual = chip.produce_output();
         assert_eq!(res_actual, &res_expected);
 
-        res_expected[0].pins[0] = true;
+        res_expected[0].pins[0] = false;
 
         chip.pinlines
             .input
@@ -592,8 +1157,6 @@ mod tests {
         res_actual = chip.produce_output();
         assert_eq!(res_actual, &res_expected);
 
-        res_expected[0].pins[0] = false;
-
         chip.read_input();
         res_actual = chip.produce_output();
         assert_eq!(res_actual, &res_expected);
@@ -715,6 +1278,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn probe() {
+        let mut and = construct_custom_and();
+        assert_eq!(and.part_selectors(), vec!["Nand:0", "Nand:1"]);
+        and.pinlines.input.set_pinlines(vec![
+            Pinline::new("a", vec![true]),
+            Pinline::new("b", vec![true]),
+        ]);
+        and.evaluate();
+        assert_eq!(and.probe("Nand:0/out").unwrap().pins, vec![false]);
+        assert_eq!(and.probe("Nand:1/a").unwrap().pins, vec![false]);
+        assert_eq!(and.probe("c").unwrap().pins, vec![false]);
+        assert!(and.probe("Nand/out").is_some());
+        assert!(and.probe("Nand:2/out").is_none());
+        assert!(and.probe("Nand:0/c").is_none());
+    }
     #[test]
     fn internal_pins() {
         let and = construct_custom_and();
@@ -754,4 +1333,155 @@ mod tests {
         let bit = construct_custom_bit();
         test_bit(bit)
     }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn saves_and_restores_state() {
+        let mut bit = construct_custom_bit();
+        bit.pinlines.input.set_pinlines(vec![
+            Pinline::new("in", vec![true]),
+            Pinline::new("load", vec![true]),
+        ]);
+        bit.tick();
+        bit.tock();
+        let json = serde_json::to_string(&bit).unwrap();
+        let bytes = bincode::serialize(&bit).unwrap();
+        assert!(bytes.len() < json.len());
+        let restored = [
+            serde_json::from_str::<Chip>(&json).unwrap(),
+            bincode::deserialize::<Chip>(&bytes).unwrap(),
+        ];
+        for mut chip in restored {
+            assert_eq!(chip.probe("DFF/buffer2").unwrap().pins, vec![true]);
+            // The DFF keeps what was loaded before saving
+            chip.pinlines
+                .input
+                .set_pinline(Pinline::new("load", vec![false]));
+            chip.tick();
+            assert_eq!(chip.tock(), &vec![Pinline::new("out", vec![true])]);
+            chip.pinlines.input.set_pinlines(vec![
+                Pinline::new("in", vec![false]),
+                Pinline::new("load", vec![true]),
+            ]);
+            chip.tick();
+            assert_eq!(chip.tock(), &vec![Pinline::new("out", vec![false])]);
+        }
+
+        // Evaluation order and pin indices are worked out again, whatever a
+        // file from an older build or edited by hand says
+        let mut json = serde_json::to_value(&bit).unwrap();
+        assert!(json.get("order").is_none());
+        json["order"] = serde_json::json!([7, 9]);
+        json["parts"][0]["input_connections"][0]["own_id"] =
+            serde_json::json!({"Output": 5});
+        let mut chip = serde_json::from_value::<Chip>(json).unwrap();
+        chip.tick();
+        assert_eq!(chip.tock(), &vec![Pinline::new("out", vec![true])]);
+        let json = serde_json::json!({
+            "name": "Empty",
+            "pinlines": {"input": [], "internal": [], "output": []},
+            "parts": [],
+            "clocked": false,
+            "builtin_id": null
+        });
+        assert!(serde_json::from_value::<Chip>(json).is_err());
+    }
+    #[test]
+    fn register_fed_loop() {
+        // Like a CPU and its memory: Cpu's address comes from a register,
+        // Mem answers it and Cpu passes the answer straight on. Each part
+        // reads the other, but no output depends on itself.
+        let dir = crate::loader::fixtures::temp_dir("chip-register-fed-loop");
+        std::fs::write(
+            dir.join("Cpu.hdl"),
+            "CHIP Cpu { IN data, next; OUT addr, out; PARTS:
+            DFF(in=next, out=addr);
+            Not(in=data, out=out); }",
+        )
+        .unwrap();
+        let path = dir.join("Top.hdl");
+        std::fs::write(
+            &path,
+            "CHIP Top { IN next; OUT out; PARTS:
+            Cpu(data=data, next=next, addr=addr, out=out);
+            Not(in=addr, out=data); }",
+        )
+        .unwrap();
+        let mut top = crate::loader::ChipLoader::for_file(&path)
+            .load_file(&path)
+            .unwrap();
+        for (next, out) in &[(true, true), (false, false), (true, true)] {
+            top.pinlines
+                .input
+                .set_pinline(Pinline::new("next", vec![*next]));
+            top.tick();
+            assert_eq!(top.tock(), &vec![Pinline::new("out", vec![*out])]);
+        }
+    }
+    #[test]
+    fn skips_unchanged_parts() {
+        let mut and = construct_custom_and();
+        and.evaluate();
+        let counts =
+            |evaluated, skipped| EvaluationCounts { evaluated, skipped };
+        assert_eq!(and.evaluation_counts(), counts(2, 0));
+        and.evaluate();
+        assert_eq!(and.evaluation_counts(), counts(2, 2));
+        // The first Nand still outputs 1, so the second Nand is skipped
+        and.pinlines
+            .input
+            .set_pinline(Pinline::new("a", vec![true]));
+        and.evaluate();
+        assert_eq!(and.evaluation_counts(), counts(3, 3));
+        and.pinlines
+            .input
+            .set_pinline(Pinline::new("b", vec![true]));
+        assert_eq!(and.evaluate(), &vec![Pinline::new("out", vec![true])]);
+        assert_eq!(and.evaluation_counts(), counts(5, 3));
+        and.reset_evaluation_counts();
+        assert_eq!(and.evaluation_counts(), counts(0, 0));
+    }
+    #[test]
+    fn unresolved_pins() {
+        let connection = |own, own_i, foreign, foreign_i| {
+            ChildConnection::new(
+                PinlineConnection::new(own, vec![own_i]),
+                PinlineConnection::new(foreign, vec![foreign_i]),
+            )
+        };
+        let not = || Chip::new_builtin(BuiltinChips::Not);
+        let err = Child::try_new(not(), vec![connection("nope", 0, "a", 0)])
+            .unwrap_err();
+        assert_eq!(err, "chip Not has no input or output pin nope[0]");
+        assert!(
+            Child::try_new(not(), vec![connection("in", 1, "a", 0)]).is_err()
+        );
+        let part = Child::new(not(), vec![connection("in", 0, "a", 3)]);
+        let err = Chip::try_new_custom(
+            "Inv",
+            vec![Pinline::with_capacity("a", 1)],
+            vec![],
+            vec![part],
+        )
+        .unwrap_err();
+        assert_eq!(err, "chip Inv has no pin a[3]");
+        assert!(Chip::try_new_custom("Empty", vec![], vec![], vec![]).is_err());
+    }
+    #[test]
+    fn pin_ids() {
+        let and = construct_custom_and();
+        let pinlines = &and.pinlines;
+        assert_eq!(pinlines.get_id("b"), Some(PinId::Input(1)));
+        assert_eq!(pinlines.get_id("c"), Some(PinId::Internal(0)));
+        assert_eq!(pinlines.get_id("out"), Some(PinId::Output(0)));
+        assert_eq!(pinlines.get_id("true"), Some(PinId::True));
+        assert_eq!(pinlines.get_id("nope"), None);
+        assert_eq!(pinlines.get(PinId::Internal(0)).name, "c");
+        let second = &and.parts[1];
+        assert_eq!(second.input_connections[1].own_id(), PinId::Input(1));
+        assert_eq!(
+            second.input_connections[1].foreign_id(),
+            PinId::Internal(0)
+        );
+        assert_eq!(second.output_connections[0].foreign_id(), PinId::Output(0));
+    }
 }
@@ -0,0 +1,164 @@
+use crate::chip::{Chip, WORD};
+
+/// Words a ROM32K holds
+pub const ROM_WORDS: usize = 1 << 15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HackError {
+    /// Line and its text, which isn't 16 binary digits
+    NotBinary(usize, String),
+    /// Instructions in a program that doesn't fit in a ROM32K
+    TooLong(usize),
+}
+
+// ============================================================================
+
+/// Instructions of a `.hack` file, 16 binary digits a line with the most
+/// significant bit first. Blank lines are skipped.
+pub fn parse_hack(text: &str) -> Result<Vec<u16>, HackError> {
+    let mut program = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != WORD || !line.chars().all(|c| c == '0' || c == '1') {
+            return Err(HackError::NotBinary(i + 1, line.to_string()));
+        }
+        program.push(u16::from_str_radix(line, 2).unwrap());
+    }
+    if program.len() > ROM_WORDS {
+        return Err(HackError::TooLong(program.len()));
+    }
+    Ok(program)
+}
+
+impl Chip {
+    /// Puts the program in the first ROM32K, depth first, and zeroes the
+    /// words after it. False when there is no ROM32K. Outputs follow on the
+    /// next evaluation.
+    pub fn load_rom(&mut self, program: &[u16]) -> bool {
+        let mut words = program.to_vec();
+        words.resize(ROM_WORDS, 0);
+        write_memory(self, "ROM32K", &words)
+    }
+    /// Code of the key held down on the first Keyboard, 0 for none. False
+    /// when there is no Keyboard.
+    pub fn set_key(&mut self, key: u16) -> bool {
+        write_memory(self, "Keyboard", &[key])
+    }
+    /// Word at the address of the first ROM32K, RAM16K, Screen or Keyboard
+    /// with the name. A Keyboard's only word is the key at 0.
+    pub fn memory_word(&self, name: &str, address: usize) -> Option<u16> {
+        let memory = &find_memory(self, name)?.pinlines.internal[0].pins;
+        let bits = memory.get(address * WORD..(address + 1) * WORD)?;
+        Some(
+            bits.iter()
+                .rev()
+                .fold(0, |word, bit| word << 1 | *bit as u16),
+        )
+    }
+}
+
+impl std::fmt::Display for HackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HackError::NotBinary(line, text) => {
+                write!(f, "line {}: {} is not 16 binary digits", line, text)
+            }
+            HackError::TooLong(count) => write!(
+                f,
+                "{} instructions is too many, a ROM32K holds {}",
+                count, ROM_WORDS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HackError {}
+
+fn is_memory(chip: &Chip, name: &str) -> bool {
+    chip.name == name
+        && chip.builtin_id.as_ref().is_some_and(|id| id.is_memory())
+}
+
+fn find_memory<'a>(chip: &'a Chip, name: &str) -> Option<&'a Chip> {
+    if is_memory(chip, name) {
+        return Some(chip);
+    }
+    chip.parts.iter().find_map(|p| find_memory(&p.chip, name))
+}
+
+/// Words from address 0. Parts on the way are marked stale so the next
+/// evaluation doesn't skip them.
+fn write_memory(chip: &mut Chip, name: &str, words: &[u16]) -> bool {
+    if is_memory(chip, name) {
+        let memory = &mut chip.pinlines.internal[0].pins;
+        for (i, word) in words.iter().enumerate() {
+            for bit in 0..WORD {
+                memory[i * WORD + bit] = (word >> bit) & 1 == 1;
+            }
+        }
+        return true;
+    }
+    for part in &mut chip.parts {
+        if write_memory(&mut part.chip, name, words) {
+            part.stale = true;
+            return true;
+        }
+    }
+    false
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::load;
+
+    #[test]
+    fn runs_program() {
+        let text = std::fs::read_to_string("../test-hdl/Store.hack").unwrap();
+        let program = parse_hack(&text).unwrap();
+        let mut computer = load("Computer");
+        assert!(computer.load_rom(&program));
+        assert!(computer.set_key(65));
+        let run = |computer: &mut Chip| {
+            // Computer has no outputs to return from a cycle
+            for _ in 0..program.len() {
+                computer.tick();
+                computer.tock();
+            }
+            [
+                ("RAM16K", 16),
+                ("RAM16K", 17),
+                ("RAM16K", 18),
+                ("Screen", 0),
+            ]
+            .iter()
+            .map(|(name, address)| computer.memory_word(name, *address))
+            .collect::<Vec<_>>()
+        };
+        let expected = vec![Some(7), Some(8), Some(65), Some(0xFFFF)];
+        assert_eq!(run(&mut computer), expected);
+        assert_eq!(computer.memory_word("ROM32K", 0), Some(7));
+        assert_eq!(computer.memory_word("Keyboard", 1), None);
+
+        // Reset clears the RAM and keeps the program and key
+        computer.reset();
+        assert_eq!(computer.memory_word("RAM16K", 16), Some(0));
+        assert_eq!(run(&mut computer), expected);
+    }
+    #[test]
+    fn hack_errors() {
+        assert_eq!(parse_hack("\n0000000000000111\n\n"), Ok(vec![7]));
+        assert_eq!(
+            parse_hack("0000000000000111\n@7\n"),
+            Err(HackError::NotBinary(2, "@7".to_string()))
+        );
+        let long = "0000000000000000\n".repeat(ROM_WORDS + 1);
+        assert_eq!(parse_hack(&long), Err(HackError::TooLong(ROM_WORDS + 1)));
+        assert!(!load("CPU").load_rom(&[]));
+    }
+}
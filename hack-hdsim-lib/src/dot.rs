@@ -0,0 +1,311 @@
+use crate::chip::{Child, ChildConnection, Chip, PinlineConnection};
+
+/// Writes nodes and edges for one chip, nested in clusters for expanded
+/// parts. Node ids are paths like `Mux/And_1`.
+struct Graph {
+    text: String,
+    indent: usize,
+}
+
+// ============================================================================
+
+impl Chip {
+    /// Graphviz graph with a node per part and an edge per connection between
+    /// them, labelled like the HDL. Parts of parts are drawn inside their
+    /// part down to `levels` levels below this chip's own parts. Clocked
+    /// parts are filled.
+    pub fn to_dot(&self, levels: usize) -> String {
+        let mut graph = Graph {
+            text: format!("digraph {} {{\n", quote(&self.name)),
+            indent: 1,
+        };
+        graph.line("rankdir=LR;");
+        graph.line("node [shape=box, fontname=Helvetica];");
+        graph.line("edge [fontname=Helvetica, fontsize=10];");
+        graph.chip(self, &self.name, levels);
+        graph.text + "}\n"
+    }
+}
+
+impl Graph {
+    fn line(&mut self, line: &str) {
+        self.text.push_str(&"    ".repeat(self.indent));
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+    fn chip(&mut self, chip: &Chip, path: &str, levels: usize) {
+        for pinline in &chip.pinlines.input {
+            self.line(&format!(
+                "{} [label={}, shape=invhouse];",
+                quote(&input(path, &pinline.name)),
+                quote(&pinline.name)
+            ));
+        }
+        for pinline in &chip.pinlines.output {
+            self.line(&format!(
+                "{} [label={}, shape=house];",
+                quote(&output(path, &pinline.name)),
+                quote(&pinline.name)
+            ));
+        }
+        for (i, part) in chip.parts.iter().enumerate() {
+            let id = part_id(path, part, i);
+            if expanded(part, levels) {
+                self.line(&format!(
+                    "subgraph {} {{",
+                    quote(&format!("cluster_{}", id))
+                ));
+                self.indent += 1;
+                self.line(&format!("label={};", quote(&part.chip.name)));
+                if part.chip.clocked {
+                    self.line("style=filled; fillcolor=lightblue;");
+                }
+                self.chip(&part.chip, &id, levels - 1);
+                self.indent -= 1;
+                self.line("}");
+            } else if part.chip.clocked {
+                self.line(&format!(
+                    "{} [label={}, style=filled, fillcolor=lightblue];",
+                    quote(&id),
+                    quote(&part.chip.name)
+                ));
+            } else {
+                self.line(&format!(
+                    "{} [label={}];",
+                    quote(&id),
+                    quote(&part.chip.name)
+                ));
+            }
+        }
+        self.edges(chip, path, levels);
+    }
+    fn edges(&mut self, chip: &Chip, path: &str, levels: usize) {
+        let width = |name: &str| {
+            chip.pinlines
+                .get_pinline(name)
+                .map_or(1, |pinline| pinline.pins.len())
+        };
+        // Where each part output connection ends up, for finding drivers
+        let mut drivers: Vec<(&ChildConnection, String)> = Vec::new();
+        for (i, part) in chip.parts.iter().enumerate() {
+            for connection in &part.output_connections {
+                let from = endpoint(
+                    path,
+                    part,
+                    i,
+                    levels,
+                    &connection.own.name,
+                    false,
+                );
+                drivers.push((connection, from));
+            }
+        }
+        for (i, part) in chip.parts.iter().enumerate() {
+            for connection in &part.input_connections {
+                let to =
+                    endpoint(path, part, i, levels, &connection.own.name, true);
+                let label = hdl(part, connection, &width);
+                let foreign = connection.foreign.name.as_str();
+                let sources: Vec<String> = if connection.foreign.is_constant() {
+                    let id = format!("{}:{}", path, foreign);
+                    self.line(&format!(
+                        "{} [label={}, shape=plaintext];",
+                        quote(&id),
+                        quote(foreign)
+                    ));
+                    vec![id]
+                } else if chip.pinlines.input.iter().any(|p| p.name == foreign)
+                {
+                    vec![input(path, foreign)]
+                } else {
+                    drivers
+                        .iter()
+                        .filter(|(c, _)| c.foreign.name == foreign)
+                        .map(|(_, from)| from.clone())
+                        .collect()
+                };
+                for from in sources {
+                    self.edge(&from, &to, &label);
+                }
+            }
+            for connection in &part.output_connections {
+                let foreign = connection.foreign.name.as_str();
+                if chip.pinlines.output.iter().any(|p| p.name == foreign) {
+                    let from = endpoint(
+                        path,
+                        part,
+                        i,
+                        levels,
+                        &connection.own.name,
+                        false,
+                    );
+                    self.edge(
+                        &from,
+                        &output(path, foreign),
+                        &hdl(part, connection, &width),
+                    );
+                }
+            }
+        }
+    }
+    fn edge(&mut self, from: &str, to: &str, label: &str) {
+        self.line(&format!(
+            "{} -> {} [label={}];",
+            quote(from),
+            quote(to),
+            quote(label)
+        ));
+    }
+}
+
+fn expanded(part: &Child, levels: usize) -> bool {
+    levels > 0 && !part.chip.is_builtin()
+}
+
+fn part_id(path: &str, part: &Child, i: usize) -> String {
+    format!("{}/{}_{}", path, part.chip.name, i)
+}
+
+fn input(path: &str, name: &str) -> String {
+    format!("{}:in:{}", path, name)
+}
+
+fn output(path: &str, name: &str) -> String {
+    format!("{}:out:{}", path, name)
+}
+
+/// The part's node, or its pin when the part is drawn with its insides
+fn endpoint(
+    path: &str,
+    part: &Child,
+    i: usize,
+    levels: usize,
+    pin: &str,
+    is_input: bool,
+) -> String {
+    let id = part_id(path, part, i);
+    match (expanded(part, levels), is_input) {
+        (false, _) => id,
+        (true, true) => input(&id, pin),
+        (true, false) => output(&id, pin),
+    }
+}
+
+/// A connection as written in HDL, `a[0..7]=x[8..15]`
+pub(crate) fn hdl(
+    part: &Child,
+    connection: &ChildConnection,
+    width: &dyn Fn(&str) -> usize,
+) -> String {
+    let own_width = part
+        .chip
+        .pinlines
+        .get_pinline(&connection.own.name)
+        .map_or(1, |pinline| pinline.pins.len());
+    let foreign = if connection.foreign.is_constant() {
+        connection.foreign.name.clone()
+    } else {
+        selection(&connection.foreign, width(&connection.foreign.name))
+    };
+    format!("{}={}", selection(&connection.own, own_width), foreign)
+}
+
+/// `name`, `name[i]` or `name[i..j]`, or a list of those when the bits are
+/// not in order
+pub(crate) fn selection(pins: &PinlineConnection, width: usize) -> String {
+    if pins.indices.iter().cloned().eq(0..width) {
+        return pins.name.clone();
+    }
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for i in &pins.indices {
+        match ranges.last_mut() {
+            Some((_, to)) if *to + 1 == *i => *to = *i,
+            _ => ranges.push((*i, *i)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(from, to)| match from == to {
+            true => format!("{}[{}]", pins.name, from),
+            false => format!("{}[{}..{}]", pins.name, from, to),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::load;
+
+    #[test]
+    fn parts_and_connections() {
+        let dot = load("Xor").to_dot(0);
+        assert!(dot.starts_with("digraph \"Xor\" {\n    rankdir=LR;\n"));
+        assert!(
+            dot.contains("    \"Xor:in:a\" [label=\"a\", shape=invhouse];\n")
+        );
+        assert!(dot.contains("    \"Xor/Or_0\" [label=\"Or\"];\n"));
+        assert!(dot
+            .contains("    \"Xor:in:b\" -> \"Xor/Nand_1\" [label=\"b=b\"];\n"));
+        assert!(dot.contains(
+            "    \"Xor/Or_0\" -> \"Xor/And_2\" [label=\"a=aorb\"];\n"
+        ));
+        assert!(dot.contains(
+            "    \"Xor/And_2\" -> \"Xor:out:out\" [label=\"out=out\"];\n"
+        ));
+        assert!(!dot.contains("subgraph"));
+    }
+    #[test]
+    fn ranges_and_constants() {
+        let dot = load("ALU").to_dot(0);
+        assert!(dot.contains(
+            "    \"ALU:false\" [label=\"false\", shape=plaintext];\n"
+        ));
+        assert!(dot.contains(
+            "    \"ALU:false\" -> \"ALU/Mux16_0\" [label=\"b=false\"];\n"
+        ));
+        assert!(dot.contains(
+            "    \"ALU/Mux16_10\" -> \"ALU:out:ng\" [label=\"out[15]=ng\"];\n"
+        ));
+        assert!(dot.contains(
+            "    \"ALU/Mux16_10\" -> \"ALU/Or8Way_11\" [label=\"in=low\"];\n"
+        ));
+    }
+    #[test]
+    fn levels() {
+        let dot = load("Bit").to_dot(1);
+        assert!(dot.contains(
+            "    \"Bit/DFF_1\" [label=\"DFF\", style=filled, fillcolor=lightblue];\n"
+        ));
+        // DFF is builtin, so only the Mux is drawn inside
+        assert!(dot.contains("subgraph \"cluster_Bit/Mux_0\""));
+        assert!(!dot.contains("subgraph \"cluster_Bit/DFF_1\""));
+
+        let dot = load("Xor").to_dot(1);
+        assert!(dot.contains(
+            "    subgraph \"cluster_Xor/Or_0\" {\n        label=\"Or\";\n"
+        ));
+        assert!(dot.contains("        \"Xor/Or_0/Not_0\" [label=\"Not\"];\n"));
+        assert!(dot.contains(
+            "    \"Xor:in:a\" -> \"Xor/Or_0:in:a\" [label=\"a=a\"];\n"
+        ));
+        assert!(dot.contains("    \"Xor/Or_0:out:out\" -> \"Xor/And_2:in:a\" [label=\"a=aorb\"];\n"));
+        assert!(load("Xor")
+            .to_dot(2)
+            .contains("subgraph \"cluster_Xor/And_2\""));
+    }
+    #[test]
+    fn selections() {
+        let pins = PinlineConnection::new("x", vec![0, 1, 2, 5, 7, 8]);
+        assert_eq!(selection(&pins, 16), "x[0..2],x[5],x[7..8]");
+        let pins = PinlineConnection::new("x", vec![0, 1]);
+        assert_eq!(selection(&pins, 2), "x");
+    }
+}
@@ -0,0 +1,680 @@
+use std::collections::HashMap;
+
+use crate::chip::Chip;
+use crate::netlist::{
+    Gate, Netlist, NetlistSimulator, ParallelSimulator, TRUE_NET,
+};
+use crate::sat::{Lit, Solver};
+
+/// Chips with at most this many input bits are checked by trying every input
+pub const EXHAUSTIVE_INPUT_BITS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    /// Every input combination, 64 at a time
+    Exhaustive,
+    /// Asks a SAT solver for inputs where any output differs
+    Sat,
+}
+
+/// Inputs on which two chips disagree, with what each of them outputs
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Counterexample {
+    pub inputs: Vec<(String, u64)>,
+    pub left: Vec<(String, u64)>,
+    pub right: Vec<(String, u64)>,
+}
+
+/// Inputs for each clock cycle from reset after which two chips' outputs
+/// differ, with what each of them outputs along the way
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Trace {
+    /// Names and widths
+    pub inputs: Vec<(String, usize)>,
+    pub outputs: Vec<(String, usize)>,
+    pub steps: Vec<TraceStep>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TraceStep {
+    pub inputs: Vec<u64>,
+    /// Outputs after the inputs are set, before the clock ticks
+    pub left: Vec<u64>,
+    pub right: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EquivalenceError {
+    Clocked(String),
+    /// A pin missing from one of the chips or with another width
+    InterfaceMismatch(String),
+    /// The name of a ROM32K, RAM16K, Screen or Keyboard part, which has no
+    /// gates to compare
+    Memory(String),
+}
+
+/// Builds CNF for netlists, sharing structurally identical gates
+pub(crate) struct Encoder {
+    pub solver: Solver,
+    true_lit: Lit,
+    gates: HashMap<(Lit, Lit, Option<Lit>), Lit>,
+}
+
+// ============================================================================
+
+/// Exhaustive for narrow inputs, SAT otherwise. `None` when the chips are
+/// equivalent.
+pub fn check(
+    left: &Chip,
+    right: &Chip,
+) -> Result<Option<Counterexample>, EquivalenceError> {
+    let width: usize = left.pinlines.input.iter().map(|p| p.pins.len()).sum();
+    let method = if width <= EXHAUSTIVE_INPUT_BITS {
+        Method::Exhaustive
+    } else {
+        Method::Sat
+    };
+    check_with(left, right, method)
+}
+
+pub fn check_with(
+    left: &Chip,
+    right: &Chip,
+    method: Method,
+) -> Result<Option<Counterexample>, EquivalenceError> {
+    for chip in &[left, right] {
+        if chip.clocked {
+            return Err(EquivalenceError::Clocked(chip.name.clone()));
+        }
+    }
+    gates_only(&[left, right])?;
+    let left = Netlist::compile(left);
+    let right = Netlist::compile(right);
+    same_interface(&left, &right)?;
+    let inputs = match method {
+        Method::Exhaustive => exhaustive(&left, &right),
+        Method::Sat => sat(&left, &right),
+    };
+    Ok(inputs.map(|inputs| counterexample(&left, &right, inputs)))
+}
+
+fn gates_only(chips: &[&Chip]) -> Result<(), EquivalenceError> {
+    match chips.iter().find_map(|chip| chip.memory_part()) {
+        Some(part) => Err(EquivalenceError::Memory(part.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// Same input and output names with the same widths, in any order
+pub(crate) fn same_interface(
+    left: &Netlist,
+    right: &Netlist,
+) -> Result<(), EquivalenceError> {
+    let pins = [
+        (&left.input, &right.input),
+        (&left.output, &right.output),
+        (&right.input, &left.input),
+        (&right.output, &left.output),
+    ];
+    for (ours, theirs) in pins.iter() {
+        for pinline in ours.iter() {
+            let other = theirs.iter().find(|p| p.name == pinline.name);
+            if other.map(|p| p.nets.len()) != Some(pinline.nets.len()) {
+                return Err(EquivalenceError::InterfaceMismatch(
+                    pinline.name.clone(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Input values where the outputs differ
+fn exhaustive(left: &Netlist, right: &Netlist) -> Option<Vec<u64>> {
+    let mut left_sim = ParallelSimulator::new(left.clone());
+    let mut right_sim = ParallelSimulator::new(right.clone());
+    let mut first = 0;
+    loop {
+        let count = left_sim.set_combinations(first);
+        if count == 0 {
+            return None;
+        }
+        let inputs: Vec<Vec<u64>> = left
+            .input
+            .iter()
+            .map(|p| left_sim.get_values(p.name.as_str()).unwrap())
+            .collect();
+        for (pinline, values) in left.input.iter().zip(inputs.iter()) {
+            let values: Vec<i64> = values.iter().map(|v| *v as i64).collect();
+            right_sim.set_input(pinline.name.as_str(), &values);
+        }
+        left_sim.evaluate();
+        right_sim.evaluate();
+        let lanes = if count == 64 { !0 } else { (1 << count) - 1 };
+        let mut differ = 0;
+        for pinline in &left.output {
+            let name = pinline.name.as_str();
+            let ours = left_sim.get_words(name).unwrap();
+            let theirs = right_sim.get_words(name).unwrap();
+            for (a, b) in ours.iter().zip(theirs.iter()) {
+                differ |= (a ^ b) & lanes;
+            }
+        }
+        if differ != 0 {
+            let lane = differ.trailing_zeros() as usize;
+            return Some(inputs.iter().map(|v| v[lane]).collect());
+        }
+        first += count as u64;
+    }
+}
+
+/// A miter: both chips on shared inputs, satisfiable when an output differs
+fn sat(left: &Netlist, right: &Netlist) -> Option<Vec<u64>> {
+    let mut encoder = Encoder::new();
+    let inputs = encoder.inputs(left);
+    let left_nets = encoder.encode(left, &inputs, &[]);
+    let right_nets = encoder.encode(right, &by_name(left, right, &inputs), &[]);
+    let differ = encoder.differ(left, right, &left_nets, &right_nets);
+    if !encoder.solver.solve_assuming(&[differ]) {
+        return None;
+    }
+    Some(inputs.iter().map(|lits| encoder.read(lits)).collect())
+}
+
+/// Shortest sequence of inputs from reset, at most `cycles` long, after
+/// which the outputs differ. Outputs are compared once the inputs are set
+/// and before the clock ticks, where `set ..., tick, output` shows them.
+pub fn check_sequential(
+    left: &Chip,
+    right: &Chip,
+    cycles: usize,
+) -> Result<Option<Trace>, EquivalenceError> {
+    gates_only(&[left, right])?;
+    let left = Netlist::compile(left);
+    let right = Netlist::compile(right);
+    same_interface(&left, &right)?;
+    let mut encoder = Encoder::new();
+    let mut left_state = vec![encoder.constant(false); left.dffs.len()];
+    let mut right_state = vec![encoder.constant(false); right.dffs.len()];
+    let mut steps: Vec<Vec<Vec<Lit>>> = Vec::new();
+    // One cycle deeper each time, so the first difference is the shortest
+    for _ in 0..cycles {
+        let inputs = encoder.inputs(&left);
+        let left_nets = encoder.encode(&left, &inputs, &left_state);
+        let right_inputs = by_name(&left, &right, &inputs);
+        let right_nets = encoder.encode(&right, &right_inputs, &right_state);
+        let differ = encoder.differ(&left, &right, &left_nets, &right_nets);
+        left_state = left.dffs.iter().map(|d| left_nets[d.input]).collect();
+        right_state = right.dffs.iter().map(|d| right_nets[d.input]).collect();
+        steps.push(inputs);
+        if encoder.solver.solve_assuming(&[differ]) {
+            let steps = steps
+                .iter()
+                .map(|step| {
+                    step.iter().map(|lits| encoder.read(lits)).collect()
+                })
+                .collect();
+            return Ok(Some(trace(&left, &right, steps)));
+        }
+    }
+    Ok(None)
+}
+
+/// Literals for `right`'s inputs, in its order, from those made for `left`
+fn by_name(
+    left: &Netlist,
+    right: &Netlist,
+    inputs: &[Vec<Lit>],
+) -> Vec<Vec<Lit>> {
+    right
+        .input
+        .iter()
+        .map(|p| {
+            let i = left.input.iter().position(|l| l.name == p.name);
+            inputs[i.unwrap()].clone()
+        })
+        .collect()
+}
+
+/// Runs both chips through the steps the way a test script would
+fn trace(left: &Netlist, right: &Netlist, steps: Vec<Vec<u64>>) -> Trace {
+    let pins = |pinlines: &[crate::netlist::NetPinline]| {
+        pinlines
+            .iter()
+            .map(|p| (p.name.clone(), p.nets.len()))
+            .collect::<Vec<_>>()
+    };
+    let mut left_sim = NetlistSimulator::new(left.clone());
+    let mut right_sim = NetlistSimulator::new(right.clone());
+    let mut trace = Trace {
+        inputs: pins(&left.input),
+        outputs: pins(&left.output),
+        steps: Vec::new(),
+    };
+    for inputs in steps {
+        let mut outputs = Vec::new();
+        for sim in [&mut left_sim, &mut right_sim].iter_mut() {
+            for (pinline, value) in left.input.iter().zip(inputs.iter()) {
+                sim.set_input(pinline.name.as_str(), *value as i64);
+            }
+            sim.tick();
+            outputs.push(
+                left.output
+                    .iter()
+                    .map(|p| sim.get_value(&p.name).unwrap())
+                    .collect::<Vec<_>>(),
+            );
+            sim.tock();
+        }
+        let right = outputs.pop().unwrap();
+        let left = outputs.pop().unwrap();
+        trace.steps.push(TraceStep {
+            inputs,
+            left,
+            right,
+        });
+    }
+    trace
+}
+
+/// Runs both chips on the inputs to report what they output
+fn counterexample(
+    left: &Netlist,
+    right: &Netlist,
+    inputs: Vec<u64>,
+) -> Counterexample {
+    let outputs = |netlist: &Netlist| {
+        let mut sim = NetlistSimulator::new(netlist.clone());
+        for (pinline, value) in left.input.iter().zip(inputs.iter()) {
+            sim.set_input(pinline.name.as_str(), *value as i64);
+        }
+        sim.evaluate();
+        left.output
+            .iter()
+            .map(|p| (p.name.clone(), sim.get_value(&p.name).unwrap()))
+            .collect()
+    };
+    Counterexample {
+        inputs: left
+            .input
+            .iter()
+            .map(|p| p.name.clone())
+            .zip(inputs.iter().cloned())
+            .collect(),
+        left: outputs(left),
+        right: outputs(right),
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        let mut solver = Solver::new();
+        let true_lit = Lit::new(solver.new_var(), true);
+        solver.add_clause(&[true_lit]);
+        Self {
+            solver,
+            true_lit,
+            gates: HashMap::new(),
+        }
+    }
+    pub fn new_lit(&mut self) -> Lit {
+        Lit::new(self.solver.new_var(), true)
+    }
+    pub fn constant(&self, value: bool) -> Lit {
+        if value {
+            self.true_lit
+        } else {
+            !self.true_lit
+        }
+    }
+    /// Literal per net, given literals for the input pins (in the netlist's
+    /// order, bit 0 first) and for the DFF outputs
+    pub fn encode(
+        &mut self,
+        netlist: &Netlist,
+        inputs: &[Vec<Lit>],
+        state: &[Lit],
+    ) -> Vec<Lit> {
+        // Nets nothing drives are false, as in the simulators
+        let mut nets: Vec<Option<Lit>> =
+            vec![Some(self.constant(false)); netlist.net_count];
+        for gate in &netlist.gates {
+            nets[gate.output()] = None;
+        }
+        nets[TRUE_NET] = Some(self.constant(true));
+        for (pinline, lits) in netlist.input.iter().zip(inputs.iter()) {
+            for (net, lit) in pinline.nets.iter().zip(lits.iter()) {
+                nets[*net] = Some(*lit);
+            }
+        }
+        for (dff, lit) in netlist.dffs.iter().zip(state.iter()) {
+            nets[dff.out] = Some(*lit);
+        }
+        for gate in &netlist.gates {
+            let (out, lit) = match *gate {
+                Gate::Nand { a, b, out } => {
+                    let a = self.net(&mut nets, a);
+                    let b = self.net(&mut nets, b);
+                    (out, self.nand(a, b))
+                }
+                Gate::Not { input, out } => (out, !self.net(&mut nets, input)),
+                Gate::Mux { a, b, sel, out } => {
+                    let a = self.net(&mut nets, a);
+                    let b = self.net(&mut nets, b);
+                    let sel = self.net(&mut nets, sel);
+                    (out, self.mux(a, b, sel))
+                }
+            };
+            match nets[out] {
+                // Driven early inside a loop, tie the two together
+                Some(early) => self.equal(early, lit),
+                None => nets[out] = Some(lit),
+            }
+        }
+        nets.into_iter()
+            .map(|n| n.unwrap_or_else(|| self.constant(false)))
+            .collect()
+    }
+    /// Nets in combinational loops are read before they are driven
+    fn net(&mut self, nets: &mut [Option<Lit>], net: usize) -> Lit {
+        match nets[net] {
+            Some(lit) => lit,
+            None => {
+                let lit = self.new_lit();
+                nets[net] = Some(lit);
+                lit
+            }
+        }
+    }
+    pub fn nand(&mut self, a: Lit, b: Lit) -> Lit {
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        let t = self.true_lit;
+        if a == !t || b == !t || a == !b {
+            return t;
+        }
+        if a == t {
+            return !b;
+        }
+        if b == t || a == b {
+            return !a;
+        }
+        if let Some(out) = self.gates.get(&(a, b, None)) {
+            return *out;
+        }
+        let out = self.new_lit();
+        self.solver.add_clause(&[out, a]);
+        self.solver.add_clause(&[out, b]);
+        self.solver.add_clause(&[!out, !a, !b]);
+        self.gates.insert((a, b, None), out);
+        out
+    }
+    pub fn mux(&mut self, a: Lit, b: Lit, sel: Lit) -> Lit {
+        let t = self.true_lit;
+        if sel == t || a == b {
+            return b;
+        }
+        if sel == !t {
+            return a;
+        }
+        if let Some(out) = self.gates.get(&(a, b, Some(sel))) {
+            return *out;
+        }
+        let out = self.new_lit();
+        self.solver.add_clause(&[!sel, !b, out]);
+        self.solver.add_clause(&[!sel, b, !out]);
+        self.solver.add_clause(&[sel, !a, out]);
+        self.solver.add_clause(&[sel, a, !out]);
+        self.gates.insert((a, b, Some(sel)), out);
+        out
+    }
+    pub fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        self.mux(a, !a, b)
+    }
+    pub fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        self.nand(!a, !b)
+    }
+    /// Fresh literals for every input bit
+    pub fn inputs(&mut self, netlist: &Netlist) -> Vec<Vec<Lit>> {
+        netlist
+            .input
+            .iter()
+            .map(|p| p.nets.iter().map(|_| self.new_lit()).collect())
+            .collect()
+    }
+    /// True when any output of `left` differs from the same one of `right`
+    pub fn differ(
+        &mut self,
+        left: &Netlist,
+        right: &Netlist,
+        left_nets: &[Lit],
+        right_nets: &[Lit],
+    ) -> Lit {
+        let mut differ = self.constant(false);
+        for pinline in &left.output {
+            let other = right.output.iter().find(|p| p.name == pinline.name);
+            for (a, b) in pinline.nets.iter().zip(other.unwrap().nets.iter()) {
+                let bit = self.xor(left_nets[*a], right_nets[*b]);
+                differ = self.or(differ, bit);
+            }
+        }
+        differ
+    }
+    pub fn equal(&mut self, a: Lit, b: Lit) {
+        self.solver.add_clause(&[!a, b]);
+        self.solver.add_clause(&[a, !b]);
+    }
+    /// Value of a number after solving, `lits[0]` is bit 0
+    pub fn read(&self, lits: &[Lit]) -> u64 {
+        lits.iter().enumerate().fold(0, |acc, (bit, lit)| {
+            let value = self.solver.value(lit.var()) == lit.is_positive();
+            acc | (value as u64) << bit
+        })
+    }
+}
+
+impl Trace {
+    /// Test script that replays the trace on `chip`, printing the outputs
+    /// after every tick and tock. The chips differ by the last tick's line
+    /// at the latest. `file` is the .hdl to load, as the script should name
+    /// it; builtin chips have none and the script says so instead.
+    pub fn to_tst(&self, chip: &str, file: Option<&str>) -> String {
+        let format = |width: usize| {
+            let pad = if width == 1 { 2 } else { 1 };
+            format!("%B{}.{}.{}", pad, width, pad)
+        };
+        let columns: Vec<String> = std::iter::once("time%S1.4.1".to_string())
+            .chain(
+                self.inputs
+                    .iter()
+                    .chain(self.outputs.iter())
+                    .map(|(name, width)| format!("{}{}", name, format(*width))),
+            )
+            .collect();
+        let load = match file {
+            Some(file) => format!("load {},\n", file),
+            None => {
+                format!("// {} is builtin, there is no file to load\n", chip)
+            }
+        };
+        let mut text = format!(
+            "// {} steps from reset until the chips' outputs differ\n\n\
+             {}output-file {}.out,\noutput-list {};\n\n",
+            self.steps.len(),
+            load,
+            chip,
+            columns.join(" ")
+        );
+        for step in &self.steps {
+            for ((name, width), value) in self.inputs.iter().zip(&step.inputs) {
+                text.push_str(&format!(
+                    "set {} %B{:0w$b}, ",
+                    name,
+                    value,
+                    w = width
+                ));
+            }
+            text.push_str("tick, output; tock, output;\n");
+        }
+        text
+    }
+}
+
+impl std::fmt::Display for EquivalenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EquivalenceError::Clocked(name) => {
+                write!(
+                    f,
+                    "{} is clocked, only combinational chips compare",
+                    name
+                )
+            }
+            EquivalenceError::InterfaceMismatch(pin) => {
+                write!(f, "pin {} is not the same in both chips", pin)
+            }
+            EquivalenceError::Memory(part) => {
+                write!(f, "chips with a {} don't compare", part)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EquivalenceError {}
+
+impl std::fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pins = |pins: &[(String, u64)]| {
+            pins.iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        write!(
+            f,
+            "{} gives {} and {}",
+            pins(&self.inputs),
+            pins(&self.left),
+            pins(&self.right)
+        )
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::BuiltinChips;
+    use crate::loader::fixtures::{load, load_str};
+
+    /// What the chips below need from test-hdl
+    const ADDERS: &[&str] =
+        &["Xor", "And", "Or", "HalfAdder", "FullAdder", "Add16"];
+
+    #[test]
+    fn mux_matches_builtin() {
+        let builtin = Chip::new_builtin(BuiltinChips::Mux);
+        for method in &[Method::Exhaustive, Method::Sat] {
+            assert_eq!(check_with(&load("Mux"), &builtin, *method), Ok(None));
+        }
+    }
+    #[test]
+    fn counterexample_found() {
+        let swapped = load_str(
+            "Swapped",
+            "CHIP Swapped { IN a, b, sel; OUT out;
+            PARTS: Mux(a=b, b=a, sel=sel, out=out); }",
+            ADDERS,
+        );
+        let builtin = Chip::new_builtin(BuiltinChips::Mux);
+        for method in &[Method::Exhaustive, Method::Sat] {
+            let found = check_with(&swapped, &builtin, *method).unwrap();
+            let found = found.unwrap();
+            let value = |name: &str| {
+                found.inputs.iter().find(|(n, _)| n == name).unwrap().1
+            };
+            assert_ne!(value("a"), value("b"));
+            assert_ne!(found.left, found.right);
+        }
+    }
+    #[test]
+    fn wide_buses() {
+        let increment = load("Inc16");
+        let adder = load_str(
+            "Plus1",
+            "CHIP Plus1 { IN in[16]; OUT out[16];
+            PARTS: Add16(a[0]=true, b=in, out=out); }",
+            ADDERS,
+        );
+        assert_eq!(check(&increment, &adder), Ok(None));
+        let wrong = load_str(
+            "Plus2",
+            "CHIP Plus2 { IN in[16]; OUT out[16];
+            PARTS: Add16(a[1]=true, b=in, out=out); }",
+            ADDERS,
+        );
+        let found = check_with(&increment, &wrong, Method::Sat).unwrap();
+        let found = found.unwrap();
+        let value = found.inputs[0].1;
+        assert_eq!(found.left[0].1, (value + 1) & 0xFFFF);
+        assert_eq!(found.right[0].1, (value + 2) & 0xFFFF);
+    }
+    #[test]
+    fn needs_same_interface() {
+        assert_eq!(
+            check(&load("And"), &Chip::new_builtin(BuiltinChips::Mux)),
+            Err(EquivalenceError::InterfaceMismatch("sel".to_string()))
+        );
+        assert_eq!(
+            check(&load("Bit"), &load("Bit")),
+            Err(EquivalenceError::Clocked("Bit".to_string()))
+        );
+        assert_eq!(
+            check_sequential(&load("Memory"), &load("Memory"), 1),
+            Err(EquivalenceError::Memory("RAM16K".to_string()))
+        );
+    }
+    #[test]
+    fn sequential() {
+        let builtin = Chip::new_builtin(BuiltinChips::Bit);
+        assert_eq!(check_sequential(&load("Bit"), &builtin, 6), Ok(None));
+        assert_eq!(check_sequential(&load("Mux"), &load("Mux"), 2), Ok(None));
+        // Has nets nothing drives, which must not be free to differ
+        assert_eq!(check_sequential(&load("PC"), &load("PC"), 3), Ok(None));
+
+        let lagging = load_str(
+            "Lagging",
+            "CHIP Lagging { IN in, load; OUT out;
+            PARTS: Bit(in=in, load=load, out=b);
+            Bit(in=b, load=true, out=out); }",
+            ADDERS,
+        );
+        let trace = check_sequential(&builtin, &lagging, 6).unwrap().unwrap();
+        assert_eq!(trace.steps.len(), 2);
+        assert_eq!(trace.steps[0].inputs, vec![1, 1]);
+        assert_eq!(trace.steps[1].left, vec![1]);
+        assert_eq!(trace.steps[1].right, vec![0]);
+
+        let script = trace.to_tst("Bit", Some("hdl/Bit.hdl"));
+        assert!(script.contains("\nload hdl/Bit.hdl,\n"));
+        assert!(script.contains("set in %B1, set load %B1, tick, output;"));
+        let builtin_script = trace.to_tst("Bit", None);
+        assert!(!builtin_script.lines().any(|l| l.starts_with("load")));
+        assert!(builtin_script.contains("// Bit is builtin"));
+        let body: Vec<&str> =
+            script.lines().filter(|l| !l.starts_with("load")).collect();
+        let run = |chip: Chip| {
+            crate::script::run_with_chip(&body.join("\n"), chip)
+                .unwrap()
+                .output
+        };
+        let (ours, theirs) = (run(builtin), run(lagging));
+        // Already apparent after the first tock
+        assert_eq!(ours[..2], theirs[..2]);
+        assert_eq!(ours[2], "| 1    |  1  |  1  |  1  |");
+        assert_eq!(theirs[2], "| 1    |  1  |  1  |  0  |");
+    }
+}
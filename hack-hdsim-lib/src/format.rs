@@ -0,0 +1,589 @@
+use crate::chip::{Chip, Pinline};
+use crate::dot::hdl;
+use crate::tokeniser::{Token, Tokeniser, UnexpectedToken};
+
+const MAX_WIDTH: usize = 80;
+const INDENT: &str = "    ";
+const CONTINUATION_INDENT: &str = "        ";
+
+/// Reprints a chip in the canonical layout, keeping comments.
+pub fn format_chip(contents: &str) -> Result<String, UnexpectedToken> {
+    let mut tokeniser = Tokeniser::with_comments(contents);
+    tokeniser.tokenise_chip()?;
+    let mut formatter = Formatter::new(tokeniser.tokens());
+    formatter.chip();
+    Ok(formatter.finish())
+}
+
+pub fn is_formatted(contents: &str) -> Result<bool, UnexpectedToken> {
+    Ok(format_chip(contents)? == contents)
+}
+
+impl Chip {
+    /// HDL for the chip, in the canonical layout. Meant for chips that
+    /// weren't read from HDL, like ones from BLIF, the parts' own chips are
+    /// left to their files.
+    pub fn to_hdl(&self) -> String {
+        let declare = |keyword: &str, pinlines: &[Pinline]| {
+            if pinlines.is_empty() {
+                return String::new();
+            }
+            let pins: Vec<String> = pinlines
+                .iter()
+                .map(|p| match p.pins.len() {
+                    1 => p.name.clone(),
+                    width => format!("{}[{}]", p.name, width),
+                })
+                .collect();
+            format!("{} {}; ", keyword, pins.join(", "))
+        };
+        let width = |name: &str| {
+            self.pinlines
+                .get_pinline(name)
+                .map_or(1, |pinline| pinline.pins.len())
+        };
+        let parts: String = self
+            .parts
+            .iter()
+            .map(|part| {
+                let connections: Vec<String> = part
+                    .input_connections
+                    .iter()
+                    .chain(part.output_connections.iter())
+                    .map(|connection| hdl(part, connection, &width))
+                    .collect();
+                format!("{}({});\n", part.chip.name, connections.join(", "))
+            })
+            .collect();
+        let text = format!(
+            "CHIP {} {{ {}{}PARTS:\n{}}}",
+            self.name,
+            declare("IN", &self.pinlines.input),
+            declare("OUT", &self.pinlines.output),
+            parts
+        );
+        format_chip(&text).expect("generated HDL tokenises")
+    }
+}
+
+/// Pieces of an identifier or assignment list in the order they appeared.
+enum Item {
+    Code(String),
+    Trailing(String),
+    OwnLine(String),
+}
+
+struct Formatter<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    lines: Vec<String>,
+    /// Declaration lines as (code, comment), comments are aligned across
+    /// `IN` and `OUT` before being written out.
+    rows: Vec<(String, Option<String>)>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            lines: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+    fn finish(mut self) -> String {
+        self.trim_blank_lines();
+        let mut out = self
+            .lines
+            .iter()
+            .map(|l| l.trim_end())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        out.push('\n');
+        out
+    }
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+    /// Next significant token, the tokeniser already checked the grammar
+    fn next(&mut self) -> &'a Token {
+        let token = &self.tokens[self.pos];
+        self.pos += 1;
+        token
+    }
+    fn comments(&mut self) -> Vec<&'a Token> {
+        let mut comments = Vec::new();
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Comment(_)
+                | Token::TrailingComment(_)
+                | Token::BlankLine => comments.push(self.next()),
+                _ => break,
+            }
+        }
+        comments
+    }
+    fn trim_blank_lines(&mut self) {
+        while let Some(true) = self.lines.last().map(|l| l.is_empty()) {
+            self.lines.pop();
+        }
+    }
+    fn push_comment(&mut self, indent: &str, text: &str, trailing: bool) {
+        let mut text_lines = text.lines();
+        let first = text_lines.next().unwrap_or("");
+        match self.lines.last_mut() {
+            Some(last) if trailing => {
+                last.push(' ');
+                last.push_str(first);
+            }
+            _ => self.lines.push(format!("{}{}", indent, first)),
+        }
+        // Block comment bodies are left as they are
+        for line in text_lines {
+            self.lines.push(line.to_string());
+        }
+    }
+    /// Comments between structural lines of the chip
+    fn block(&mut self, comments: Vec<&Token>, indent: &str, blanks: bool) {
+        for comment in comments {
+            match comment {
+                Token::Comment(text) => self.push_comment(indent, text, false),
+                Token::TrailingComment(text) => {
+                    self.push_comment(indent, text, true)
+                }
+                Token::BlankLine if blanks => {
+                    let after_opening = match self.lines.last() {
+                        Some(last) => {
+                            last.is_empty()
+                                || last.ends_with('{')
+                                || last.ends_with(':')
+                        }
+                        None => true,
+                    };
+                    if !after_opening {
+                        self.lines.push(String::new());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    fn chip(&mut self) {
+        let comments = self.comments();
+        self.block(comments, "", true);
+        self.next(); // CHIP
+        let mut pending = self.comments();
+        let name = self.identifier();
+        pending.append(&mut self.comments());
+        self.next(); // {
+        self.lines.push(format!("CHIP {} {{", name));
+        pending.append(&mut self.comments());
+        self.block(pending, INDENT, false);
+
+        self.declaration("IN");
+        let keyword = self.tokens[self.pos..]
+            .iter()
+            .find(|t| matches!(t, Token::Keyword(_)));
+        if let Some(Token::Keyword(keyword)) = keyword {
+            if keyword == "OUT" {
+                self.declaration("OUT");
+            }
+        }
+        self.flush_rows();
+        let comments = self.comments();
+        self.block(comments, INDENT, false);
+
+        self.next(); // PARTS
+        let mut pending = self.comments();
+        self.next(); // :
+        self.lines.push(String::new());
+        self.lines.push(format!("{}PARTS:", INDENT));
+        pending.append(&mut self.comments());
+        self.block(pending, INDENT, true);
+        while let Some(Token::Identifier(_)) = self.peek() {
+            self.part();
+            let comments = self.comments();
+            self.block(comments, INDENT, true);
+        }
+        self.next(); // }
+        self.trim_blank_lines();
+        self.lines.push("}".to_string());
+        let comments = self.comments();
+        self.block(comments, "", true);
+    }
+    fn identifier(&mut self) -> String {
+        match self.next() {
+            Token::Identifier(name) => name.clone(),
+            other => unreachable!("expected identifier, got {:?}", other),
+        }
+    }
+    /// Identifier with an optional `[i]` or `[i..j]` subscript
+    fn pin(&mut self, items: &mut Vec<Item>) -> String {
+        let mut pin = self.identifier();
+        if let Some(Token::Symbol('[')) = self.peek() {
+            // Comments inside subscripts are moved after the pin
+            let mut comments = Vec::new();
+            loop {
+                comments.append(&mut self.comments());
+                match self.next() {
+                    Token::Symbol(']') => {
+                        pin.push(']');
+                        break;
+                    }
+                    Token::Symbol(ch) => pin.push(*ch),
+                    Token::Number(n) => pin.push_str(&n.to_string()),
+                    Token::Range => pin.push_str(".."),
+                    other => {
+                        unreachable!("unexpected {:?} in subscript", other)
+                    }
+                }
+            }
+            for comment in comments {
+                Self::push_item(items, comment);
+            }
+        }
+        pin
+    }
+    fn push_item(items: &mut Vec<Item>, comment: &Token) {
+        match comment {
+            Token::Comment(text) => items.push(Item::OwnLine(text.clone())),
+            Token::TrailingComment(text) => {
+                items.push(Item::Trailing(text.clone()))
+            }
+            _ => {}
+        }
+    }
+    fn declaration(&mut self, keyword: &str) {
+        for comment in self.comments() {
+            match comment {
+                Token::Comment(text) => {
+                    self.rows.push((format!("{}{}", INDENT, text), None))
+                }
+                Token::TrailingComment(text) => match self.rows.last_mut() {
+                    Some(last) if last.1.is_none() => {
+                        last.1 = Some(text.clone())
+                    }
+                    Some(_) => {
+                        self.rows.push((String::new(), Some(text.clone())))
+                    }
+                    None => self.push_comment(INDENT, text, true),
+                },
+                _ => {}
+            }
+        }
+        self.next(); // keyword
+        let mut items = Vec::new();
+        let mut pins = Vec::new();
+        loop {
+            for comment in self.comments() {
+                Self::push_item(&mut items, comment);
+            }
+            if let Some(Token::Symbol(';')) = self.peek() {
+                break;
+            }
+            let pin = self.pin(&mut items);
+            pins.push(items.len());
+            items.push(Item::Code(pin));
+        }
+        self.next(); // ;
+
+        // Only a trailing comment and its continuation lines belong to us
+        if let Some(Token::TrailingComment(text)) = self.peek() {
+            self.next();
+            items.push(Item::Trailing(text.clone()));
+            while let Some(Token::Comment(text)) = self.peek() {
+                self.next();
+                items.push(Item::OwnLine(text.clone()));
+            }
+        }
+        // Separators go on the pins themselves
+        for (i, item_i) in pins.iter().enumerate() {
+            if let Item::Code(pin) = &mut items[*item_i] {
+                pin.push(if i == pins.len() - 1 { ';' } else { ',' });
+            }
+        }
+
+        let head = format!("{}{:<4}", INDENT, keyword);
+        if pins.is_empty() {
+            self.rows.push((format!("{}{};", INDENT, keyword), None));
+        } else if items.len() == pins.len() {
+            let items = items.into_iter().map(|item| match item {
+                Item::Code(pin) => pin,
+                _ => unreachable!(),
+            });
+            let lines = Self::wrap(head, items);
+            self.rows.extend(lines.into_iter().map(|l| (l, None)));
+        } else {
+            self.aligned_rows(head, items);
+        }
+    }
+    /// One pin per line with trailing comments lined up
+    fn aligned_rows(&mut self, head: String, items: Vec<Item>) {
+        // (code, comment)
+        let mut rows: Vec<(String, Option<String>)> = Vec::new();
+        let mut after_trailing = false;
+        for item in items {
+            match item {
+                Item::Code(pin) => {
+                    let prefix = if rows.iter().any(|r| !r.0.is_empty()) {
+                        CONTINUATION_INDENT
+                    } else {
+                        head.as_str()
+                    };
+                    rows.push((format!("{}{}", prefix, pin), None));
+                    after_trailing = false;
+                }
+                Item::Trailing(text) => {
+                    match rows.last_mut() {
+                        Some(last) if last.1.is_none() => last.1 = Some(text),
+                        Some(_) => rows.push((String::new(), Some(text))),
+                        None => self
+                            .rows
+                            .push((format!("{}{}", INDENT, text), None)),
+                    }
+                    after_trailing = !rows.is_empty();
+                }
+                Item::OwnLine(text) => {
+                    if after_trailing {
+                        rows.push((String::new(), Some(text)));
+                    } else if rows.is_empty() {
+                        self.rows.push((format!("{}{}", INDENT, text), None));
+                    } else {
+                        rows.push((
+                            format!("{}{}", CONTINUATION_INDENT, text),
+                            None,
+                        ));
+                    }
+                }
+            }
+        }
+        self.rows.append(&mut rows);
+    }
+    fn flush_rows(&mut self) {
+        let column = self
+            .rows
+            .iter()
+            .filter(|r| r.1.is_some() && !r.0.is_empty())
+            .map(|r| r.0.len())
+            .max()
+            .unwrap_or(0)
+            + 1;
+        for (code, comment) in std::mem::take(&mut self.rows) {
+            match comment {
+                Some(comment) => self.lines.push(format!(
+                    "{:<width$}{}",
+                    code,
+                    comment,
+                    width = column
+                )),
+                None => self.lines.push(code),
+            }
+        }
+    }
+    /// Fills lines up to `MAX_WIDTH`, continuing on indented lines
+    fn wrap(head: String, items: impl Iterator<Item = String>) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = head;
+        let mut fresh = true;
+        for item in items {
+            if fresh {
+                line.push_str(&item);
+            } else if line.len() + 1 + item.len() > MAX_WIDTH {
+                lines.push(line);
+                line = format!("{}{}", CONTINUATION_INDENT, item);
+            } else {
+                line.push(' ');
+                line.push_str(&item);
+            }
+            fresh = false;
+        }
+        lines.push(line);
+        lines
+    }
+    fn part(&mut self) {
+        let name = self.identifier();
+        let mut items = Vec::new();
+        for comment in self.comments() {
+            Self::push_item(&mut items, comment);
+        }
+        self.next(); // (
+        let mut assignments = Vec::new();
+        loop {
+            for comment in self.comments() {
+                Self::push_item(&mut items, comment);
+            }
+            if let Some(Token::Symbol(')')) = self.peek() {
+                break;
+            }
+            let own = self.pin(&mut items);
+            for comment in self.comments() {
+                Self::push_item(&mut items, comment);
+            }
+            self.next(); // =
+            for comment in self.comments() {
+                Self::push_item(&mut items, comment);
+            }
+            let foreign = self.pin(&mut items);
+            assignments.push(items.len());
+            items.push(Item::Code(format!("{}={}", own, foreign)));
+        }
+        self.next(); // )
+        for comment in self.comments() {
+            Self::push_item(&mut items, comment);
+        }
+        self.next(); // ;
+        if let Some(Token::TrailingComment(text)) = self.peek() {
+            self.next();
+            items.push(Item::Trailing(text.clone()));
+        }
+        for (i, item_i) in assignments.iter().enumerate() {
+            if let Item::Code(assignment) = &mut items[*item_i] {
+                let separator = if i == assignments.len() - 1 {
+                    ");"
+                } else {
+                    ","
+                };
+                assignment.push_str(separator);
+            }
+        }
+
+        let head = format!("{}{}(", INDENT, name);
+        if assignments.is_empty() {
+            self.lines.push(format!("{});", head));
+            for item in items {
+                if let Item::Trailing(text) | Item::OwnLine(text) = item {
+                    self.push_comment(INDENT, &text, true);
+                }
+            }
+            return;
+        }
+        let mut line = head;
+        let mut fresh = true;
+        for item in items {
+            match item {
+                Item::Code(assignment) => {
+                    if fresh {
+                        line.push_str(&assignment);
+                    } else if line.len() + 1 + assignment.len() > MAX_WIDTH {
+                        self.lines.push(line);
+                        line = format!("{}{}", CONTINUATION_INDENT, assignment);
+                    } else {
+                        line.push(' ');
+                        line.push_str(&assignment);
+                    }
+                    fresh = false;
+                }
+                Item::Trailing(text) => {
+                    line.push(' ');
+                    line.push_str(&text);
+                    self.lines.push(line);
+                    line = CONTINUATION_INDENT.to_string();
+                    fresh = true;
+                }
+                Item::OwnLine(text) => {
+                    if line.trim().is_empty() {
+                        line = CONTINUATION_INDENT.to_string();
+                    } else {
+                        self.lines.push(line);
+                        line = CONTINUATION_INDENT.to_string();
+                    }
+                    self.push_comment(CONTINUATION_INDENT, &text, false);
+                    fresh = true;
+                }
+            }
+        }
+        if !line.trim().is_empty() {
+            self.lines.push(line);
+        }
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn spacing_and_alignment() {
+        let contents = "CHIP And{IN a,b;OUT out;PARTS:
+\tNand(a = a,b=b , out=c);
+  Nand(a=c, b=c, out=out);}";
+        let formatted = "CHIP And {
+    IN  a, b;
+    OUT out;
+
+    PARTS:
+    Nand(a=a, b=b, out=c);
+    Nand(a=c, b=c, out=out);
+}
+";
+        assert_eq!(format_chip(contents).unwrap(), formatted);
+        assert!(is_formatted(formatted).unwrap());
+        assert!(!is_formatted(contents).unwrap());
+    }
+    #[test]
+    fn comments_kept() {
+        let contents = "// header
+
+CHIP Bit {
+    IN in, // data
+       load; // write enable
+             // (active high)
+    OUT out;
+    PARTS:
+    // feedback
+\tMux(b=in, a=checkout, sel=load, out=int); // select
+
+\tDFF(in=int, out=out, out=checkout);
+    /* block
+\tcomment */
+}";
+        let formatted = "// header
+
+CHIP Bit {
+    IN  in,   // data
+        load; // write enable
+              // (active high)
+    OUT out;
+
+    PARTS:
+    // feedback
+    Mux(b=in, a=checkout, sel=load, out=int); // select
+
+    DFF(in=int, out=out, out=checkout);
+    /* block
+\tcomment */
+}
+";
+        assert_eq!(format_chip(contents).unwrap(), formatted);
+        assert_eq!(format_chip(formatted).unwrap(), formatted);
+    }
+    #[test]
+    fn long_parts_wrapped() {
+        let contents = "CHIP CPU { IN x[16]; OUT out[16], pc[15]; PARTS:
+ALU(x=dout, y=aorm, out=outM, out=aluout, zx=instruction[11], nx=instruction[10], zy=instruction[9]);
+PC(in=aout, out[0..14]=pc);
+}";
+        let formatted = "CHIP CPU {
+    IN  x[16];
+    OUT out[16], pc[15];
+
+    PARTS:
+    ALU(x=dout, y=aorm, out=outM, out=aluout, zx=instruction[11],
+        nx=instruction[10], zy=instruction[9]);
+    PC(in=aout, out[0..14]=pc);
+}
+";
+        assert_eq!(format_chip(contents).unwrap(), formatted);
+    }
+    #[test]
+    fn test_hdl_idempotent() {
+        for file in &["And", "Bit", "CPU", "Mux"] {
+            let path = format!("../test-hdl/{}.hdl", file);
+            let contents = std::fs::read_to_string(path).unwrap();
+            let once = format_chip(contents.as_str()).unwrap();
+            let twice = format_chip(once.as_str()).unwrap();
+            assert_eq!(once, twice);
+        }
+    }
+}
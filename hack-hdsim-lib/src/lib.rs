@@ -1,2 +1,24 @@
+pub mod aiger;
+pub mod blif;
 pub mod chip;
+pub mod computer;
+pub mod dot;
+pub mod equivalence;
+pub mod format;
+pub mod lint;
+pub mod loader;
+pub mod logic;
+pub mod netlist;
+pub mod parser;
+pub mod property;
+pub mod random;
+pub mod reset;
+pub mod sat;
+pub mod script;
+pub mod snapshot;
+pub mod stats;
+pub mod svg;
 pub mod tokeniser;
+pub mod truth_table;
+pub mod verilog;
+pub mod vhdl;
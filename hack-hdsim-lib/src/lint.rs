@@ -0,0 +1,410 @@
+use crate::chip::{BuiltinChips, Chip};
+use crate::parser::ChipDecl;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum LintRule {
+    UnusedInternal,
+    UnassignedOutput,
+    UnreadInput,
+    UnusedPart,
+    ShadowedBuiltin,
+    FileName,
+    DoubleNegation,
+}
+
+/// Style and likely-mistake warnings, as opposed to `ParseError`s
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Warning {
+    pub rule: LintRule,
+    pub message: String,
+    #[cfg_attr(feature = "serde", serde(rename = "line"))]
+    pub nline: i32,
+    #[cfg_attr(feature = "serde", serde(rename = "char"))]
+    pub nchar: i32,
+}
+
+/// All rules are enabled by default
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    disabled: Vec<LintRule>,
+}
+
+// ============================================================================
+
+/// `chip` must have been built from `decl` by `ChipLoader`, so that parts
+/// line up. `file_stem` is the name of the file without `.hdl`.
+pub fn lint(
+    decl: &ChipDecl,
+    chip: &Chip,
+    file_stem: Option<&str>,
+    config: &LintConfig,
+) -> Vec<Warning> {
+    let mut linter = Linter {
+        decl,
+        chip,
+        config,
+        warnings: Vec::new(),
+    };
+    linter.pins();
+    linter.parts();
+    linter.names(file_stem);
+    linter
+        .warnings
+        .sort_by_key(|w| (w.nline, w.nchar, w.rule.name()));
+    linter.warnings
+}
+
+impl LintRule {
+    pub const ALL: &'static [LintRule] = &[
+        LintRule::UnusedInternal,
+        LintRule::UnassignedOutput,
+        LintRule::UnreadInput,
+        LintRule::UnusedPart,
+        LintRule::ShadowedBuiltin,
+        LintRule::FileName,
+        LintRule::DoubleNegation,
+    ];
+    pub fn name(&self) -> &'static str {
+        use LintRule::*;
+        match self {
+            UnusedInternal => "unused-internal",
+            UnassignedOutput => "unassigned-output",
+            UnreadInput => "unread-input",
+            UnusedPart => "unused-part",
+            ShadowedBuiltin => "shadowed-builtin",
+            FileName => "file-name",
+            DoubleNegation => "double-negation",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().find(|r| r.name() == name).copied()
+    }
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} [{}]",
+            self.nline,
+            self.nchar,
+            self.message,
+            self.rule.name()
+        )
+    }
+}
+
+impl LintConfig {
+    pub fn enable(&mut self, rule: LintRule) {
+        self.disabled.retain(|r| *r != rule);
+    }
+    pub fn disable(&mut self, rule: LintRule) {
+        if !self.disabled.contains(&rule) {
+            self.disabled.push(rule);
+        }
+    }
+    pub fn is_enabled(&self, rule: LintRule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+}
+
+struct Linter<'a> {
+    decl: &'a ChipDecl,
+    chip: &'a Chip,
+    config: &'a LintConfig,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, rule: LintRule, message: String, position: (i32, i32)) {
+        if self.config.is_enabled(rule) {
+            self.warnings.push(Warning {
+                rule,
+                message,
+                nline: position.0,
+                nchar: position.1,
+            });
+        }
+    }
+    /// Read by at least one part
+    fn is_read(&self, name: &str) -> bool {
+        self.chip.parts.iter().any(|part| {
+            part.input_connections
+                .iter()
+                .any(|c| c.foreign.name == name)
+        })
+    }
+    /// Where a pin that isn't declared first appears in the parts
+    fn first_use(&self, name: &str) -> (i32, i32) {
+        self.decl
+            .parts
+            .iter()
+            .flat_map(|p| p.connections.iter())
+            .find(|c| c.foreign.name == name)
+            .map(|c| (c.foreign.nline, c.foreign.nchar))
+            .unwrap_or((self.decl.nline, self.decl.nchar))
+    }
+    fn pins(&mut self) {
+        for (pinline, decl) in
+            self.chip.pinlines.input.iter().zip(self.decl.input.iter())
+        {
+            if !self.is_read(pinline.name.as_str()) {
+                self.warn(
+                    LintRule::UnreadInput,
+                    format!("input pin {} is never read", pinline.name),
+                    (decl.nline, decl.nchar),
+                );
+            }
+        }
+        for (pinline, decl) in self
+            .chip
+            .pinlines
+            .output
+            .iter()
+            .zip(self.decl.output.iter())
+        {
+            let mut assigned = vec![false; pinline.pins.len()];
+            for connection in self
+                .chip
+                .parts
+                .iter()
+                .flat_map(|p| p.output_connections.iter())
+                .filter(|c| c.foreign.name == pinline.name)
+            {
+                for i in &connection.foreign.indices {
+                    assigned[*i] = true;
+                }
+            }
+            let unassigned: Vec<String> = assigned
+                .iter()
+                .enumerate()
+                .filter(|(_, a)| !**a)
+                .map(|(i, _)| i.to_string())
+                .collect();
+            let message = if unassigned.len() == assigned.len() {
+                format!("output pin {} is never assigned", pinline.name)
+            } else if !unassigned.is_empty() {
+                format!(
+                    "bits {} of output pin {} are never assigned",
+                    unassigned.join(", "),
+                    pinline.name
+                )
+            } else {
+                continue;
+            };
+            self.warn(
+                LintRule::UnassignedOutput,
+                message,
+                (decl.nline, decl.nchar),
+            );
+        }
+        for pinline in &self.chip.pinlines.internal {
+            if !self.is_read(pinline.name.as_str()) {
+                self.warn(
+                    LintRule::UnusedInternal,
+                    format!("internal pin {} is never read", pinline.name),
+                    self.first_use(pinline.name.as_str()),
+                );
+            }
+        }
+    }
+    fn parts(&mut self) {
+        for (i, (part, decl)) in self
+            .chip
+            .parts
+            .iter()
+            .zip(self.decl.parts.iter())
+            .enumerate()
+        {
+            let position = (decl.nline, decl.nchar);
+            let used = part.output_connections.iter().any(|c| {
+                self.chip
+                    .pinlines
+                    .output
+                    .iter()
+                    .any(|p| p.name == c.foreign.name)
+                    || self.is_read(c.foreign.name.as_str())
+            });
+            if !used {
+                self.warn(
+                    LintRule::UnusedPart,
+                    format!("outputs of part {} are never used", decl.name),
+                    position,
+                );
+            }
+            if let Some(source) = self.negated_by(i) {
+                self.warn(
+                    LintRule::DoubleNegation,
+                    format!(
+                        "part {} negates the output of another {}",
+                        decl.name, source
+                    ),
+                    position,
+                );
+            }
+        }
+    }
+    /// A `Not` (or `Not16`) whose whole input is a pin another one writes.
+    /// Parts can't subscript internal pins and only one part drives each,
+    /// so reading all of it means reading the bits that part negated.
+    fn negated_by(&self, part_i: usize) -> Option<&'a str> {
+        let part = &self.chip.parts[part_i];
+        let name = part.chip.name.as_str();
+        if name != "Not" && name != "Not16" {
+            return None;
+        }
+        let input = part.input_connections.first()?;
+        if input.own.indices.len() != part.chip.pinlines.input[0].pins.len() {
+            return None;
+        }
+        self.chip
+            .parts
+            .iter()
+            .enumerate()
+            .filter(|(j, p)| *j != part_i && p.chip.name == name)
+            .flat_map(|(_, p)| p.output_connections.iter())
+            .find(|c| c.foreign.name == input.foreign.name)
+            .map(|_| name)
+    }
+    fn names(&mut self, file_stem: Option<&str>) {
+        let position = (self.decl.nline, self.decl.nchar);
+        let name = self.chip.name.as_str();
+        if BuiltinChips::from_name(name).is_some() {
+            self.warn(
+                LintRule::ShadowedBuiltin,
+                format!(
+                    "chip {} shadows the builtin chip of the same name",
+                    name
+                ),
+                position,
+            );
+        }
+        if let Some(stem) = file_stem {
+            if stem != name {
+                self.warn(
+                    LintRule::FileName,
+                    format!("chip {} is in a file named {}.hdl", name, stem),
+                    position,
+                );
+            }
+        }
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::ChipLoader;
+    use crate::parser::parse_chip;
+
+    fn lint_str(contents: &str, config: &LintConfig) -> Vec<Warning> {
+        let decl = parse_chip(contents).unwrap();
+        let chip = ChipLoader::new(None).build(&decl).unwrap();
+        lint(&decl, &chip, Some("Test"), config)
+    }
+    fn rules(warnings: &[Warning]) -> Vec<LintRule> {
+        warnings.iter().map(|w| w.rule).collect()
+    }
+
+    #[test]
+    fn clean_chip() {
+        let contents = "CHIP Test { IN a, b; OUT out; PARTS:
+            Nand(a=a, b=b, out=c);
+            Not(in=c, out=out); }";
+        assert!(lint_str(contents, &LintConfig::default()).is_empty());
+    }
+    #[test]
+    fn pins() {
+        let contents = "CHIP Test { IN a, b; OUT out[2], x; PARTS:
+            Nand(a=a, b=a, out=out[0], out=c); }";
+        let warnings = lint_str(contents, &LintConfig::default());
+        assert_eq!(
+            rules(&warnings),
+            vec![
+                LintRule::UnreadInput,
+                LintRule::UnassignedOutput,
+                LintRule::UnassignedOutput,
+                LintRule::UnusedInternal
+            ]
+        );
+        assert_eq!(
+            warnings[1].message,
+            "bits 1 of output pin out are never assigned"
+        );
+        assert_eq!(warnings[2].message, "output pin x is never assigned");
+        assert_eq!((warnings[3].nline, warnings[3].nchar), (2, 44));
+    }
+    #[test]
+    fn parts() {
+        let contents = "CHIP Test { IN a; OUT out; PARTS:
+            Not(in=a, out=na);
+            Not(in=na, out=out);
+            Not(in=a, out=unused); }";
+        let warnings = lint_str(contents, &LintConfig::default());
+        assert_eq!(
+            rules(&warnings),
+            vec![
+                LintRule::DoubleNegation,
+                LintRule::UnusedPart,
+                LintRule::UnusedInternal
+            ]
+        );
+        assert_eq!(warnings[0].nline, 3);
+        assert_eq!(
+            warnings[1].to_string(),
+            "4:13: outputs of part Not are never used [unused-part]"
+        );
+
+        // A Not that reads its own output isn't negating another one
+        let contents = "CHIP Test { IN a; OUT out; PARTS:
+            Not(in=x, out=x);
+            Not(in=a, out=out); }";
+        let warnings = lint_str(contents, &LintConfig::default());
+        assert!(!rules(&warnings).contains(&LintRule::DoubleNegation));
+
+        // Only half of the second Not16's bits were negated by the first
+        let lint_not16 = |contents| {
+            let decl = parse_chip(contents).unwrap();
+            let dir = std::path::Path::new("../test-hdl");
+            let chip = ChipLoader::new(Some(dir)).build(&decl).unwrap();
+            lint(&decl, &chip, Some("Test"), &LintConfig::default())
+        };
+        let warnings = lint_not16(
+            "CHIP Test { IN a[16]; OUT c[16]; PARTS:
+            Not16(in=a, out=w);
+            Not16(in=w, out=c); }",
+        );
+        assert!(rules(&warnings).contains(&LintRule::DoubleNegation));
+        let warnings = lint_not16(
+            "CHIP Test { IN a[16]; OUT c[16]; PARTS:
+            Not16(in=a, out[0..7]=w);
+            Not16(in[0..7]=w, in[8..15]=w, out=c); }",
+        );
+        assert!(!rules(&warnings).contains(&LintRule::DoubleNegation));
+    }
+    #[test]
+    fn names_and_config() {
+        let contents = "CHIP Nand { IN a; OUT out; PARTS:
+            Not(in=a, out=out); }";
+        let mut config = LintConfig::default();
+        let warnings = lint_str(contents, &config);
+        assert_eq!(
+            rules(&warnings),
+            vec![LintRule::FileName, LintRule::ShadowedBuiltin]
+        );
+        config.disable(LintRule::FileName);
+        let warnings = lint_str(contents, &config);
+        assert_eq!(rules(&warnings), vec![LintRule::ShadowedBuiltin]);
+        config.enable(LintRule::FileName);
+        assert!(config.is_enabled(LintRule::FileName));
+        assert_eq!(
+            LintRule::from_name("double-negation"),
+            Some(LintRule::DoubleNegation)
+        );
+    }
+}
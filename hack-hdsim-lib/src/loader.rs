@@ -0,0 +1,590 @@
+use crate::blif::BlifError;
+use crate::chip::{
+    BuiltinChips, Child, ChildConnection, Chip, Pinline, PinlineConnection,
+    PinlinesMethods,
+};
+use crate::parser::{
+    parse_chip, ChipDecl, ParseError, ParseErrorKind, PartDecl, PinDecl, PinRef,
+};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Builds chips from their declarations. Parts are looked up as `Name.hdl`
+/// in the chip's directory first, like the Java simulator does, and fall
+/// back to builtins.
+pub struct ChipLoader {
+    dir: Option<PathBuf>,
+    decls: HashMap<String, Option<Rc<ChipDecl>>>,
+    /// Chips currently being built, to catch chips that contain themselves
+    loading: Vec<String>,
+}
+
+/// Pins of the chip being built that parts have written to or read from
+struct PinUsage {
+    written: HashMap<String, Vec<bool>>,
+    internal_widths: HashMap<String, usize>,
+    internal_reads: Vec<(PinRef, usize)>,
+}
+
+/// Why `ChipLoader::load_file` failed. Serialised like `ParseError`, a BLIF
+/// error with kind `blif` and its message as the detail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    Parse(ParseError),
+    Blif { file: PathBuf, error: BlifError },
+}
+
+impl ChipLoader {
+    pub fn new(dir: Option<&Path>) -> Self {
+        Self {
+            dir: dir.map(|d| d.to_path_buf()),
+            decls: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+    /// Loader that looks for parts next to `path`
+    pub fn for_file(path: &Path) -> Self {
+        Self::new(path.parent())
+    }
+    /// Chip from an HDL file, or a BLIF netlist when the file ends in `.blif`
+    pub fn load_file(&mut self, path: &Path) -> Result<Chip, LoadError> {
+        if path.extension().is_some_and(|e| e == "blif") {
+            return load_blif(path);
+        }
+        self.parse_file(path)
+            .and_then(|decl| self.build(&decl))
+            .map_err(|e| LoadError::Parse(with_file(e, path)))
+    }
+    pub fn parse_file(&self, path: &Path) -> Result<ChipDecl, ParseError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            ParseError::new(ParseErrorKind::ReadFailed(e.to_string()), 0, 0)
+        })?;
+        parse_chip(contents.as_str()).map_err(|e| with_file(e, path))
+    }
+    /// Chip by name, from the loader's directory or a builtin
+    pub fn load(&mut self, name: &str) -> Result<Chip, ParseError> {
+        self.instantiate(name, 0, 0)
+    }
+    pub fn build(&mut self, decl: &ChipDecl) -> Result<Chip, ParseError> {
+        if decl.parts.is_empty() {
+            return Err(ParseError::new(
+                ParseErrorKind::NoParts(decl.name.clone()),
+                decl.nline,
+                decl.nchar,
+            ));
+        }
+        self.loading.push(decl.name.clone());
+        let parts = self.build_parts(decl);
+        self.loading.pop();
+        let parts = parts?;
+
+        let pinlines = |pins: &[PinDecl]| {
+            pins.iter()
+                .map(|p| Pinline::with_capacity(p.name.as_str(), p.width))
+                .collect()
+        };
+        Ok(Chip::new_custom(
+            decl.name.as_str(),
+            pinlines(&decl.input),
+            pinlines(&decl.output),
+            parts,
+        ))
+    }
+    fn build_parts(
+        &mut self,
+        decl: &ChipDecl,
+    ) -> Result<Vec<Child>, ParseError> {
+        let mut usage = PinUsage {
+            written: HashMap::new(),
+            internal_widths: HashMap::new(),
+            internal_reads: Vec::new(),
+        };
+        let mut parts = Vec::with_capacity(decl.parts.len());
+        for part in &decl.parts {
+            let chip = self.instantiate(&part.name, part.nline, part.nchar)?;
+            let connections = connect(decl, part, &chip, &mut usage)?;
+            parts.push(Child::new(chip, connections));
+        }
+        for (pin, width) in &usage.internal_reads {
+            match usage.internal_widths.get(&pin.name) {
+                None => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::Undriven(pin.name.clone()),
+                        pin.nline,
+                        pin.nchar,
+                    ))
+                }
+                Some(expected) if expected != width => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::WidthMismatch {
+                            pin: pin.name.clone(),
+                            expected: *expected,
+                            got: *width,
+                        },
+                        pin.nline,
+                        pin.nchar,
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(parts)
+    }
+    fn instantiate(
+        &mut self,
+        name: &str,
+        nline: i32,
+        nchar: i32,
+    ) -> Result<Chip, ParseError> {
+        if self.loading.iter().any(|l| l == name) {
+            return Err(ParseError::new(
+                ParseErrorKind::Recursive(name.to_string()),
+                nline,
+                nchar,
+            ));
+        }
+        if let Some(decl) = self.find_decl(name)? {
+            let path = self.decl_path(name).unwrap();
+            return self.build(&decl).map_err(|e| with_file(e, &path));
+        }
+        match BuiltinChips::from_name(name) {
+            Some(id) => Ok(Chip::new_builtin(id)),
+            None => Err(ParseError::new(
+                ParseErrorKind::UnknownChip(name.to_string()),
+                nline,
+                nchar,
+            )),
+        }
+    }
+    fn decl_path(&self, name: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|d| d.join(format!("{}.hdl", name)))
+    }
+    fn find_decl(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<Rc<ChipDecl>>, ParseError> {
+        if let Some(decl) = self.decls.get(name) {
+            return Ok(decl.clone());
+        }
+        let decl = match self.decl_path(name) {
+            Some(path) if path.is_file() => {
+                Some(Rc::new(self.parse_file(path.as_path())?))
+            }
+            _ => None,
+        };
+        self.decls.insert(name.to_string(), decl.clone());
+        Ok(decl)
+    }
+}
+
+impl From<ParseError> for LoadError {
+    fn from(e: ParseError) -> Self {
+        LoadError::Parse(e)
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Parse(e) => write!(f, "{}", e),
+            LoadError::Blif { file, error } => write!(
+                f,
+                "{}:{}:0: {}",
+                file.display(),
+                error.line(),
+                error.message()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for LoadError {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        match self {
+            LoadError::Parse(e) => e.serialize(serializer),
+            LoadError::Blif { file, error } => {
+                let mut map = serializer.serialize_map(Some(5))?;
+                map.serialize_entry("file", file)?;
+                map.serialize_entry("line", &error.line())?;
+                map.serialize_entry("char", &0)?;
+                map.serialize_entry("kind", "blif")?;
+                map.serialize_entry("detail", &error.message())?;
+                map.end()
+            }
+        }
+    }
+}
+
+fn load_blif(path: &Path) -> Result<Chip, LoadError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        let kind = ParseErrorKind::ReadFailed(e.to_string());
+        with_file(ParseError::new(kind, 0, 0), path)
+    })?;
+    Chip::from_blif(&contents).map_err(|error| LoadError::Blif {
+        file: path.to_path_buf(),
+        error,
+    })
+}
+
+fn with_file(mut e: ParseError, path: &Path) -> ParseError {
+    if e.file.is_none() {
+        e.file = Some(path.to_path_buf());
+    }
+    e
+}
+
+/// Resolves the `own=foreign` connections of one part
+fn connect(
+    decl: &ChipDecl,
+    part: &PartDecl,
+    chip: &Chip,
+    usage: &mut PinUsage,
+) -> Result<Vec<ChildConnection>, ParseError> {
+    let mut connections = Vec::with_capacity(part.connections.len());
+    for connection in &part.connections {
+        let own = &connection.own;
+        let foreign = &connection.foreign;
+        let err = |kind: ParseErrorKind, pin: &PinRef| {
+            Err(ParseError::new(kind, pin.nline, pin.nchar))
+        };
+        let own_input = chip.pinlines.input.get_pinline(own.name.as_str());
+        let own_output = chip.pinlines.output.get_pinline(own.name.as_str());
+        let own_width = match own_input.or(own_output) {
+            Some(pinline) => pinline.pins.len(),
+            None => {
+                return err(
+                    ParseErrorKind::UnknownPin {
+                        chip: part.name.clone(),
+                        pin: own.name.clone(),
+                    },
+                    own,
+                )
+            }
+        };
+        if !range_fits(own, own_width) {
+            return err(ParseErrorKind::BadSubscript(own.to_string()), own);
+        }
+        let own_indices = own.indices(own_width);
+        let width = own_indices.len();
+        let width_mismatch = |got: usize| {
+            err(
+                ParseErrorKind::WidthMismatch {
+                    pin: foreign.to_string(),
+                    expected: width,
+                    got,
+                },
+                foreign,
+            )
+        };
+
+        let foreign_indices: Vec<usize>;
+        if own_input.is_some() {
+            if foreign.is_constant() {
+                if foreign.range.is_some() {
+                    return err(
+                        ParseErrorKind::BadSubscript(foreign.to_string()),
+                        foreign,
+                    );
+                }
+                foreign_indices = (0..width).collect();
+            } else if let Some(pin) = decl.get_input(foreign.name.as_str()) {
+                if !range_fits(foreign, pin.width) {
+                    return err(
+                        ParseErrorKind::BadSubscript(foreign.to_string()),
+                        foreign,
+                    );
+                }
+                foreign_indices = foreign.indices(pin.width);
+                if foreign_indices.len() != width {
+                    return width_mismatch(foreign_indices.len());
+                }
+            } else if decl.get_output(foreign.name.as_str()).is_some() {
+                return err(
+                    ParseErrorKind::ReadsOutput(foreign.name.clone()),
+                    foreign,
+                );
+            } else {
+                if foreign.range.is_some() {
+                    return err(
+                        ParseErrorKind::BadSubscript(foreign.to_string()),
+                        foreign,
+                    );
+                }
+                usage.internal_reads.push((foreign.clone(), width));
+                foreign_indices = (0..width).collect();
+            }
+        } else if foreign.is_constant()
+            || decl.get_input(foreign.name.as_str()).is_some()
+        {
+            return err(
+                ParseErrorKind::WritesInput(foreign.name.clone()),
+                foreign,
+            );
+        } else {
+            let pin_width = match decl.get_output(foreign.name.as_str()) {
+                Some(pin) => {
+                    if !range_fits(foreign, pin.width) {
+                        return err(
+                            ParseErrorKind::BadSubscript(foreign.to_string()),
+                            foreign,
+                        );
+                    }
+                    pin.width
+                }
+                None => {
+                    if foreign.range.is_some() {
+                        return err(
+                            ParseErrorKind::BadSubscript(foreign.to_string()),
+                            foreign,
+                        );
+                    }
+                    let known = usage
+                        .internal_widths
+                        .entry(foreign.name.clone())
+                        .or_insert(width);
+                    if *known != width {
+                        return width_mismatch(*known);
+                    }
+                    width
+                }
+            };
+            foreign_indices = foreign.indices(pin_width);
+            if foreign_indices.len() != width {
+                return width_mismatch(foreign_indices.len());
+            }
+            let written = usage
+                .written
+                .entry(foreign.name.clone())
+                .or_insert_with(|| vec![false; pin_width]);
+            for i in &foreign_indices {
+                if written[*i] {
+                    return err(
+                        ParseErrorKind::MultipleWriters(foreign.to_string()),
+                        foreign,
+                    );
+                }
+                written[*i] = true;
+            }
+        }
+        connections.push(ChildConnection::new(
+            PinlineConnection::new(own.name.as_str(), own_indices),
+            PinlineConnection::new(foreign.name.as_str(), foreign_indices),
+        ));
+    }
+    Ok(connections)
+}
+
+fn range_fits(pin: &PinRef, width: usize) -> bool {
+    match pin.range {
+        Some((from, to)) => from <= to && to < width,
+        None => true,
+    }
+}
+
+// ============================================================================
+
+/// Chips for the tests of every module
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::ChipLoader;
+    use crate::chip::Chip;
+    use crate::netlist::{Netlist, NetlistSimulator};
+    use crate::random::Random;
+    use std::path::PathBuf;
+
+    /// A chip from the test-hdl directory
+    pub(crate) fn load(name: &str) -> Chip {
+        let path = PathBuf::from(format!("../test-hdl/{}.hdl", name));
+        ChipLoader::for_file(&path).load_file(&path).unwrap()
+    }
+
+    /// A directory of its own under the system temp dir, tests run in
+    /// parallel and would otherwise write over each other's files
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hack-hdsim-{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Loads `hdl` as chip `name` next to copies of the test-hdl chips in
+    /// `parts`, anything else it uses is a builtin
+    pub(crate) fn load_str(name: &str, hdl: &str, parts: &[&str]) -> Chip {
+        let dir = temp_dir(&format!("chip-{}", name));
+        for part in parts {
+            let file = format!("{}.hdl", part);
+            std::fs::copy(format!("../test-hdl/{}", file), dir.join(&file))
+                .unwrap();
+        }
+        let path = dir.join(format!("{}.hdl", name));
+        std::fs::write(&path, hdl).unwrap();
+        ChipLoader::for_file(&path).load_file(&path).unwrap()
+    }
+
+    /// Drives a chip and a netlist of it, compiled or read back from a
+    /// file, with the same pseudo-random inputs and compares their outputs
+    /// after every evaluate, tick and tock
+    pub(crate) fn same_behaviour(mut chip: Chip, netlist: Netlist) {
+        let mut sim = NetlistSimulator::new(netlist);
+        let mut random = Random::new(12345);
+        for step in 0..200 {
+            for pinline in &mut chip.pinlines.input {
+                let value = random.next_u64() as i64;
+                pinline.set_value(value as u64);
+                sim.set_input(pinline.name.as_str(), value).unwrap();
+            }
+            match step % 3 {
+                0 => {
+                    chip.evaluate();
+                    sim.evaluate();
+                }
+                1 => {
+                    chip.tick();
+                    sim.tick();
+                }
+                _ => {
+                    chip.tock();
+                    sim.tock();
+                }
+            }
+            for pinline in &chip.pinlines.output {
+                assert_eq!(
+                    sim.get_pinline(pinline.name.as_str()).as_ref(),
+                    Some(pinline),
+                    "{} at step {}",
+                    chip.name,
+                    step
+                );
+            }
+        }
+    }
+
+    /// Sets the given inputs, runs one clock cycle and returns the first
+    /// output
+    pub(crate) fn cycle(chip: &mut Chip, inputs: &[(&str, u64)]) -> u64 {
+        for (name, value) in inputs {
+            let pinline =
+                chip.pinlines.input.iter_mut().find(|p| p.name == *name);
+            pinline.unwrap().set_value(*value);
+        }
+        chip.tick();
+        chip.tock();
+        chip.pinlines.output[0].get_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn load_str(contents: &str) -> Result<Chip, ParseError> {
+        let decl = parse_chip(contents)?;
+        ChipLoader::new(None).build(&decl)
+    }
+    #[test]
+    fn and_from_file() {
+        let mut loader = ChipLoader::for_file(Path::new("../test-hdl/And.hdl"));
+        let and = loader.load_file(Path::new("../test-hdl/And.hdl")).unwrap();
+        assert_eq!(and.name, "And");
+        assert_eq!(and.parts.len(), 2);
+        assert_eq!(and.pinlines.internal, vec![Pinline::with_capacity("c", 1)]);
+        assert_eq!(
+            and.parts[1].input_connections[0].foreign.name.as_str(),
+            "c"
+        );
+    }
+    #[test]
+    fn parts_from_directory() {
+        let mut loader = ChipLoader::new(Some(Path::new("../test-hdl")));
+        let bit = loader.load("Bit").unwrap();
+        assert!(bit.clocked);
+        assert_eq!(bit.parts[0].chip.name, "Mux");
+        // Mux.hdl is used over the builtin
+        assert!(!bit.parts[0].chip.is_builtin());
+        let nand = loader.load("Nand").unwrap();
+        assert!(nand.is_builtin());
+    }
+    #[test]
+    fn subscripts_and_constants() {
+        let chip = load_str(
+            "CHIP C { IN x[4]; OUT y[2], z;
+            PARTS:
+            Mux(a=x[1], b=true, sel=x[3], out=y[0], out=y[1]);
+            Not(in=false, out=z); }",
+        )
+        .unwrap();
+        assert!(chip.pinlines.internal.is_empty());
+        let connections = &chip.parts[0].input_connections;
+        assert_eq!(connections[0].foreign.indices, vec![1]);
+        assert!(connections[1].foreign.is_constant());
+        assert_eq!(
+            chip.parts[0].output_connections[1].foreign.indices,
+            vec![1]
+        );
+    }
+    #[test]
+    fn errors() {
+        let kind = |contents: &str| load_str(contents).unwrap_err().kind;
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: Foo(a=a, out=out); }"),
+            ParseErrorKind::UnknownChip("Foo".to_string())
+        );
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: Not(x=a, out=out); }"),
+            ParseErrorKind::UnknownPin {
+                chip: "Not".to_string(),
+                pin: "x".to_string()
+            }
+        );
+        assert_eq!(
+            kind("CHIP C { IN a[2]; OUT out; PARTS: Not(in=a, out=out); }"),
+            ParseErrorKind::WidthMismatch {
+                pin: "a".to_string(),
+                expected: 1,
+                got: 2
+            }
+        );
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: Not(in=a[1], out=out); }"),
+            ParseErrorKind::BadSubscript("a[1]".to_string())
+        );
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: Not(in=out, out=out); }"),
+            ParseErrorKind::ReadsOutput("out".to_string())
+        );
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: Not(in=a, out=a); }"),
+            ParseErrorKind::WritesInput("a".to_string())
+        );
+        assert_eq!(
+            kind(
+                "CHIP C { IN a; OUT out; PARTS:
+                Not(in=a, out=out); Not(in=a, out=out); }"
+            ),
+            ParseErrorKind::MultipleWriters("out".to_string())
+        );
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: Not(in=c, out=out); }"),
+            ParseErrorKind::Undriven("c".to_string())
+        );
+        assert_eq!(
+            kind("CHIP C { IN a; OUT out; PARTS: }"),
+            ParseErrorKind::NoParts("C".to_string())
+        );
+    }
+    #[test]
+    fn error_location() {
+        let err = load_str(
+            "CHIP C {\n IN a;\n OUT out;\n PARTS:\n Not(in=b, out=out);\n}",
+        )
+        .unwrap_err();
+        assert_eq!((err.nline, err.nchar), (5, 9));
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use crate::chip::Chip;
+use crate::netlist::{Gate, Netlist, FALSE_NET, TRUE_NET};
+
+/// A pin's value in four-valued simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Logic {
+    Zero,
+    One,
+    /// Unknown, like a register that was never loaded
+    X,
+    /// Floating, a pin nothing drives. Gates read it like X.
+    Z,
+}
+
+/// Runs a chip flattened like `NetlistSimulator` but with `Logic` values.
+/// Every net but the inputs starts as X. Registers stay X until they are
+/// loaded and pins nothing drives become Z once evaluated. Gates give X
+/// whenever an X or Z input could change their output.
+#[derive(Debug, Clone)]
+pub struct LogicSimulator {
+    netlist: Netlist,
+    /// `Netlist::loop_start`
+    loop_start: usize,
+    probes: HashMap<String, Vec<usize>>,
+    values: Vec<Logic>,
+    /// Nets no gate, register or input drives
+    floating: Vec<usize>,
+    /// What each DFF read on the last tick
+    state: Vec<Logic>,
+}
+
+// ============================================================================
+
+impl Logic {
+    pub fn from_bool(value: bool) -> Self {
+        match value {
+            true => Logic::One,
+            false => Logic::Zero,
+        }
+    }
+    /// `None` for X and Z
+    pub fn to_bool(self) -> Option<bool> {
+        match self {
+            Logic::Zero => Some(false),
+            Logic::One => Some(true),
+            Logic::X | Logic::Z => None,
+        }
+    }
+    /// What a gate or register reads, Z becomes X
+    fn read(self) -> Self {
+        match self {
+            Logic::Z => Logic::X,
+            value => value,
+        }
+    }
+    /// A 0 on either input decides the output whatever the other one is
+    pub fn nand(self, other: Self) -> Self {
+        match (self.to_bool(), other.to_bool()) {
+            (Some(false), _) | (_, Some(false)) => Logic::One,
+            (Some(true), Some(true)) => Logic::Zero,
+            _ => Logic::X,
+        }
+    }
+    /// X whenever `sel` is unknown, even if `a` and `b` agree
+    pub fn mux(a: Self, b: Self, sel: Self) -> Self {
+        match sel.to_bool() {
+            Some(false) => a.read(),
+            Some(true) => b.read(),
+            None => Logic::X,
+        }
+    }
+}
+
+impl std::ops::Not for Logic {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        self.to_bool()
+            .map_or(Logic::X, |value| Logic::from_bool(!value))
+    }
+}
+
+impl std::fmt::Display for Logic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let c = match self {
+            Logic::Zero => '0',
+            Logic::One => '1',
+            Logic::X => 'X',
+            Logic::Z => 'Z',
+        };
+        write!(f, "{}", c)
+    }
+}
+
+impl LogicSimulator {
+    /// Inputs start as 0 like in the other simulators and every other net
+    /// as X, nothing has driven it yet. Panics for chips with memory parts,
+    /// like `Netlist::compile`.
+    pub fn new(chip: &Chip) -> Self {
+        let (netlist, probes) = Netlist::compile_with_probes(chip);
+        let mut values = vec![Logic::X; netlist.net_count];
+        let mut driven = vec![false; netlist.net_count];
+        for gate in &netlist.gates {
+            driven[gate.output()] = true;
+        }
+        for dff in &netlist.dffs {
+            driven[dff.out] = true;
+        }
+        for pinline in &netlist.input {
+            for net in &pinline.nets {
+                values[*net] = Logic::Zero;
+                driven[*net] = true;
+            }
+        }
+        values[FALSE_NET] = Logic::Zero;
+        values[TRUE_NET] = Logic::One;
+        driven[FALSE_NET] = true;
+        driven[TRUE_NET] = true;
+        Self {
+            floating: (0..netlist.net_count).filter(|n| !driven[*n]).collect(),
+            state: vec![Logic::X; netlist.dffs.len()],
+            loop_start: netlist.loop_start(),
+            netlist,
+            probes,
+            values,
+        }
+    }
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+    /// Negative values are two's complement. `None` if there is no such
+    /// input.
+    pub fn set_input(&mut self, name: &str, value: i64) -> Option<()> {
+        let pinline = self.netlist.input.iter().find(|p| p.name == name)?;
+        for (bit, net) in pinline.nets.iter().enumerate() {
+            self.values[*net] =
+                Logic::from_bool(bit < 64 && (value >> bit) & 1 == 1);
+        }
+        Some(())
+    }
+    /// Input, internal or output pin of the chip, `pins[0]` is bit 0
+    pub fn get_pins(&self, name: &str) -> Option<Vec<Logic>> {
+        let pinline = self.netlist.get_pinline(name)?;
+        Some(pinline.nets.iter().map(|n| self.values[*n]).collect())
+    }
+    /// Pin of a part at any depth, `Mux/And:1/out`, like `Chip::probe`
+    pub fn probe(&self, path: &str) -> Option<Vec<Logic>> {
+        let mut segments: Vec<&str> = path.split('/').collect();
+        let pin = segments.pop()?;
+        if segments.is_empty() {
+            return self.get_pins(pin);
+        }
+        let mut key = String::new();
+        for segment in segments {
+            key.push_str(segment);
+            if !segment.contains(':') {
+                key.push_str(":0");
+            }
+            key.push('/');
+        }
+        key.push_str(pin);
+        let nets = self.probes.get(&key)?;
+        Some(nets.iter().map(|n| self.values[*n]).collect())
+    }
+    pub fn evaluate(&mut self) {
+        for net in &self.floating {
+            self.values[*net] = Logic::Z;
+        }
+        let loop_start = self.loop_start;
+        self.netlist.evaluate_gates(
+            loop_start,
+            &mut self.values,
+            |gate, values| match *gate {
+                Gate::Nand { a, b, .. } => values[a].nand(values[b]),
+                Gate::Not { input, .. } => !values[input],
+                Gate::Mux { a, b, sel, .. } => {
+                    Logic::mux(values[a], values[b], values[sel])
+                }
+            },
+        );
+    }
+    pub fn tick(&mut self) {
+        self.evaluate();
+        for (state, dff) in self.state.iter_mut().zip(self.netlist.dffs.iter())
+        {
+            *state = self.values[dff.input].read();
+        }
+    }
+    pub fn tock(&mut self) {
+        for (state, dff) in self.state.iter().zip(self.netlist.dffs.iter()) {
+            self.values[dff.out] = *state;
+        }
+        self.evaluate();
+    }
+}
+
+/// Bits from the most significant, like `01X0`
+pub fn bits(pins: &[Logic]) -> String {
+    pins.iter().rev().map(|p| p.to_string()).collect()
+}
+
+/// The pins as an unsigned number, `None` if any of them is X or Z
+pub fn value(pins: &[Logic]) -> Option<u64> {
+    pins.iter().enumerate().try_fold(0, |acc, (bit, pin)| {
+        Some(acc | (pin.to_bool()? as u64) << bit)
+    })
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::{load, load_str};
+    use Logic::*;
+
+    #[test]
+    fn pessimistic_gates() {
+        assert_eq!(Zero.nand(X), One);
+        assert_eq!(Z.nand(Zero), One);
+        assert_eq!(One.nand(X), X);
+        assert_eq!(One.nand(Z), X);
+        assert_eq!(!X, X);
+        assert_eq!(!Z, X);
+        assert_eq!(Logic::mux(One, Zero, Zero), One);
+        assert_eq!(Logic::mux(X, One, One), One);
+        assert_eq!(Logic::mux(Z, One, Zero), X);
+        assert_eq!(Logic::mux(One, One, X), X);
+        assert_eq!(bits(&[Zero, X, One, Z]), "Z1X0");
+        assert_eq!(value(&[One, Zero, One]), Some(5));
+        assert_eq!(value(&[One, X]), None);
+    }
+    #[test]
+    fn uninitialised_registers() {
+        let mut sim = LogicSimulator::new(&load_str(
+            "Pair",
+            "CHIP Pair { IN in[2], load; OUT out[2]; PARTS: \
+             Bit(in=in[0], load=load, out=out[0]); \
+             Bit(in=in[1], load=load, out=out[1]); }",
+            &[],
+        ));
+        sim.evaluate();
+        assert_eq!(sim.get_pins("out").unwrap(), vec![X; 2]);
+        sim.set_input("in", 2);
+        sim.tick();
+        sim.tock();
+        assert_eq!(sim.get_pins("out").unwrap(), vec![X; 2]);
+        sim.set_input("load", 1);
+        sim.tick();
+        sim.tock();
+        assert_eq!(value(&sim.get_pins("out").unwrap()), Some(2));
+        assert_eq!(sim.probe("Bit:1/out").unwrap(), vec![One]);
+        assert_eq!(sim.probe("Bit/load").unwrap(), vec![One]);
+        assert_eq!(sim.probe("Bit:2/out"), None);
+
+        // This Mux is built so that an unknown `a` leaks through when `b`
+        // is 0, and the register never loses its X bits
+        let mut sim = LogicSimulator::new(&load("Register"));
+        sim.set_input("in", 5);
+        sim.set_input("load", 1);
+        sim.tick();
+        sim.tock();
+        assert_eq!(bits(&sim.get_pins("out").unwrap()), "XXXXXXXXXXXXX1X1");
+        assert_eq!(sim.probe("Bit:1/Mux/Xor/out").unwrap(), vec![X]);
+    }
+    #[test]
+    fn floating_pins() {
+        let chip = load_str(
+            "Half",
+            "CHIP Half { IN a; OUT out[3]; PARTS: Not(in=a, out=out[0]); \
+             Nand(a=true, out=out[1]); }",
+            &[],
+        );
+        let mut sim = LogicSimulator::new(&chip);
+        assert_eq!(bits(&sim.get_pins("out").unwrap()), "XXX");
+        assert_eq!(sim.probe("Nand/b").unwrap(), vec![X]);
+        sim.evaluate();
+        assert_eq!(bits(&sim.get_pins("out").unwrap()), "ZX1");
+        assert_eq!(sim.probe("Nand/b").unwrap(), vec![Z]);
+    }
+}
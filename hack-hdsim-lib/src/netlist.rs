@@ -0,0 +1,803 @@
+use std::collections::HashMap;
+
+use crate::chip::{BuiltinChips, Chip, ChipPinlines, Pinline};
+
+/// Always false
+pub const FALSE_NET: usize = 0;
+/// Always true
+pub const TRUE_NET: usize = 1;
+
+/// A `Chip` hierarchy flattened into primitive gates over numbered nets.
+/// Every pin bit of every part becomes a net, connected pins share one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Netlist {
+    pub name: String,
+    pub input: Vec<NetPinline>,
+    pub internal: Vec<NetPinline>,
+    pub output: Vec<NetPinline>,
+    /// In evaluation order, every gate comes after the gates driving it
+    pub gates: Vec<Gate>,
+    pub dffs: Vec<Dff>,
+    pub net_count: usize,
+}
+
+/// Nets of a pin of the top chip, `nets[0]` is bit 0
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetPinline {
+    pub name: String,
+    pub nets: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gate {
+    Nand {
+        a: usize,
+        b: usize,
+        out: usize,
+    },
+    Not {
+        input: usize,
+        out: usize,
+    },
+    Mux {
+        a: usize,
+        b: usize,
+        sel: usize,
+        out: usize,
+    },
+}
+
+/// Shows on tock what it read on tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dff {
+    pub input: usize,
+    pub out: usize,
+}
+
+/// Runs a `Netlist` with the same tick/tock semantics as `Chip`
+#[derive(Debug, Clone)]
+pub struct NetlistSimulator {
+    netlist: Netlist,
+    /// `Netlist::loop_start`
+    loop_start: usize,
+    values: Vec<bool>,
+    /// What each DFF read on the last tick
+    state: Vec<bool>,
+}
+
+/// Runs 64 independent vectors through a `Netlist` at once. Each net holds a
+/// word whose bit n is its value in vector (lane) n.
+#[derive(Debug, Clone)]
+pub struct ParallelSimulator {
+    netlist: Netlist,
+    loop_start: usize,
+    values: Vec<u64>,
+    state: Vec<u64>,
+}
+
+// ============================================================================
+
+impl Netlist {
+    /// Panics where `try_compile` returns an error
+    pub fn compile(chip: &Chip) -> Self {
+        Self::try_compile(chip).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// An error when the chip has a ROM32K, RAM16K, Screen or Keyboard part,
+    /// which have no gates to flatten to
+    pub fn try_compile(chip: &Chip) -> Result<Self, String> {
+        gates_only(chip)?;
+        Ok(Self::build(chip, None).0)
+    }
+    /// Also the nets of every pin of every part, by paths like
+    /// `Mux:0/And:1/out` where every part has its number. Panics like
+    /// `compile`.
+    pub fn compile_with_probes(
+        chip: &Chip,
+    ) -> (Self, HashMap<String, Vec<usize>>) {
+        gates_only(chip).unwrap_or_else(|e| panic!("{}", e));
+        Self::build(chip, Some(Vec::new()))
+    }
+    /// The gates of every part but memory, whose outputs nothing drives
+    pub(crate) fn compile_gates(chip: &Chip) -> Self {
+        Self::build(chip, None).0
+    }
+    fn build(
+        chip: &Chip,
+        probes: Option<Vec<(String, Vec<usize>)>>,
+    ) -> (Self, HashMap<String, Vec<usize>>) {
+        let mut builder = Builder {
+            parent: vec![FALSE_NET, TRUE_NET],
+            gates: Vec::new(),
+            dffs: Vec::new(),
+            probes,
+        };
+        let nets = builder.instance(chip);
+        builder.flatten(chip, "", &nets);
+        builder.finish(chip, &nets)
+    }
+    /// Total number of input bits
+    pub fn input_width(&self) -> usize {
+        self.input.iter().map(|p| p.nets.len()).sum()
+    }
+    pub fn get_pinline(&self, name: &str) -> Option<&NetPinline> {
+        self.input
+            .iter()
+            .chain(self.internal.iter())
+            .chain(self.output.iter())
+            .find(|p| p.name == name)
+    }
+    /// First gate that reads a gate at or after it. Gates in combinational
+    /// loops, and the gates they feed, are all from there on.
+    pub fn loop_start(&self) -> usize {
+        let mut position = vec![usize::MAX; self.net_count];
+        for (i, gate) in self.gates.iter().enumerate() {
+            position[gate.output()] = i;
+        }
+        self.gates
+            .iter()
+            .enumerate()
+            .position(|(i, gate)| {
+                gate.inputs()
+                    .iter()
+                    .any(|n| position[*n] != usize::MAX && position[*n] >= i)
+            })
+            .unwrap_or(self.gates.len())
+    }
+    /// Sets every gate's output to `output` of it in order. Gates from
+    /// `loop_start` on then go round until their outputs stop changing, or
+    /// once more per gate for loops that never settle, like parts in
+    /// `Chip::evaluate`.
+    pub(crate) fn evaluate_gates<T: Copy + PartialEq>(
+        &self,
+        loop_start: usize,
+        values: &mut [T],
+        output: impl Fn(&Gate, &[T]) -> T,
+    ) {
+        let (settled, looping) = self.gates.split_at(loop_start);
+        for gate in settled {
+            values[gate.output()] = output(gate, values);
+        }
+        for _ in 0..=looping.len() {
+            let mut changed = false;
+            for gate in looping {
+                let value = output(gate, values);
+                changed |= values[gate.output()] != value;
+                values[gate.output()] = value;
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+impl Gate {
+    pub fn output(&self) -> usize {
+        match *self {
+            Gate::Nand { out, .. } | Gate::Not { out, .. } => out,
+            Gate::Mux { out, .. } => out,
+        }
+    }
+    pub fn inputs(&self) -> Vec<usize> {
+        match *self {
+            Gate::Nand { a, b, .. } => vec![a, b],
+            Gate::Not { input, .. } => vec![input],
+            Gate::Mux { a, b, sel, .. } => vec![a, b, sel],
+        }
+    }
+    fn map(&self, f: impl Fn(usize) -> usize) -> Self {
+        match *self {
+            Gate::Nand { a, b, out } => Gate::Nand {
+                a: f(a),
+                b: f(b),
+                out: f(out),
+            },
+            Gate::Not { input, out } => Gate::Not {
+                input: f(input),
+                out: f(out),
+            },
+            Gate::Mux { a, b, sel, out } => Gate::Mux {
+                a: f(a),
+                b: f(b),
+                sel: f(sel),
+                out: f(out),
+            },
+        }
+    }
+}
+
+impl NetlistSimulator {
+    pub fn new(netlist: Netlist) -> Self {
+        let mut values = vec![false; netlist.net_count];
+        values[TRUE_NET] = true;
+        Self {
+            state: vec![false; netlist.dffs.len()],
+            loop_start: netlist.loop_start(),
+            values,
+            netlist,
+        }
+    }
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+    /// Negative values are two's complement. `None` if there is no such
+    /// input.
+    pub fn set_input(&mut self, name: &str, value: i64) -> Option<()> {
+        let pinline = self.netlist.input.iter().find(|p| p.name == name)?;
+        for (bit, net) in pinline.nets.iter().enumerate() {
+            self.values[*net] = bit < 64 && (value >> bit) & 1 == 1;
+        }
+        Some(())
+    }
+    /// Input, internal or output pin as an unsigned number
+    pub fn get_value(&self, name: &str) -> Option<u64> {
+        let pinline = self.netlist.get_pinline(name)?;
+        Some(pinline.nets.iter().enumerate().fold(0, |acc, (bit, net)| {
+            acc | ((self.values[*net] as u64) << bit)
+        }))
+    }
+    pub fn get_pinline(&self, name: &str) -> Option<Pinline> {
+        let pinline = self.netlist.get_pinline(name)?;
+        let pins = pinline.nets.iter().map(|n| self.values[*n]).collect();
+        Some(Pinline::new(name, pins))
+    }
+    /// All pins of the top chip, like `Chip::pinlines`
+    pub fn pinlines(&self) -> ChipPinlines {
+        let pinlines = |nets: &[NetPinline]| {
+            nets.iter()
+                .filter_map(|p| self.get_pinline(p.name.as_str()))
+                .collect()
+        };
+        ChipPinlines::new(
+            pinlines(&self.netlist.input),
+            pinlines(&self.netlist.internal),
+            pinlines(&self.netlist.output),
+        )
+    }
+    pub fn evaluate(&mut self) {
+        let loop_start = self.loop_start;
+        self.netlist.evaluate_gates(
+            loop_start,
+            &mut self.values,
+            |gate, values| match *gate {
+                Gate::Nand { a, b, .. } => !(values[a] && values[b]),
+                Gate::Not { input, .. } => !values[input],
+                Gate::Mux { a, b, sel, .. } => {
+                    if values[sel] {
+                        values[b]
+                    } else {
+                        values[a]
+                    }
+                }
+            },
+        );
+    }
+    pub fn tick(&mut self) {
+        self.evaluate();
+        for (state, dff) in self.state.iter_mut().zip(self.netlist.dffs.iter())
+        {
+            *state = self.values[dff.input];
+        }
+    }
+    pub fn tock(&mut self) {
+        for (state, dff) in self.state.iter().zip(self.netlist.dffs.iter()) {
+            self.values[dff.out] = *state;
+        }
+        self.evaluate();
+    }
+}
+
+impl ParallelSimulator {
+    pub const LANES: usize = 64;
+
+    pub fn new(netlist: Netlist) -> Self {
+        let mut values = vec![0; netlist.net_count];
+        values[TRUE_NET] = u64::MAX;
+        Self {
+            state: vec![0; netlist.dffs.len()],
+            loop_start: netlist.loop_start(),
+            values,
+            netlist,
+        }
+    }
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+    /// One value per lane, lanes past the end of `values` are 0
+    pub fn set_input(&mut self, name: &str, values: &[i64]) -> Option<()> {
+        let pinline = self.netlist.input.iter().find(|p| p.name == name)?;
+        for (bit, net) in pinline.nets.iter().enumerate() {
+            self.values[*net] = values
+                .iter()
+                .take(Self::LANES)
+                .enumerate()
+                .filter(|(_, v)| bit < 64 && (*v >> bit) & 1 == 1)
+                .fold(0, |word, (lane, _)| word | 1 << lane);
+        }
+        Some(())
+    }
+    /// Puts input combinations `first..first + 64` in the lanes and returns
+    /// how many of them exist. A combination reads the inputs left to right
+    /// as one binary number, so the last bit of the last input is the least
+    /// significant.
+    pub fn set_combinations(&mut self, first: u64) -> usize {
+        let width = self.netlist.input_width();
+        let nets: Vec<usize> = self
+            .netlist
+            .input
+            .iter()
+            .flat_map(|p| p.nets.iter().rev().cloned())
+            .collect();
+        for (position, net) in nets.iter().enumerate() {
+            let bit = width - 1 - position;
+            self.values[*net] = (0..Self::LANES as u64)
+                .filter(|lane| bit < 64 && ((first + lane) >> bit) & 1 == 1)
+                .fold(0, |word, lane| word | 1 << lane);
+        }
+        match 1u64.checked_shl(width as u32) {
+            Some(total) if width < 64 => {
+                total.saturating_sub(first).min(Self::LANES as u64) as usize
+            }
+            _ => Self::LANES,
+        }
+    }
+    /// The value of a pin in every lane, as unsigned numbers
+    pub fn get_values(&self, name: &str) -> Option<Vec<u64>> {
+        let pinline = self.netlist.get_pinline(name)?;
+        let values = (0..Self::LANES)
+            .map(|lane| {
+                pinline.nets.iter().enumerate().fold(0, |acc, (bit, net)| {
+                    acc | ((self.values[*net] >> lane) & 1) << bit
+                })
+            })
+            .collect();
+        Some(values)
+    }
+    /// Per bit of a pin, the word holding that bit for every lane
+    pub fn get_words(&self, name: &str) -> Option<Vec<u64>> {
+        let pinline = self.netlist.get_pinline(name)?;
+        Some(pinline.nets.iter().map(|n| self.values[*n]).collect())
+    }
+    pub fn evaluate(&mut self) {
+        let loop_start = self.loop_start;
+        self.netlist.evaluate_gates(
+            loop_start,
+            &mut self.values,
+            |gate, values| match *gate {
+                Gate::Nand { a, b, .. } => !(values[a] & values[b]),
+                Gate::Not { input, .. } => !values[input],
+                Gate::Mux { a, b, sel, .. } => {
+                    values[sel] & values[b] | !values[sel] & values[a]
+                }
+            },
+        );
+    }
+    pub fn tick(&mut self) {
+        self.evaluate();
+        for (state, dff) in self.state.iter_mut().zip(self.netlist.dffs.iter())
+        {
+            *state = self.values[dff.input];
+        }
+    }
+    pub fn tock(&mut self) {
+        for (state, dff) in self.state.iter().zip(self.netlist.dffs.iter()) {
+            self.values[dff.out] = *state;
+        }
+        self.evaluate();
+    }
+}
+
+struct Builder {
+    /// Union-find over nets, the two constants are always their own roots
+    parent: Vec<usize>,
+    gates: Vec<Gate>,
+    dffs: Vec<Dff>,
+    /// Nets of part pins by path, only kept when asked for
+    probes: Option<Vec<(String, Vec<usize>)>>,
+}
+
+impl Builder {
+    fn new_net(&mut self) -> usize {
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
+    }
+    fn find(&mut self, net: usize) -> usize {
+        let mut root = net;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut net = net;
+        while self.parent[net] != root {
+            let next = self.parent[net];
+            self.parent[net] = root;
+            net = next;
+        }
+        root
+    }
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if b == FALSE_NET || b == TRUE_NET {
+            self.parent[a] = b;
+        } else {
+            self.parent[b] = a;
+        }
+    }
+    /// Fresh nets for every pin of a chip
+    fn instance(&mut self, chip: &Chip) -> HashMap<String, Vec<usize>> {
+        let pinlines = &chip.pinlines;
+        pinlines
+            .input
+            .iter()
+            .chain(pinlines.internal.iter())
+            .chain(pinlines.output.iter())
+            .map(|p| {
+                let nets = p.pins.iter().map(|_| self.new_net()).collect();
+                (p.name.clone(), nets)
+            })
+            .collect()
+    }
+    fn flatten(
+        &mut self,
+        chip: &Chip,
+        path: &str,
+        nets: &HashMap<String, Vec<usize>>,
+    ) {
+        if let Some(id) = &chip.builtin_id {
+            return self.builtin(id, nets);
+        }
+        let mut numbers: HashMap<&str, usize> = HashMap::new();
+        for part in &chip.parts {
+            let part_nets = self.instance(&part.chip);
+            let number = numbers.entry(part.chip.name.as_str()).or_insert(0);
+            let part_path = format!("{}{}:{}/", path, part.chip.name, number);
+            *number += 1;
+            if let Some(probes) = &mut self.probes {
+                for (name, nets) in &part_nets {
+                    probes
+                        .push((format!("{}{}", part_path, name), nets.clone()));
+                }
+            }
+            for connection in part
+                .input_connections
+                .iter()
+                .chain(part.output_connections.iter())
+            {
+                let own = &part_nets[connection.own.name.as_str()];
+                if connection.foreign.is_constant() {
+                    let constant = match connection.foreign.name.as_str() {
+                        "true" => TRUE_NET,
+                        _ => FALSE_NET,
+                    };
+                    for own_i in &connection.own.indices {
+                        self.union(own[*own_i], constant);
+                    }
+                    continue;
+                }
+                let foreign = &nets[connection.foreign.name.as_str()];
+                for (own_i, foreign_i) in connection
+                    .own
+                    .indices
+                    .iter()
+                    .zip(connection.foreign.indices.iter())
+                {
+                    self.union(own[*own_i], foreign[*foreign_i]);
+                }
+            }
+            self.flatten(&part.chip, &part_path, &part_nets);
+        }
+    }
+    fn builtin(
+        &mut self,
+        id: &BuiltinChips,
+        nets: &HashMap<String, Vec<usize>>,
+    ) {
+        let net = |name: &str| nets[name][0];
+        match id {
+            BuiltinChips::Nand => self.gates.push(Gate::Nand {
+                a: net("a"),
+                b: net("b"),
+                out: net("out"),
+            }),
+            BuiltinChips::Not => self.gates.push(Gate::Not {
+                input: net("in"),
+                out: net("out"),
+            }),
+            BuiltinChips::Mux => self.gates.push(Gate::Mux {
+                a: net("a"),
+                b: net("b"),
+                sel: net("sel"),
+                out: net("out"),
+            }),
+            BuiltinChips::DFF => self.dffs.push(Dff {
+                input: net("in"),
+                out: net("out"),
+            }),
+            // Left out by `compile_gates` and refused by `try_compile`
+            BuiltinChips::ROM32K
+            | BuiltinChips::RAM16K
+            | BuiltinChips::Screen
+            | BuiltinChips::Keyboard => {}
+            BuiltinChips::Bit => {
+                let next = self.new_net();
+                self.gates.push(Gate::Mux {
+                    a: net("out"),
+                    b: net("in"),
+                    sel: net("load"),
+                    out: next,
+                });
+                self.dffs.push(Dff {
+                    input: next,
+                    out: net("out"),
+                });
+            }
+        }
+    }
+    /// Numbers the nets that are left after connecting and orders the gates
+    fn finish(
+        mut self,
+        chip: &Chip,
+        nets: &HashMap<String, Vec<usize>>,
+    ) -> (Netlist, HashMap<String, Vec<usize>>) {
+        let mut numbers = vec![usize::MAX; self.parent.len()];
+        let mut net_count = 0;
+        for net in 0..self.parent.len() {
+            let root = self.find(net);
+            if numbers[root] == usize::MAX {
+                numbers[root] = net_count;
+                net_count += 1;
+            }
+            numbers[net] = numbers[root];
+        }
+        let gates: Vec<Gate> =
+            self.gates.iter().map(|g| g.map(|n| numbers[n])).collect();
+        let dffs = self
+            .dffs
+            .iter()
+            .map(|d| Dff {
+                input: numbers[d.input],
+                out: numbers[d.out],
+            })
+            .collect();
+        let pinlines = |pinlines: &[Pinline]| {
+            pinlines
+                .iter()
+                .map(|p| NetPinline {
+                    name: p.name.clone(),
+                    nets: nets[p.name.as_str()]
+                        .iter()
+                        .map(|n| numbers[*n])
+                        .collect(),
+                })
+                .collect()
+        };
+        let netlist = Netlist {
+            name: chip.name.clone(),
+            input: pinlines(&chip.pinlines.input),
+            internal: pinlines(&chip.pinlines.internal),
+            output: pinlines(&chip.pinlines.output),
+            gates: topological_order(gates, net_count),
+            dffs,
+            net_count,
+        };
+        let probes = self
+            .probes
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(path, nets)| {
+                (path, nets.iter().map(|n| numbers[*n]).collect())
+            })
+            .collect();
+        (netlist, probes)
+    }
+}
+
+/// Names of the bits of a pin, `a` for a single bit and `b[0]`, `b[1]` and
+/// so on for a bus
+pub(crate) fn bit_names(pinline: &NetPinline) -> Vec<String> {
+    if pinline.nets.len() == 1 {
+        return vec![pinline.name.clone()];
+    }
+    (0..pinline.nets.len())
+        .map(|i| format!("{}[{}]", pinline.name, i))
+        .collect()
+}
+
+/// Pins from named bits, `b[3]` being bit 3 of `b`, in order of first
+/// appearance. Missing bits get new nets, added to `net_count`.
+pub(crate) fn group_bits(
+    bits: Vec<(String, usize)>,
+    net_count: &mut usize,
+) -> Vec<NetPinline> {
+    let mut pinlines: Vec<NetPinline> = Vec::new();
+    for (name, net) in bits {
+        let (name, index) = match name.find('[') {
+            Some(open) if name.ends_with(']') => {
+                match name[open + 1..name.len() - 1].parse() {
+                    Ok(index) => (name[..open].to_string(), index),
+                    Err(_) => (name, 0),
+                }
+            }
+            _ => (name, 0),
+        };
+        let position = match pinlines.iter().position(|p| p.name == name) {
+            Some(position) => position,
+            None => {
+                pinlines.push(NetPinline {
+                    name,
+                    nets: Vec::new(),
+                });
+                pinlines.len() - 1
+            }
+        };
+        let nets = &mut pinlines[position].nets;
+        while nets.len() <= index {
+            nets.push(*net_count);
+            *net_count += 1;
+        }
+        nets[index] = net;
+    }
+    pinlines
+}
+
+fn gates_only(chip: &Chip) -> Result<(), String> {
+    match chip.memory_part() {
+        Some(name) => Err(format!(
+            "chip {} has a {} part, memory can't be flattened to gates",
+            chip.name, name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Gates in combinational loops keep their order at the end
+pub(crate) fn topological_order(
+    gates: Vec<Gate>,
+    net_count: usize,
+) -> Vec<Gate> {
+    let mut driver = vec![None; net_count];
+    for (i, gate) in gates.iter().enumerate() {
+        driver[gate.output()] = Some(i);
+    }
+    let mut readers = vec![Vec::new(); gates.len()];
+    let mut waiting = vec![0; gates.len()];
+    for (i, gate) in gates.iter().enumerate() {
+        for input in gate.inputs() {
+            if let Some(d) = driver[input] {
+                readers[d].push(i);
+                waiting[i] += 1;
+            }
+        }
+    }
+    let mut ready: Vec<usize> =
+        (0..gates.len()).filter(|i| waiting[*i] == 0).collect();
+    let mut placed = vec![false; gates.len()];
+    let mut order = Vec::with_capacity(gates.len());
+    while let Some(i) = ready.pop() {
+        placed[i] = true;
+        order.push(gates[i]);
+        for reader in &readers[i] {
+            waiting[*reader] -= 1;
+            if waiting[*reader] == 0 {
+                ready.push(*reader);
+            }
+        }
+    }
+    order.extend((0..gates.len()).filter(|i| !placed[*i]).map(|i| gates[i]));
+    order
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::{load, same_behaviour, temp_dir};
+    use crate::loader::ChipLoader;
+    use crate::random::Random;
+
+    #[test]
+    fn compile_and() {
+        let netlist = Netlist::compile(&load("And"));
+        assert_eq!(netlist.gates.len(), 2);
+        assert!(netlist.dffs.is_empty());
+        // Constants, a, b, out and the internal pin
+        assert_eq!(netlist.net_count, 6);
+        let nand_out = netlist.gates[0].output();
+        assert_eq!(netlist.gates[1].inputs(), vec![nand_out, nand_out]);
+    }
+    #[test]
+    fn matches_tree() {
+        for name in &["And", "Or", "Xor", "Mux", "Bit", "ALU", "PC", "CPU"] {
+            let chip = load(name);
+            same_behaviour(chip.clone(), Netlist::compile(&chip));
+        }
+        // Gates that read each other take more than one pass to settle
+        let dir = temp_dir("netlist-latch");
+        std::fs::write(
+            dir.join("Latch.hdl"),
+            "CHIP Latch { IN s, r; OUT q, qb; PARTS:
+            Nand(a=r, b=qq, out=qb, out=qqb);
+            Nand(a=s, b=qqb, out=q, out=qq); }",
+        )
+        .unwrap();
+        let latch = ChipLoader::new(Some(dir.as_path())).load("Latch").unwrap();
+        let mut sim = NetlistSimulator::new(Netlist::compile(&latch));
+        assert_eq!(sim.loop_start, 0);
+        sim.set_input("r", 1);
+        sim.evaluate();
+        assert_eq!(
+            (sim.get_value("q"), sim.get_value("qb")),
+            (Some(1), Some(0))
+        );
+        same_behaviour(latch.clone(), Netlist::compile(&latch));
+    }
+    #[test]
+    fn buses_and_constants() {
+        let dir = temp_dir("netlist");
+        std::fs::write(
+            dir.join("Swap.hdl"),
+            "CHIP Swap { IN in[4], load; OUT out[4], low[2], one;
+            PARTS:
+            Bit(in=in[3], load=load, out=out[0], out=low[0]);
+            Not(in=in[0], out=out[3]);
+            Mux(a=in[1], b=true, sel=in[2], out=out[1], out=out[2], out=low[1]);
+            Nand(a=false, b=in[0], out=one); }",
+        )
+        .unwrap();
+        let chip = ChipLoader::new(Some(dir.as_path())).load("Swap").unwrap();
+        same_behaviour(chip.clone(), Netlist::compile(&chip));
+    }
+    #[test]
+    fn parallel_combinations() {
+        let netlist = Netlist::compile(&load("Mux"));
+        let mut parallel = ParallelSimulator::new(netlist.clone());
+        assert_eq!(parallel.set_combinations(0), 8);
+        parallel.evaluate();
+        let outs = parallel.get_values("out").unwrap();
+        let mut sim = NetlistSimulator::new(netlist);
+        for combination in 0..8 {
+            let a = (combination >> 2) & 1;
+            let b = (combination >> 1) & 1;
+            let sel = combination & 1;
+            sim.set_input("a", a);
+            sim.set_input("b", b);
+            sim.set_input("sel", sel);
+            sim.evaluate();
+            assert_eq!(sim.get_value("out"), Some(outs[combination as usize]));
+            assert_eq!(outs[combination as usize] as i64, [a, b][sel as usize]);
+        }
+        assert_eq!(parallel.set_combinations(4), 4);
+        assert_eq!(parallel.set_combinations(100), 0);
+    }
+    #[test]
+    fn parallel_lanes() {
+        let netlist = Netlist::compile(&load("Bit"));
+        let mut parallel = ParallelSimulator::new(netlist.clone());
+        let mut sims = vec![NetlistSimulator::new(netlist); 64];
+        let mut random = Random::new(99);
+        for _ in 0..20 {
+            let bits = random.next_u64();
+            let ins: Vec<i64> =
+                (0..64).map(|l| (bits >> l) as i64 & 1).collect();
+            let loads: Vec<i64> =
+                (0..64).map(|l| (bits >> (l / 2)) as i64 & 1).collect();
+            parallel.set_input("in", &ins).unwrap();
+            parallel.set_input("load", &loads).unwrap();
+            parallel.tick();
+            parallel.tock();
+            for (lane, sim) in sims.iter_mut().enumerate() {
+                sim.set_input("in", ins[lane]);
+                sim.set_input("load", loads[lane]);
+                sim.tick();
+                sim.tock();
+            }
+            let outs = parallel.get_values("out").unwrap();
+            for (lane, sim) in sims.iter().enumerate() {
+                assert_eq!(sim.get_value("out"), Some(outs[lane]));
+            }
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use crate::tokeniser::{Token, Tokeniser, UnexpectedToken};
+
+/// A chip as written in an .hdl file, before its parts are resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChipDecl {
+    pub name: String,
+    pub input: Vec<PinDecl>,
+    pub output: Vec<PinDecl>,
+    pub parts: Vec<PartDecl>,
+    pub nline: i32,
+    pub nchar: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinDecl {
+    pub name: String,
+    pub width: usize,
+    pub nline: i32,
+    pub nchar: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartDecl {
+    pub name: String,
+    pub connections: Vec<ConnectionDecl>,
+    pub nline: i32,
+    pub nchar: i32,
+}
+
+/// `own=foreign` inside a part
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionDecl {
+    pub own: PinRef,
+    pub foreign: PinRef,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinRef {
+    pub name: String,
+    /// Inclusive sub-bus, `a[3]` is `(3, 3)`
+    pub range: Option<(usize, usize)>,
+    pub nline: i32,
+    pub nchar: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ParseError {
+    /// File the error is in, when the chip was loaded from one
+    pub file: Option<std::path::PathBuf>,
+    #[cfg_attr(feature = "serde", serde(rename = "line"))]
+    pub nline: i32,
+    #[cfg_attr(feature = "serde", serde(rename = "char"))]
+    pub nchar: i32,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub kind: ParseErrorKind,
+}
+
+/// Serialised as `{"kind": "unknown_chip", "detail": "Foo"}`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "kind", content = "detail", rename_all = "snake_case")
+)]
+pub enum ParseErrorKind {
+    Syntax(
+        #[cfg_attr(feature = "serde", serde(serialize_with = "display"))] Token,
+    ),
+    UnknownChip(String),
+    UnknownPin {
+        chip: String,
+        pin: String,
+    },
+    WidthMismatch {
+        pin: String,
+        expected: usize,
+        got: usize,
+    },
+    BadSubscript(String),
+    ReadsOutput(String),
+    WritesInput(String),
+    MultipleWriters(String),
+    Undriven(String),
+    NoParts(String),
+    Recursive(String),
+    ReadFailed(String),
+}
+
+// ============================================================================
+
+pub fn parse_chip(contents: &str) -> Result<ChipDecl, ParseError> {
+    let mut tokeniser = Tokeniser::new(contents);
+    tokeniser.tokenise_chip()?;
+    let mut parser = Parser {
+        tokens: tokeniser.tokens(),
+        positions: tokeniser.positions(),
+        pos: 0,
+    };
+    parser.chip()
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}: ", self.nline, self.nchar)?;
+        use ParseErrorKind::*;
+        match &self.kind {
+            Syntax(expected) => write!(f, "expected {}", expected),
+            UnknownChip(name) => write!(f, "unknown chip {}", name),
+            UnknownPin { chip, pin } => {
+                write!(f, "chip {} has no pin {}", chip, pin)
+            }
+            WidthMismatch { pin, expected, got } => write!(
+                f,
+                "pin {} is {} bits wide but is connected to {} bits",
+                pin, expected, got
+            ),
+            BadSubscript(pin) => write!(f, "bad subscript on pin {}", pin),
+            ReadsOutput(pin) => {
+                write!(f, "output pin {} can't be used as a part input", pin)
+            }
+            WritesInput(pin) => {
+                write!(f, "input pin {} can't be driven by a part", pin)
+            }
+            MultipleWriters(pin) => {
+                write!(f, "pin {} is driven by more than one part", pin)
+            }
+            Undriven(pin) => {
+                write!(f, "internal pin {} is not driven by any part", pin)
+            }
+            NoParts(chip) => write!(f, "chip {} has no parts", chip),
+            Recursive(chip) => write!(f, "chip {} contains itself", chip),
+            ReadFailed(reason) => write!(f, "could not read file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(feature = "serde")]
+fn display<T: std::fmt::Display, S: serde::Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+impl From<UnexpectedToken> for ParseError {
+    fn from(e: UnexpectedToken) -> Self {
+        Self {
+            file: None,
+            nline: e.nline,
+            nchar: e.nchar,
+            kind: ParseErrorKind::Syntax(e.expected),
+        }
+    }
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, nline: i32, nchar: i32) -> Self {
+        Self {
+            file: None,
+            nline,
+            nchar,
+            kind,
+        }
+    }
+}
+
+impl ChipDecl {
+    pub fn get_input(&self, name: &str) -> Option<&PinDecl> {
+        self.input.iter().find(|p| p.name == name)
+    }
+    pub fn get_output(&self, name: &str) -> Option<&PinDecl> {
+        self.output.iter().find(|p| p.name == name)
+    }
+}
+
+impl PinRef {
+    pub fn is_constant(&self) -> bool {
+        self.name == "true" || self.name == "false"
+    }
+    /// Indices this reference covers in a pin of the given width
+    pub fn indices(&self, width: usize) -> Vec<usize> {
+        match self.range {
+            Some((from, to)) => (from..=to).collect(),
+            None => (0..width).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for PinRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.range {
+            Some((from, to)) if from == to => {
+                write!(f, "{}[{}]", self.name, from)
+            }
+            Some((from, to)) => write!(f, "{}[{}..{}]", self.name, from, to),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Walks tokens that already passed `Tokeniser::tokenise_chip`
+struct Parser<'a> {
+    tokens: &'a [Token],
+    positions: &'a [(i32, i32)],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> &'a Token {
+        let token = &self.tokens[self.pos];
+        self.pos += 1;
+        token
+    }
+    fn position(&self) -> (i32, i32) {
+        self.positions[self.pos]
+    }
+    fn chip(&mut self) -> Result<ChipDecl, ParseError> {
+        self.next(); // CHIP
+        let (nline, nchar) = self.position();
+        let name = self.identifier();
+        self.next(); // {
+        self.next(); // IN
+        let input = self.pin_list()?;
+        let output = match self.peek() {
+            Some(Token::Keyword(keyword)) if keyword == "OUT" => {
+                self.next();
+                self.pin_list()?
+            }
+            _ => Vec::new(),
+        };
+        self.next(); // PARTS
+        self.next(); // :
+        let mut parts = Vec::new();
+        while let Some(Token::Identifier(_)) = self.peek() {
+            parts.push(self.part());
+        }
+        Ok(ChipDecl {
+            name,
+            input,
+            output,
+            parts,
+            nline,
+            nchar,
+        })
+    }
+    fn identifier(&mut self) -> String {
+        match self.next() {
+            Token::Identifier(name) => name.clone(),
+            other => unreachable!("expected identifier, got {:?}", other),
+        }
+    }
+    fn number(&mut self) -> usize {
+        match self.next() {
+            Token::Number(n) => *n as usize,
+            other => unreachable!("expected number, got {:?}", other),
+        }
+    }
+    fn pin_ref(&mut self) -> PinRef {
+        let (nline, nchar) = self.position();
+        let name = self.identifier();
+        let mut range = None;
+        if let Some(Token::Symbol('[')) = self.peek() {
+            self.next();
+            let from = self.number();
+            let mut to = from;
+            if let Some(Token::Range) = self.peek() {
+                self.next();
+                to = self.number();
+            }
+            self.next(); // ]
+            range = Some((from, to));
+        }
+        PinRef {
+            name,
+            range,
+            nline,
+            nchar,
+        }
+    }
+    /// In declarations `a[16]` gives the width rather than a subscript, so
+    /// it can't be 0 or a range
+    fn pin_list(&mut self) -> Result<Vec<PinDecl>, ParseError> {
+        let mut pins = Vec::new();
+        while let Some(Token::Identifier(_)) = self.peek() {
+            let pin = self.pin_ref();
+            let width = match pin.range {
+                None => 1,
+                Some((width, to)) if width == to && width > 0 => width,
+                Some(_) => {
+                    return Err(ParseError::new(
+                        ParseErrorKind::BadSubscript(pin.to_string()),
+                        pin.nline,
+                        pin.nchar,
+                    ))
+                }
+            };
+            pins.push(PinDecl {
+                name: pin.name,
+                width,
+                nline: pin.nline,
+                nchar: pin.nchar,
+            });
+        }
+        self.next(); // ;
+        Ok(pins)
+    }
+    fn part(&mut self) -> PartDecl {
+        let (nline, nchar) = self.position();
+        let name = self.identifier();
+        self.next(); // (
+        let mut connections = Vec::new();
+        while let Some(Token::Identifier(_)) = self.peek() {
+            let own = self.pin_ref();
+            self.next(); // =
+            let foreign = self.pin_ref();
+            connections.push(ConnectionDecl { own, foreign });
+        }
+        self.next(); // )
+        self.next(); // ;
+        PartDecl {
+            name,
+            connections,
+            nline,
+            nchar,
+        }
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn and() {
+        let contents = std::fs::read_to_string("../test-hdl/And.hdl").unwrap();
+        let decl = parse_chip(contents.as_str()).unwrap();
+        assert_eq!(decl.name, "And");
+        assert_eq!(
+            decl.input
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(decl.output[0].width, 1);
+        assert_eq!(decl.parts.len(), 2);
+        assert_eq!(decl.parts[1].name, "Nand");
+        assert_eq!((decl.parts[1].nline, decl.parts[1].nchar), (20, 2));
+        assert_eq!(decl.parts[1].connections[2].foreign.name, "out");
+    }
+    #[test]
+    fn subscripts() {
+        let contents = "CHIP C { IN x[16]; OUT y[15];
+            PARTS: P(a=x[3], b[0..14]=y, c=true); }";
+        let decl = parse_chip(contents).unwrap();
+        assert_eq!(decl.input[0].width, 16);
+        assert_eq!(decl.output[0].width, 15);
+        let connections = &decl.parts[0].connections;
+        assert_eq!(connections[0].foreign.range, Some((3, 3)));
+        assert_eq!(connections[1].own.range, Some((0, 14)));
+        assert_eq!(connections[1].own.indices(16), (0..15).collect::<Vec<_>>());
+        assert!(connections[2].foreign.is_constant());
+        assert_eq!(connections[1].own.to_string(), "b[0..14]");
+
+        // Computer has no outputs and no OUT line
+        let decl = parse_chip("CHIP C { IN x; PARTS: P(a=x); }").unwrap();
+        assert!(decl.output.is_empty());
+    }
+    #[test]
+    fn declared_widths() {
+        let err = parse_chip("CHIP C { IN a[0]; OUT b; PARTS: }").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::BadSubscript("a[0]".to_string()));
+        assert_eq!((err.nline, err.nchar), (1, 13));
+        let err =
+            parse_chip("CHIP C { IN a; OUT b[2..5]; PARTS: }").unwrap_err();
+        assert_eq!(err.to_string(), "1:20: bad subscript on pin b[2..5]");
+    }
+    #[test]
+    fn syntax_error() {
+        let err = parse_chip("CHIP And {\n    IN a,;").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Syntax(Token::Identifier("identifier".to_string()))
+        );
+        assert_eq!(err.to_string(), "2:10: expected identifier");
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialise_error() {
+        let err = parse_chip("CHIP And {\n    IN a,;").unwrap_err();
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({
+                "file": null,
+                "line": 2,
+                "char": 10,
+                "kind": "syntax",
+                "detail": "identifier",
+            })
+        );
+    }
+}
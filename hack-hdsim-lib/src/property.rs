@@ -0,0 +1,398 @@
+use crate::chip::{Chip, Pinline};
+use crate::random::Random;
+
+/// Expected behaviour of an unclocked chip: a function of its inputs in the
+/// order they are declared, giving its outputs in order. Implemented for
+/// closures taking one to four `u64`s, or a `&[u64]` of all the inputs, and
+/// returning a `u64`, a tuple of them or a `Vec<u64>`. `Args` only tells
+/// those apart.
+pub trait Model<Args> {
+    /// Number of inputs taken, `None` for any
+    fn arity(&self) -> Option<usize>;
+    fn outputs(&self, inputs: &[u64]) -> Vec<u64>;
+}
+
+/// What a model returns, one value per output pin
+pub trait Outputs {
+    fn into_values(self) -> Vec<u64>;
+}
+
+/// Random inputs mixed with edge cases: all zeros, all ones, single bits and
+/// the two's complement sign boundaries
+#[derive(Debug, Clone)]
+pub struct PropertyTest {
+    pub cases: usize,
+    pub seed: u64,
+}
+
+/// Inputs on which a chip disagrees with its model, shrunk to as few and as
+/// low bits as still disagree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub inputs: Vec<(String, u64)>,
+    pub expected: Vec<(String, u64)>,
+    pub actual: Vec<(String, u64)>,
+    /// Inputs as first found, before shrinking
+    pub original: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyError {
+    Clocked,
+    /// Input count of the chip and of the model
+    Arity(usize, usize),
+    /// Output count of the chip and of what the model returned
+    OutputCount(usize, usize),
+    Failed(Box<Failure>),
+}
+
+/// Runs a chip and its model side by side
+struct Runner<'a> {
+    chip: Chip,
+    model: &'a dyn Fn(&[u64]) -> Vec<u64>,
+    /// Widths of the inputs
+    widths: Vec<usize>,
+}
+
+// ============================================================================
+
+macro_rules! model {
+    ($count:expr, $($arg:ident),+) => {
+        impl<F, O> Model<($($arg,)+)> for F
+        where
+            F: Fn($($arg),+) -> O,
+            O: Outputs,
+        {
+            fn arity(&self) -> Option<usize> {
+                Some($count)
+            }
+            #[allow(unused_assignments)]
+            fn outputs(&self, inputs: &[u64]) -> Vec<u64> {
+                let mut i = 0;
+                self($({
+                    let value: $arg = inputs[i];
+                    i += 1;
+                    value
+                }),+)
+                .into_values()
+            }
+        }
+    };
+}
+
+model!(1, u64);
+model!(2, u64, u64);
+model!(3, u64, u64, u64);
+model!(4, u64, u64, u64, u64);
+
+impl<F, O> Model<Vec<u64>> for F
+where
+    F: Fn(&[u64]) -> O,
+    O: Outputs,
+{
+    fn arity(&self) -> Option<usize> {
+        None
+    }
+    fn outputs(&self, inputs: &[u64]) -> Vec<u64> {
+        self(inputs).into_values()
+    }
+}
+
+impl Outputs for u64 {
+    fn into_values(self) -> Vec<u64> {
+        vec![self]
+    }
+}
+
+impl Outputs for (u64, u64) {
+    fn into_values(self) -> Vec<u64> {
+        vec![self.0, self.1]
+    }
+}
+
+impl Outputs for (u64, u64, u64) {
+    fn into_values(self) -> Vec<u64> {
+        vec![self.0, self.1, self.2]
+    }
+}
+
+impl Outputs for Vec<u64> {
+    fn into_values(self) -> Vec<u64> {
+        self
+    }
+}
+
+impl Default for PropertyTest {
+    fn default() -> Self {
+        Self {
+            cases: 1000,
+            seed: 1,
+        }
+    }
+}
+
+impl PropertyTest {
+    /// Number of cases that passed, which is all of them when `Ok`
+    pub fn check<Args, M: Model<Args>>(
+        &self,
+        chip: &Chip,
+        model: M,
+    ) -> Result<usize, PropertyError> {
+        if chip.clocked {
+            return Err(PropertyError::Clocked);
+        }
+        let widths: Vec<usize> =
+            chip.pinlines.input.iter().map(|p| p.pins.len()).collect();
+        if let Some(arity) = model.arity() {
+            if arity != widths.len() {
+                return Err(PropertyError::Arity(widths.len(), arity));
+            }
+        }
+        let count = model.outputs(&vec![0; widths.len()]).len();
+        if count != chip.pinlines.output.len() {
+            return Err(PropertyError::OutputCount(
+                chip.pinlines.output.len(),
+                count,
+            ));
+        }
+        let outputs = |inputs: &[u64]| model.outputs(inputs);
+        let mut runner = Runner {
+            chip: chip.clone(),
+            model: &outputs,
+            widths,
+        };
+        let mut random = Random::new(self.seed);
+        for case in 0..self.cases {
+            let inputs = runner.generate(case, &mut random);
+            let (expected, actual) = runner.run(&inputs);
+            if expected != actual {
+                return Err(PropertyError::Failed(Box::new(
+                    runner.shrink(inputs),
+                )));
+            }
+        }
+        Ok(self.cases)
+    }
+}
+
+impl Runner<'_> {
+    /// All zeros first, then all ones, then each input either an edge case
+    /// or random
+    fn generate(&self, case: usize, random: &mut Random) -> Vec<u64> {
+        self.widths
+            .iter()
+            .map(|width| match case {
+                0 => 0,
+                1 => mask(*width),
+                _ if random.next_u64() & 1 == 0 => {
+                    let edges = edges(*width);
+                    edges[random.next_u64() as usize % edges.len()]
+                }
+                _ => random.next_u64() & mask(*width),
+            })
+            .collect()
+    }
+    /// Expected and actual outputs, cut to the widths of the output pins
+    fn run(&mut self, inputs: &[u64]) -> (Vec<u64>, Vec<u64>) {
+        for (pinline, value) in
+            self.chip.pinlines.input.iter_mut().zip(inputs.iter())
+        {
+            pinline.set_value(*value);
+        }
+        let actual: Vec<u64> = self
+            .chip
+            .evaluate()
+            .iter()
+            .map(Pinline::get_value)
+            .collect();
+        let expected = (self.model)(inputs)
+            .iter()
+            .zip(self.chip.pinlines.output.iter())
+            .map(|(value, pinline)| value & mask(pinline.pins.len()))
+            .collect();
+        (expected, actual)
+    }
+    /// Greedily takes any smaller input that still fails, until none does
+    fn shrink(&mut self, original: Vec<u64>) -> Failure {
+        let mut inputs = original.clone();
+        'shrinking: loop {
+            for i in 0..inputs.len() {
+                let value = inputs[i];
+                if value == 0 {
+                    continue;
+                }
+                let highest = 1 << (63 - value.leading_zeros());
+                let candidates = [
+                    0,
+                    value >> 1,
+                    value & (value - 1),
+                    value & !highest,
+                    value - 1,
+                ];
+                for candidate in candidates.iter().filter(|c| **c < value) {
+                    inputs[i] = *candidate;
+                    let (expected, actual) = self.run(&inputs);
+                    if expected != actual {
+                        continue 'shrinking;
+                    }
+                }
+                inputs[i] = value;
+            }
+            break;
+        }
+        let (expected, actual) = self.run(&inputs);
+        let pinlines = &self.chip.pinlines;
+        let named = |pinlines: &[Pinline], values: &[u64]| {
+            pinlines
+                .iter()
+                .map(|p| p.name.clone())
+                .zip(values.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        Failure {
+            inputs: named(&pinlines.input, &inputs),
+            expected: named(&pinlines.output, &expected),
+            actual: named(&pinlines.output, &actual),
+            original: named(&pinlines.input, &original),
+        }
+    }
+}
+
+fn mask(width: usize) -> u64 {
+    if width >= 64 {
+        !0
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// Zero, all ones, every single bit and the largest positive number
+fn edges(width: usize) -> Vec<u64> {
+    let mut edges = vec![0, mask(width)];
+    edges.extend((0..width.min(64)).map(|bit| 1 << bit));
+    if width > 1 {
+        edges.push(mask(width) >> 1);
+    }
+    edges
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pins = |pins: &[(String, u64)]| {
+            pins.iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        write!(
+            f,
+            "{} gives {}, expected {}",
+            pins(&self.inputs),
+            pins(&self.actual),
+            pins(&self.expected)
+        )
+    }
+}
+
+impl std::fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PropertyError::Clocked => {
+                write!(f, "only unclocked chips can be checked against a model")
+            }
+            PropertyError::Arity(chip, model) => write!(
+                f,
+                "the chip has {} inputs but the model takes {}",
+                chip, model
+            ),
+            PropertyError::OutputCount(chip, model) => write!(
+                f,
+                "the chip has {} outputs but the model gives {}",
+                chip, model
+            ),
+            PropertyError::Failed(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+impl std::error::Error for PropertyError {}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::load;
+
+    #[test]
+    fn matching_models() {
+        let test = PropertyTest {
+            cases: 200,
+            ..PropertyTest::default()
+        };
+        assert_eq!(test.check(&load("And16"), |a, b| a & b), Ok(200));
+        let mux = |a: u64, b: u64, sel: u64| if sel == 1 { b } else { a };
+        assert_eq!(test.check(&load("Mux"), mux), Ok(200));
+        let half_adder = |a: u64, b: u64| (a ^ b, a & b);
+        assert_eq!(test.check(&load("HalfAdder"), half_adder), Ok(200));
+        // Negation is left to the output mask
+        assert_eq!(test.check(&load("Inc16"), |x: u64| x + 1), Ok(200));
+    }
+    #[test]
+    fn slice_model() {
+        let alu = |i: &[u64]| {
+            let (mut x, mut y) = (i[0], i[1]);
+            if i[2] == 1 {
+                x = 0;
+            }
+            if i[3] == 1 {
+                x = !x;
+            }
+            if i[4] == 1 {
+                y = 0;
+            }
+            if i[5] == 1 {
+                y = !y;
+            }
+            let mut out = if i[6] == 1 { x.wrapping_add(y) } else { x & y };
+            if i[7] == 1 {
+                out = !out;
+            }
+            out &= 0xFFFF;
+            vec![out, (out == 0) as u64, out >> 15]
+        };
+        assert_eq!(PropertyTest::default().check(&load("ALU"), alu), Ok(1000));
+    }
+    #[test]
+    fn shrinks_failures() {
+        let result = PropertyTest::default()
+            .check(&load("Add16"), |a: u64, b: u64| a | b);
+        let failure = match result {
+            Err(PropertyError::Failed(failure)) => failure,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(
+            failure.inputs,
+            vec![("a".to_string(), 1), ("b".to_string(), 1)]
+        );
+        assert_eq!(failure.expected, vec![("out".to_string(), 1)]);
+        assert_eq!(failure.actual, vec![("out".to_string(), 2)]);
+        assert_eq!(failure.to_string(), "a=1 b=1 gives out=2, expected out=1");
+    }
+    #[test]
+    fn mismatched_models() {
+        let test = PropertyTest::default();
+        assert_eq!(
+            test.check(&load("And"), |a: u64| a),
+            Err(PropertyError::Arity(2, 1))
+        );
+        assert_eq!(
+            test.check(&load("And"), |a: u64, b: u64| (a, b)),
+            Err(PropertyError::OutputCount(1, 2))
+        );
+        assert_eq!(
+            test.check(&load("Bit"), |i: &[u64]| i[0]),
+            Err(PropertyError::Clocked)
+        );
+    }
+}
@@ -0,0 +1,48 @@
+/// Xorshift pseudo-random numbers, the same sequence for the same seed. Good
+/// enough for test inputs and register contents, nothing more.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+// ============================================================================
+
+impl Random {
+    /// Different seeds, 0 included, start from different states
+    pub fn new(seed: u64) -> Self {
+        // splitmix64's mixing, which takes every seed to a different number.
+        // xorshift gets stuck on zero, which only one seed mixes to.
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self {
+            state: (state ^ (state >> 31)).max(1),
+        }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats_for_a_seed() {
+        let numbers = |seed| {
+            let mut random = Random::new(seed);
+            (0..100).map(|_| random.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(numbers(7), numbers(7));
+        assert_ne!(numbers(7), numbers(8));
+        assert_ne!(numbers(0), numbers(1));
+        let ones: u32 = numbers(7).iter().map(|n| n.count_ones()).sum();
+        assert!((3000..3400).contains(&ones));
+    }
+}
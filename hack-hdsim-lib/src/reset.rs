@@ -0,0 +1,209 @@
+use crate::chip::{BuiltinChips, Chip, Pinlines};
+use crate::random::Random;
+
+/// What the registers, builtin DFF and Bit chips, hold when a chip is
+/// powered on
+#[derive(Debug, Clone, PartialEq)]
+pub enum PowerOn {
+    /// Every register holds 0
+    Zero,
+    /// Registers hold bits drawn from the seed
+    Random(u64),
+    /// Registers under each part path, like `Register` or `Register/Bit:3`,
+    /// hold the bits of the value from bit 0 in the order of the parts.
+    /// Registers not under any of the paths hold 0.
+    Values(Vec<(String, u64)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PowerOnError {
+    /// A path that doesn't lead to a part
+    UnknownPart(String),
+    /// A path to a part without registers
+    NoRegisters(String),
+}
+
+// ============================================================================
+
+impl Chip {
+    /// Every register back to 0 and every other pin recalculated from them
+    /// and the chip's inputs
+    pub fn reset(&mut self) {
+        self.power_on(&PowerOn::Zero).unwrap();
+    }
+    /// Clears every pin below the chip's inputs, sets the registers and
+    /// evaluates so that outputs follow from them
+    pub fn power_on(&mut self, power_on: &PowerOn) -> Result<(), PowerOnError> {
+        let mut registers = vec![false; count_registers(self)];
+        match power_on {
+            PowerOn::Zero => {}
+            PowerOn::Random(seed) => {
+                let mut random = Random::new(*seed);
+                for register in &mut registers {
+                    *register = random.next_u64() & 1 == 1;
+                }
+            }
+            PowerOn::Values(values) => {
+                for (path, value) in values {
+                    let (first, count) = find_registers(self, path)?;
+                    let bits = registers[first..first + count].iter_mut();
+                    for (bit, register) in bits.take(64).enumerate() {
+                        *register = (value >> bit) & 1 == 1;
+                    }
+                }
+            }
+        }
+        set_registers(self, &mut registers.into_iter());
+        if !self.is_builtin() {
+            self.evaluate();
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PowerOnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PowerOnError::UnknownPart(path) => write!(f, "no part {}", path),
+            PowerOnError::NoRegisters(path) => {
+                write!(f, "part {} has no registers", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PowerOnError {}
+
+fn is_register(chip: &Chip) -> bool {
+    matches!(chip.builtin_id, Some(BuiltinChips::DFF | BuiltinChips::Bit))
+}
+
+fn count_registers(chip: &Chip) -> usize {
+    match is_register(chip) {
+        true => 1,
+        false => chip.parts.iter().map(|p| count_registers(&p.chip)).sum(),
+    }
+}
+
+/// Index of the first register under the part and how many there are
+fn find_registers(
+    chip: &Chip,
+    path: &str,
+) -> Result<(usize, usize), PowerOnError> {
+    let unknown = || PowerOnError::UnknownPart(path.to_string());
+    let mut chip = chip;
+    let mut first = 0;
+    for segment in path.split('/') {
+        let part = chip.get_part(segment).ok_or_else(unknown)?;
+        let before = chip.parts.iter().take_while(|p| !std::ptr::eq(*p, part));
+        first += before.map(|p| count_registers(&p.chip)).sum::<usize>();
+        chip = &part.chip;
+    }
+    match count_registers(chip) {
+        0 => Err(PowerOnError::NoRegisters(path.to_string())),
+        count => Ok((first, count)),
+    }
+}
+
+/// Registers in the order of the parts, depth first. Parts are marked stale
+/// so the next evaluation doesn't skip them. RAM16K and Screen are cleared,
+/// ROM32K keeps its program and Keyboard its key.
+fn set_registers(chip: &mut Chip, registers: &mut impl Iterator<Item = bool>) {
+    if let Some(BuiltinChips::ROM32K | BuiltinChips::Keyboard) = chip.builtin_id
+    {
+        return;
+    }
+    if is_register(chip) {
+        let value = registers.next().unwrap();
+        for pinline in chip.pinlines.internal.iter_mut() {
+            pinline.pins[0] = value;
+        }
+        chip.pinlines.output[0].pins[0] = value;
+        return;
+    }
+    clear(&mut chip.pinlines.internal);
+    clear(&mut chip.pinlines.output);
+    for i in 0..chip.parts.len() {
+        let part = &mut chip.parts[i];
+        clear(&mut part.chip.pinlines.input);
+        set_registers(&mut part.chip, registers);
+        part.stale = true;
+        chip.pinlines.receive_output(&chip.parts[i]);
+    }
+}
+
+fn clear(pinlines: &mut Pinlines) {
+    for pinline in pinlines {
+        pinline.set_value(0);
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::{cycle, load};
+
+    #[test]
+    fn resets() {
+        let mut pc = load("PC");
+        cycle(&mut pc, &[("in", 1234), ("load", 1)]);
+        pc.reset();
+        assert_eq!(pc.pinlines.output[0].get_value(), 0);
+        // Inputs are kept, load is still set
+        assert_eq!(cycle(&mut pc, &[]), 1234);
+        assert_eq!(cycle(&mut pc, &[("load", 0), ("reset", 1)]), 0);
+    }
+    #[test]
+    fn explicit_values() {
+        let mut pc = load("PC");
+        let values = vec![("Register".to_string(), 40)];
+        pc.power_on(&PowerOn::Values(values)).unwrap();
+        assert_eq!(pc.pinlines.output[0].get_value(), 40);
+        assert_eq!(cycle(&mut pc, &[("inc", 1)]), 41);
+
+        let values = vec![("Register/Bit:3".to_string(), 1)];
+        pc.power_on(&PowerOn::Values(values)).unwrap();
+        assert_eq!(pc.pinlines.output[0].get_value(), 8);
+
+        let errors = [
+            PowerOnError::UnknownPart("Register/Bit:16".to_string()),
+            PowerOnError::NoRegisters("Inc16".to_string()),
+        ];
+        for error in errors.iter() {
+            let path = match error {
+                PowerOnError::UnknownPart(path)
+                | PowerOnError::NoRegisters(path) => path.clone(),
+            };
+            let values = PowerOn::Values(vec![(path, 1)]);
+            assert_eq!(pc.power_on(&values).as_ref(), Err(error));
+        }
+    }
+    #[test]
+    fn random_values() {
+        let mut values = Vec::new();
+        for seed in 0..8 {
+            let mut pc = load("PC");
+            pc.power_on(&PowerOn::Random(seed)).unwrap();
+            let value = pc.pinlines.output[0].get_value();
+            let register = pc.probe("Register/out").unwrap().get_value();
+            assert_eq!(value, register);
+            values.push(value);
+        }
+        // Every seed, 0 and 1 included, gives the PC a value of its own
+        values.sort();
+        values.dedup();
+        assert_eq!(values.len(), 8);
+
+        let mut dff = Chip::new_builtin(BuiltinChips::DFF);
+        let ones = (0..8)
+            .filter(|seed| {
+                dff.power_on(&PowerOn::Random(*seed)).unwrap();
+                let out = dff.pinlines.output[0].pins[0];
+                dff.probe("buffer1").unwrap().pins == vec![out] && out
+            })
+            .count();
+        assert!(ones > 0 && ones < 8);
+    }
+}
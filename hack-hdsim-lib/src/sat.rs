@@ -0,0 +1,430 @@
+/// A variable or its negation. Variable `v` is `2v`, its negation `2v + 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Lit(u32);
+
+/// Conflict-driven clause learning SAT solver, small enough for the miters
+/// built by equivalence checking
+#[derive(Debug, Clone, Default)]
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    /// Clauses by the literal they watch, one of their first two
+    watches: Vec<Vec<usize>>,
+    /// Per variable, `None` when unassigned
+    values: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    /// Clause that forced each variable, `None` for decisions
+    reasons: Vec<Option<usize>>,
+    activity: Vec<f64>,
+    /// Value each variable had last, tried first when deciding
+    phases: Vec<bool>,
+    trail: Vec<Lit>,
+    /// Where each decision level starts in `trail`
+    trail_levels: Vec<usize>,
+    /// Trail entries already propagated
+    propagated: usize,
+    increment: f64,
+    /// An empty clause was added
+    unsatisfiable: bool,
+}
+
+// ============================================================================
+
+impl Lit {
+    pub fn new(var: usize, value: bool) -> Self {
+        Lit(var as u32 * 2 + !value as u32)
+    }
+    pub fn var(self) -> usize {
+        (self.0 / 2) as usize
+    }
+    /// Whether the variable is `true` when the literal is
+    pub fn is_positive(self) -> bool {
+        self.0 & 1 == 0
+    }
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl std::ops::Not for Lit {
+    type Output = Lit;
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self {
+            increment: 1.0,
+            ..Self::default()
+        }
+    }
+    pub fn new_var(&mut self) -> usize {
+        self.values.push(None);
+        self.levels.push(0);
+        self.reasons.push(None);
+        self.activity.push(0.0);
+        self.phases.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.values.len() - 1
+    }
+    pub fn var_count(&self) -> usize {
+        self.values.len()
+    }
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        if self.unsatisfiable {
+            return;
+        }
+        self.backtrack(0);
+        let mut clause: Vec<Lit> = Vec::with_capacity(lits.len());
+        for lit in lits {
+            match self.lit_value(*lit) {
+                // Known at level 0, so for good
+                Some(true) => return,
+                Some(false) => {}
+                None if clause.contains(&!*lit) => return,
+                None if !clause.contains(lit) => clause.push(*lit),
+                None => {}
+            }
+        }
+        match clause.len() {
+            0 => self.unsatisfiable = true,
+            1 => {
+                self.assign(clause[0], None);
+                if self.propagate().is_some() {
+                    self.unsatisfiable = true;
+                }
+            }
+            _ => {
+                self.attach(clause);
+            }
+        }
+    }
+    /// Whether the clauses can all be true at once. Afterwards `value` gives
+    /// the assignment that does it.
+    pub fn solve(&mut self) -> bool {
+        self.solve_assuming(&[])
+    }
+    /// Like `solve` with the literals taken as true for this call only, so
+    /// that clauses can still be added after an unsatisfiable answer
+    pub fn solve_assuming(&mut self, assumptions: &[Lit]) -> bool {
+        if self.unsatisfiable {
+            return false;
+        }
+        self.backtrack(0);
+        let mut conflicts = 0;
+        let mut restart_at = 100;
+        loop {
+            if let Some(conflict) = self.propagate() {
+                if self.trail_levels.is_empty() {
+                    self.unsatisfiable = true;
+                    return false;
+                }
+                conflicts += 1;
+                let (learnt, level) = self.analyze(conflict);
+                self.backtrack(level);
+                let asserting = learnt[0];
+                if learnt.len() == 1 {
+                    self.assign(asserting, None);
+                } else {
+                    let clause = self.attach(learnt);
+                    self.assign(asserting, Some(clause));
+                }
+                self.increment /= 0.95;
+            } else {
+                if conflicts >= restart_at {
+                    conflicts = 0;
+                    restart_at += restart_at / 2;
+                    self.backtrack(0);
+                    continue;
+                }
+                // Assumptions are the first decisions, one per level
+                let level = self.trail_levels.len();
+                let next = match assumptions.get(level) {
+                    Some(lit) => match self.lit_value(*lit) {
+                        Some(true) => {
+                            self.trail_levels.push(self.trail.len());
+                            continue;
+                        }
+                        Some(false) => return false,
+                        None => Some(*lit),
+                    },
+                    None => self.pick(),
+                };
+                match next {
+                    Some(lit) => {
+                        self.trail_levels.push(self.trail.len());
+                        self.assign(lit, None);
+                    }
+                    None => return true,
+                }
+            }
+        }
+    }
+    /// Value in the last satisfying assignment, `false` if it didn't matter
+    pub fn value(&self, var: usize) -> bool {
+        self.values[var].unwrap_or(false)
+    }
+    fn lit_value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.var()].map(|v| v == lit.is_positive())
+    }
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let index = self.clauses.len();
+        self.watches[clause[0].index()].push(index);
+        self.watches[clause[1].index()].push(index);
+        self.clauses.push(clause);
+        index
+    }
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var();
+        self.values[var] = Some(lit.is_positive());
+        self.levels[var] = self.trail_levels.len();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+    /// Returns a clause that became false
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let falsified = !self.trail[self.propagated];
+            self.propagated += 1;
+            let watching = std::mem::take(&mut self.watches[falsified.index()]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+            for (i, &index) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[i..]);
+                    break;
+                }
+                let values = &self.values;
+                let clause = &mut self.clauses[index];
+                if clause[0] == falsified {
+                    clause.swap(0, 1);
+                }
+                let other = clause[0];
+                if values[other.var()] == Some(other.is_positive()) {
+                    kept.push(index);
+                    continue;
+                }
+                let replacement = (2..clause.len()).find(|&j| {
+                    let lit = clause[j];
+                    values[lit.var()] != Some(!lit.is_positive())
+                });
+                match replacement {
+                    Some(j) => {
+                        clause.swap(1, j);
+                        let lit = clause[1];
+                        self.watches[lit.index()].push(index);
+                    }
+                    None => {
+                        kept.push(index);
+                        if self.values[other.var()].is_some() {
+                            conflict = Some(index);
+                        } else {
+                            self.assign(other, Some(index));
+                        }
+                    }
+                }
+            }
+            self.watches[falsified.index()].extend(kept);
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+    /// First unique implication point: a learnt clause with exactly one
+    /// literal from the current level, put first, and the level to go back to
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let level = self.trail_levels.len();
+        let mut seen = vec![false; self.values.len()];
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut clause = conflict;
+        // A reason's first literal is the one it implied, already handled
+        let mut skip = 0;
+        let mut index = self.trail.len();
+        loop {
+            for j in skip..self.clauses[clause].len() {
+                let lit = self.clauses[clause][j];
+                let var = lit.var();
+                if seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+                seen[var] = true;
+                self.bump(var);
+                if self.levels[var] == level {
+                    pending += 1;
+                } else {
+                    learnt.push(lit);
+                }
+            }
+            // Latest literal of the current level that led here
+            let lit = loop {
+                index -= 1;
+                if seen[self.trail[index].var()] {
+                    break self.trail[index];
+                }
+            };
+            seen[lit.var()] = false;
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = !lit;
+                break;
+            }
+            clause = self.reasons[lit.var()].unwrap();
+            skip = 1;
+        }
+        // The second watch must be the literal assigned last
+        let mut back_to = 0;
+        if learnt.len() > 1 {
+            let (i, _) = learnt
+                .iter()
+                .enumerate()
+                .skip(1)
+                .max_by_key(|(_, l)| self.levels[l.var()])
+                .unwrap();
+            learnt.swap(1, i);
+            back_to = self.levels[learnt[1].var()];
+        }
+        (learnt, back_to)
+    }
+    fn bump(&mut self, var: usize) {
+        self.activity[var] += self.increment;
+        if self.activity[var] > 1e100 {
+            for activity in &mut self.activity {
+                *activity *= 1e-100;
+            }
+            self.increment *= 1e-100;
+        }
+    }
+    fn backtrack(&mut self, level: usize) {
+        if self.trail_levels.len() <= level {
+            return;
+        }
+        let start = self.trail_levels[level];
+        for lit in self.trail.drain(start..) {
+            let var = lit.var();
+            self.phases[var] = lit.is_positive();
+            self.values[var] = None;
+            self.reasons[var] = None;
+        }
+        self.trail_levels.truncate(level);
+        self.propagated = self.trail.len();
+    }
+    /// Most active unassigned variable with its saved phase
+    fn pick(&self) -> Option<Lit> {
+        (0..self.values.len())
+            .filter(|v| self.values[*v].is_none())
+            .max_by(|a, b| self.activity[*a].total_cmp(&self.activity[*b]))
+            .map(|v| Lit::new(v, self.phases[v]))
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random::Random;
+
+    /// `pigeons` into `holes`, one variable per pigeon and hole
+    fn pigeonhole(pigeons: usize, holes: usize) -> Solver {
+        let mut solver = Solver::new();
+        let vars: Vec<Vec<usize>> = (0..pigeons)
+            .map(|_| (0..holes).map(|_| solver.new_var()).collect())
+            .collect();
+        for pigeon in &vars {
+            let lits: Vec<Lit> =
+                pigeon.iter().map(|v| Lit::new(*v, true)).collect();
+            solver.add_clause(&lits);
+        }
+        for hole in 0..holes {
+            for (a, first) in vars.iter().enumerate() {
+                for second in &vars[a + 1..] {
+                    solver.add_clause(&[
+                        Lit::new(first[hole], false),
+                        Lit::new(second[hole], false),
+                    ]);
+                }
+            }
+        }
+        solver
+    }
+
+    #[test]
+    fn literals() {
+        let lit = Lit::new(3, true);
+        assert_eq!(lit.var(), 3);
+        assert!(lit.is_positive());
+        assert!(!(!lit).is_positive());
+        assert_eq!(!!lit, lit);
+    }
+    #[test]
+    fn satisfiable() {
+        let mut solver = pigeonhole(5, 5);
+        assert!(solver.solve());
+        for hole in 0..5 {
+            let filled = (0..5).filter(|p| solver.value(p * 5 + hole)).count();
+            assert!(filled <= 1);
+        }
+    }
+    #[test]
+    fn unsatisfiable() {
+        assert!(!pigeonhole(6, 5).solve());
+        let mut solver = Solver::new();
+        let a = solver.new_var();
+        solver.add_clause(&[Lit::new(a, true)]);
+        solver.add_clause(&[Lit::new(a, false)]);
+        assert!(!solver.solve());
+    }
+    #[test]
+    fn assumptions() {
+        let mut solver = Solver::new();
+        let a = Lit::new(solver.new_var(), true);
+        let b = Lit::new(solver.new_var(), true);
+        solver.add_clause(&[!a, b]);
+        assert!(!solver.solve_assuming(&[a, !b]));
+        assert!(solver.solve_assuming(&[a]));
+        assert!(solver.value(b.var()));
+        solver.add_clause(&[!b]);
+        assert!(!solver.solve_assuming(&[a]));
+        assert!(solver.solve());
+        assert!(!solver.value(a.var()));
+    }
+    #[test]
+    fn random_matches_brute_force() {
+        let mut random = Random::new(7);
+        let mut next = |n: u64| random.next_u64() % n;
+        for _ in 0..300 {
+            let clauses: Vec<Vec<Lit>> = (0..43)
+                .map(|_| {
+                    (0..3)
+                        .map(|_| Lit::new(next(10) as usize, next(2) == 0))
+                        .collect()
+                })
+                .collect();
+            let satisfied = |assignment: u32| {
+                clauses.iter().all(|c| {
+                    c.iter().any(|l| {
+                        (assignment >> l.var()) & 1 == l.is_positive() as u32
+                    })
+                })
+            };
+            let mut solver = Solver::new();
+            for _ in 0..10 {
+                solver.new_var();
+            }
+            for clause in &clauses {
+                solver.add_clause(clause);
+            }
+            let expected = (0..1 << 10).any(satisfied);
+            assert_eq!(solver.solve(), expected);
+            if expected {
+                let model = (0..10)
+                    .fold(0, |acc, v| acc | (solver.value(v) as u32) << v);
+                assert!(satisfied(model));
+            }
+        }
+    }
+}
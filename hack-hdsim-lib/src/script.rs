@@ -0,0 +1,1049 @@
+use std::path::{Path, PathBuf};
+
+use crate::chip::{Chip, PinlinesMethods};
+use crate::loader::{ChipLoader, LoadError};
+use crate::logic::{self, Logic, LogicSimulator};
+
+/// A command of a .tst script and the line it starts on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub command: Command,
+    pub nline: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Load(String),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(PinSelect, i64),
+    Eval,
+    Tick,
+    Tock,
+    Output,
+    Repeat(usize, Vec<Statement>),
+    Echo(String),
+    ClearEcho,
+}
+
+/// A pin or part of one, `a`, `a[3]` or `a[0..7]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinSelect {
+    pub name: String,
+    /// Inclusive, like `PinRef::range`
+    pub range: Option<(usize, usize)>,
+}
+
+/// `name%B1.16.1` in an output list
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub pin: PinSelect,
+    /// `None` when the script gives no format
+    pub format: Option<ColumnFormat>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColumnFormat {
+    /// One of `B`, `X`, `D` or `S`
+    pub kind: char,
+    pub pad_left: usize,
+    pub len: usize,
+    pub pad_right: usize,
+}
+
+/// How a script simulates the chip it loads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Boolean,
+    /// With `LogicSimulator`, so that output shows unknown bits as X and
+    /// floating ones as Z. Output columns can then also name part pins like
+    /// `Bit:1/DFF/out`.
+    FourValued,
+}
+
+/// What running a script produced
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TestOutcome {
+    /// Lines written by `output-list` and `output`
+    pub output: Vec<String>,
+    /// Resolved `output-file`, the script runner doesn't write it
+    pub output_file: Option<PathBuf>,
+    pub compare_to: Option<PathBuf>,
+    /// Every line that differs from the compare file
+    pub mismatches: Vec<Mismatch>,
+    pub echo: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Mismatch {
+    /// Starting from 1, like in an editor
+    pub line: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ScriptError {
+    #[cfg_attr(feature = "serde", serde(rename = "line"))]
+    pub nline: i32,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub kind: ScriptErrorKind,
+}
+
+/// Serialised like `ParseErrorKind`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "kind", content = "detail", rename_all = "snake_case")
+)]
+pub enum ScriptErrorKind {
+    Syntax(String),
+    UnknownCommand(String),
+    Unsupported(String),
+    BadValue(String),
+    BadFormat(String),
+    UnknownPin(String),
+    NoChip,
+    Load(LoadError),
+    ReadFailed { file: PathBuf, reason: String },
+}
+
+// ============================================================================
+
+pub fn parse_script(contents: &str) -> Result<Vec<Statement>, ScriptError> {
+    let mut parser = ScriptParser {
+        lexemes: lex(contents)?,
+        pos: 0,
+    };
+    parser.statements(false)
+}
+
+/// Runs a script, `dir` is where file names in it are relative to
+pub fn run_script(
+    contents: &str,
+    dir: Option<&Path>,
+) -> Result<TestOutcome, ScriptError> {
+    run(contents, dir, None, Mode::Boolean)
+}
+
+/// Runs a script on an already loaded chip, for scripts without `load`
+pub fn run_with_chip(
+    contents: &str,
+    chip: Chip,
+) -> Result<TestOutcome, ScriptError> {
+    run(contents, None, Some(chip), Mode::Boolean)
+}
+
+fn run(
+    contents: &str,
+    dir: Option<&Path>,
+    chip: Option<Chip>,
+    mode: Mode,
+) -> Result<TestOutcome, ScriptError> {
+    let statements = parse_script(contents)?;
+    let mut runner = Runner {
+        dir,
+        chip,
+        mode,
+        logic: None,
+        time: 0,
+        ticked: false,
+        columns: Vec::new(),
+        compare_line: 0,
+        outcome: TestOutcome::default(),
+    };
+    runner.execute(&statements)?;
+    runner.compare()?;
+    Ok(runner.outcome)
+}
+
+pub fn run_file(path: &Path) -> Result<TestOutcome, ScriptError> {
+    run_file_with(path, Mode::Boolean)
+}
+
+pub fn run_file_with(
+    path: &Path,
+    mode: Mode,
+) -> Result<TestOutcome, ScriptError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| ScriptError {
+        nline: 0,
+        kind: ScriptErrorKind::ReadFailed {
+            file: path.to_path_buf(),
+            reason: e.to_string(),
+        },
+    })?;
+    run(contents.as_str(), path.parent(), None, mode)
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl std::fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: ", self.nline)?;
+        use ScriptErrorKind::*;
+        match &self.kind {
+            Syntax(expected) => write!(f, "expected {}", expected),
+            UnknownCommand(name) => write!(f, "unknown command {}", name),
+            Unsupported(what) => write!(f, "{} is not supported", what),
+            BadValue(value) => write!(f, "bad value {}", value),
+            BadFormat(column) => write!(f, "bad output format {}", column),
+            UnknownPin(pin) => write!(f, "chip has no pin {}", pin),
+            NoChip => write!(f, "no chip loaded"),
+            Load(e) => write!(f, "could not load chip: {}", e),
+            ReadFailed { file, reason } => {
+                write!(f, "could not read {}: {}", file.display(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl std::fmt::Display for PinSelect {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.range {
+            Some((from, to)) if from == to => {
+                write!(f, "{}[{}]", self.name, from)
+            }
+            Some((from, to)) => write!(f, "{}[{}..{}]", self.name, from, to),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "comparison failure at line {}", self.line)?;
+        writeln!(f, "expected: {}", self.expected)?;
+        write!(f, "actual:   {}", self.actual)
+    }
+}
+
+impl PinSelect {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (name, range) = match text.find('[') {
+            Some(open) => {
+                let inner = text[open + 1..].strip_suffix(']')?;
+                let range = match inner.find("..") {
+                    Some(dots) => (
+                        inner[..dots].parse().ok()?,
+                        inner[dots + 2..].parse().ok()?,
+                    ),
+                    None => {
+                        let i = inner.parse().ok()?;
+                        (i, i)
+                    }
+                };
+                (&text[..open], Some(range))
+            }
+            None => (text, None),
+        };
+        if name.is_empty() || range.is_some_and(|(from, to)| from > to) {
+            return None;
+        }
+        Some(Self {
+            name: name.to_string(),
+            range,
+        })
+    }
+    /// Indices in a pin of the given width, `None` if out of range
+    pub fn indices(&self, width: usize) -> Option<Vec<usize>> {
+        match self.range {
+            Some((_, to)) if to >= width => None,
+            Some((from, to)) => Some((from..=to).collect()),
+            None => Some((0..width).collect()),
+        }
+    }
+    /// Value of any pin of the chip, `None` if there is no such pin
+    pub fn read(&self, chip: &Chip) -> Option<u64> {
+        let pinline = chip.pinlines.get_pinline(self.name.as_str())?;
+        let indices = self.indices(pinline.pins.len())?;
+        Some(
+            indices.iter().enumerate().fold(0, |acc, (bit, i)| {
+                acc | ((pinline.pins[*i] as u64) << bit)
+            }),
+        )
+    }
+    /// Sets an input pin, negative values are two's complement.
+    /// `None` if the chip has no such input.
+    pub fn write(&self, chip: &mut Chip, value: i64) -> Option<()> {
+        let pinline =
+            chip.pinlines.input.get_pinline_mut(self.name.as_str())?;
+        let indices = self.indices(pinline.pins.len())?;
+        for (bit, i) in indices.into_iter().enumerate() {
+            pinline.pins[i] = bit < 64 && (value >> bit) & 1 == 1;
+        }
+        Some(())
+    }
+}
+
+impl Column {
+    fn parse(text: &str) -> Option<Self> {
+        let (pin, format) = match text.find('%') {
+            Some(percent) => (&text[..percent], Some(&text[percent + 1..])),
+            None => (text, None),
+        };
+        let format = match format {
+            Some(format) => {
+                let kind = format.chars().next()?;
+                if !"BXDS".contains(kind) {
+                    return None;
+                }
+                let numbers = format[1..]
+                    .split('.')
+                    .map(|n| n.parse().ok())
+                    .collect::<Option<Vec<usize>>>()?;
+                if numbers.len() != 3 {
+                    return None;
+                }
+                Some(ColumnFormat {
+                    kind,
+                    pad_left: numbers[0],
+                    len: numbers[1],
+                    pad_right: numbers[2],
+                })
+            }
+            None => None,
+        };
+        Some(Self {
+            pin: PinSelect::parse(pin)?,
+            format,
+        })
+    }
+    pub(crate) fn header(&self, format: &ColumnFormat) -> String {
+        let space = format.pad_left + format.len + format.pad_right;
+        let mut name = self.pin.to_string();
+        name.truncate(space);
+        let left = (space - name.len()) / 2;
+        let right = space - name.len() - left;
+        format!("{}{}{}", " ".repeat(left), name, " ".repeat(right))
+    }
+}
+
+impl ColumnFormat {
+    /// `value` holds `width` bits
+    pub(crate) fn cell(&self, value: u64, width: usize) -> String {
+        let len = self.len;
+        let text = match self.kind {
+            'B' => format!("{:0len$b}", value, len = len),
+            'X' => format!("{:0len$X}", value, len = len),
+            'D' if width == 16 => {
+                format!("{:>len$}", value as u16 as i16, len = len)
+            }
+            'D' => format!("{:>len$}", value, len = len),
+            _ => format!("{:<len$}", value, len = len),
+        };
+        self.pad(last_chars(text, len))
+    }
+    /// Bits as X or Z, hexadecimal digits as X unless all their bits are Z
+    /// and numbers as a single X or Z
+    pub(crate) fn unknown_cell(&self, pins: &[Logic]) -> String {
+        let len = self.len;
+        let unknown = |pins: &[Logic]| match pins.iter().all(|p| *p == Logic::Z)
+        {
+            true => 'Z',
+            false => 'X',
+        };
+        let text = match self.kind {
+            'B' => format!("{:0>len$}", logic::bits(pins), len = len),
+            'X' => {
+                let digits: String = pins
+                    .chunks(4)
+                    .rev()
+                    .map(|digit| match logic::value(digit) {
+                        Some(value) => format!("{:X}", value),
+                        None => unknown(digit).to_string(),
+                    })
+                    .collect();
+                format!("{:0>len$}", digits, len = len)
+            }
+            'D' => format!("{:>len$}", unknown(pins), len = len),
+            _ => format!("{:<len$}", unknown(pins), len = len),
+        };
+        self.pad(last_chars(text, len))
+    }
+    fn pad(&self, text: String) -> String {
+        format!(
+            "{}{}{}",
+            " ".repeat(self.pad_left),
+            text,
+            " ".repeat(self.pad_right)
+        )
+    }
+}
+
+fn last_chars(text: String, len: usize) -> String {
+    let count = text.chars().count();
+    if count <= len {
+        return text;
+    }
+    text.chars().skip(count - len).collect()
+}
+
+/// `5`, `-1`, `%B101`, `%XFF` or `%D5`
+pub fn parse_value(text: &str) -> Option<i64> {
+    let (radix, digits) = match text.get(..2) {
+        Some("%B") => (2, &text[2..]),
+        Some("%X") => (16, &text[2..]),
+        Some("%D") => (10, &text[2..]),
+        _ => (10, text),
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Word(String),
+    Text(String),
+    Symbol(char),
+}
+
+fn lex(contents: &str) -> Result<Vec<(Lexeme, i32)>, ScriptError> {
+    let mut lexemes = Vec::new();
+    let mut nline = 1;
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => nline += 1,
+            c if c.is_whitespace() => {}
+            ',' | ';' | '{' | '}' => lexemes.push((Lexeme::Symbol(c), nline)),
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                nline += 1;
+                            }
+                            last = c;
+                        }
+                        None => break,
+                    }
+                }
+            }
+            '"' => {
+                let start = nline;
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') | None => {
+                            return Err(ScriptError {
+                                nline: start,
+                                kind: ScriptErrorKind::Syntax(
+                                    "closing \"".to_string(),
+                                ),
+                            })
+                        }
+                        Some(c) => text.push(c),
+                    }
+                }
+                lexemes.push((Lexeme::Text(text), start));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || ",;{}\"".contains(*c) {
+                        break;
+                    }
+                    word.push(*c);
+                    chars.next();
+                }
+                lexemes.push((Lexeme::Word(word), nline));
+            }
+        }
+    }
+    Ok(lexemes)
+}
+
+struct ScriptParser {
+    lexemes: Vec<(Lexeme, i32)>,
+    pos: usize,
+}
+
+impl ScriptParser {
+    fn peek(&self) -> Option<&Lexeme> {
+        self.lexemes.get(self.pos).map(|l| &l.0)
+    }
+    fn nline(&self) -> i32 {
+        self.lexemes
+            .get(self.pos)
+            .or_else(|| self.lexemes.last())
+            .map_or(1, |l| l.1)
+    }
+    fn error(&self, kind: ScriptErrorKind) -> ScriptError {
+        ScriptError {
+            nline: self.nline(),
+            kind,
+        }
+    }
+    fn expected(&self, what: &str) -> ScriptError {
+        self.error(ScriptErrorKind::Syntax(what.to_string()))
+    }
+    fn word(&mut self, what: &str) -> Result<String, ScriptError> {
+        match self.peek() {
+            Some(Lexeme::Word(word)) => {
+                let word = word.clone();
+                self.pos += 1;
+                Ok(word)
+            }
+            _ => Err(self.expected(what)),
+        }
+    }
+    fn symbol(&mut self, symbol: char) -> Result<(), ScriptError> {
+        if self.peek() != Some(&Lexeme::Symbol(symbol)) {
+            return Err(self.expected(format!("'{}'", symbol).as_str()));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+    fn statements(
+        &mut self,
+        in_block: bool,
+    ) -> Result<Vec<Statement>, ScriptError> {
+        let mut statements = Vec::new();
+        loop {
+            let nline = self.nline();
+            match self.peek() {
+                None if in_block => return Err(self.expected("'}'")),
+                None => break,
+                Some(Lexeme::Symbol('}')) if in_block => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(Lexeme::Word(word)) if word == "repeat" => {
+                    self.pos += 1;
+                    let count = match self.peek() {
+                        Some(Lexeme::Word(_)) => {
+                            let count = self.word("repeat count")?;
+                            count.parse().map_err(|_| {
+                                self.error(ScriptErrorKind::BadValue(count))
+                            })?
+                        }
+                        _ => {
+                            return Err(self.error(
+                                ScriptErrorKind::Unsupported(
+                                    "repeat without a count".to_string(),
+                                ),
+                            ))
+                        }
+                    };
+                    self.symbol('{')?;
+                    let body = self.statements(true)?;
+                    statements.push(Statement {
+                        command: Command::Repeat(count, body),
+                        nline,
+                    });
+                }
+                Some(Lexeme::Word(_)) => {
+                    let command = self.command()?;
+                    statements.push(Statement { command, nline });
+                    match self.peek() {
+                        Some(Lexeme::Symbol(','))
+                        | Some(Lexeme::Symbol(';')) => self.pos += 1,
+                        Some(Lexeme::Symbol('}')) if in_block => {}
+                        _ => return Err(self.expected("',' or ';'")),
+                    }
+                }
+                _ => return Err(self.expected("command")),
+            }
+        }
+        Ok(statements)
+    }
+    fn command(&mut self) -> Result<Command, ScriptError> {
+        let name = self.word("command")?;
+        let command = match name.as_str() {
+            "load" => match self.peek() {
+                Some(Lexeme::Word(_)) => Command::Load(self.word("file")?),
+                _ => {
+                    return Err(self.error(ScriptErrorKind::Unsupported(
+                        "load without a file name".to_string(),
+                    )))
+                }
+            },
+            "output-file" => Command::OutputFile(self.word("file")?),
+            "compare-to" => Command::CompareTo(self.word("file")?),
+            "output-list" => {
+                let mut columns = Vec::new();
+                while let Some(Lexeme::Word(_)) = self.peek() {
+                    let text = self.word("column")?;
+                    let column =
+                        Column::parse(text.as_str()).ok_or_else(|| {
+                            self.error(ScriptErrorKind::BadFormat(text))
+                        })?;
+                    columns.push(column);
+                }
+                Command::OutputList(columns)
+            }
+            "set" => {
+                let pin = self.word("pin")?;
+                let pin = PinSelect::parse(pin.as_str()).ok_or_else(|| {
+                    self.error(ScriptErrorKind::UnknownPin(pin))
+                })?;
+                let value = self.word("value")?;
+                let value = parse_value(value.as_str()).ok_or_else(|| {
+                    self.error(ScriptErrorKind::BadValue(value))
+                })?;
+                Command::Set(pin, value)
+            }
+            "eval" => Command::Eval,
+            "tick" => Command::Tick,
+            "tock" => Command::Tock,
+            "output" => Command::Output,
+            "echo" => match self.peek() {
+                Some(Lexeme::Text(text)) => {
+                    let text = text.clone();
+                    self.pos += 1;
+                    Command::Echo(text)
+                }
+                _ => return Err(self.expected("text in quotes")),
+            },
+            "clear-echo" => Command::ClearEcho,
+            "while" | "breakpoint" | "clear-breakpoints" => {
+                return Err(self.error(ScriptErrorKind::Unsupported(name)))
+            }
+            _ => return Err(self.error(ScriptErrorKind::UnknownCommand(name))),
+        };
+        Ok(command)
+    }
+}
+
+struct Runner<'a> {
+    dir: Option<&'a Path>,
+    chip: Option<Chip>,
+    mode: Mode,
+    /// Runs the chip in four-valued mode, the chip then only holds inputs
+    logic: Option<LogicSimulator>,
+    time: usize,
+    /// Between a tick and its tock, shown as `n+`
+    ticked: bool,
+    columns: Vec<Column>,
+    /// Where `compare-to` is, for errors reading the file
+    compare_line: i32,
+    outcome: TestOutcome,
+}
+
+impl<'a> Runner<'a> {
+    fn path(&self, file: &str) -> PathBuf {
+        match self.dir {
+            Some(dir) => dir.join(file),
+            None => PathBuf::from(file),
+        }
+    }
+    fn chip(&mut self, nline: i32) -> Result<&mut Chip, ScriptError> {
+        self.chip.as_mut().ok_or(ScriptError {
+            nline,
+            kind: ScriptErrorKind::NoChip,
+        })
+    }
+    fn execute(&mut self, statements: &[Statement]) -> Result<(), ScriptError> {
+        for statement in statements {
+            self.command(statement)?;
+        }
+        Ok(())
+    }
+    fn command(&mut self, statement: &Statement) -> Result<(), ScriptError> {
+        let nline = statement.nline;
+        let error = |kind| ScriptError { nline, kind };
+        match &statement.command {
+            Command::Load(file) => {
+                let path = self.path(file);
+                let mut loader = ChipLoader::for_file(&path);
+                let chip = loader
+                    .load_file(&path)
+                    .map_err(|e| error(ScriptErrorKind::Load(e)))?;
+                if self.mode == Mode::FourValued {
+                    if let Some(part) = chip.memory_part() {
+                        return Err(error(ScriptErrorKind::Unsupported(
+                            format!("{} in four-valued mode", part),
+                        )));
+                    }
+                    self.logic = Some(LogicSimulator::new(&chip));
+                }
+                self.chip = Some(chip);
+                self.time = 0;
+                self.ticked = false;
+            }
+            Command::OutputFile(file) => {
+                self.outcome.output_file = Some(self.path(file))
+            }
+            Command::CompareTo(file) => {
+                self.outcome.compare_to = Some(self.path(file));
+                self.compare_line = nline;
+            }
+            Command::OutputList(columns) => {
+                self.columns = columns.clone();
+                let mut line = String::from("|");
+                for column in &self.columns {
+                    let format = self.format(column, nline)?;
+                    line.push_str(column.header(&format).as_str());
+                    line.push('|');
+                }
+                self.outcome.output.push(line);
+            }
+            Command::Set(pin, value) => {
+                let chip = self.chip(nline)?;
+                pin.write(chip, *value).ok_or_else(|| {
+                    error(ScriptErrorKind::UnknownPin(pin.to_string()))
+                })?;
+                let input = chip.pinlines.input.get_pinline(&pin.name);
+                let value = input.unwrap().get_value() as i64;
+                if let Some(logic) = &mut self.logic {
+                    logic.set_input(&pin.name, value);
+                }
+            }
+            Command::Eval => match &mut self.logic {
+                Some(logic) => logic.evaluate(),
+                None => {
+                    self.chip(nline)?.evaluate();
+                }
+            },
+            Command::Tick => {
+                match &mut self.logic {
+                    Some(logic) => logic.tick(),
+                    None => self.chip(nline)?.tick(),
+                }
+                self.ticked = true;
+            }
+            Command::Tock => {
+                match &mut self.logic {
+                    Some(logic) => logic.tock(),
+                    None => {
+                        self.chip(nline)?.tock();
+                    }
+                }
+                self.time += 1;
+                self.ticked = false;
+            }
+            Command::Output => {
+                let mut line = String::from("|");
+                for column in &self.columns {
+                    line.push_str(self.cell(column, nline)?.as_str());
+                    line.push('|');
+                }
+                self.outcome.output.push(line);
+            }
+            Command::Repeat(count, body) => {
+                for _ in 0..*count {
+                    self.execute(body)?;
+                }
+            }
+            Command::Echo(text) => self.outcome.echo.push(text.clone()),
+            Command::ClearEcho => self.outcome.echo.clear(),
+        }
+        Ok(())
+    }
+    /// Width of a column's pin, `None` for `time`
+    fn width(
+        &self,
+        pin: &PinSelect,
+        nline: i32,
+    ) -> Result<Option<Vec<usize>>, ScriptError> {
+        if pin.name == "time" {
+            return Ok(None);
+        }
+        let unknown = || ScriptError {
+            nline,
+            kind: ScriptErrorKind::UnknownPin(pin.to_string()),
+        };
+        let chip = self.chip.as_ref().ok_or(ScriptError {
+            nline,
+            kind: ScriptErrorKind::NoChip,
+        })?;
+        let width = match &self.logic {
+            Some(logic) => logic.probe(&pin.name).map(|pins| pins.len()),
+            None => chip
+                .pinlines
+                .get_pinline(pin.name.as_str())
+                .map(|p| p.pins.len()),
+        };
+        let indices = pin
+            .indices(width.ok_or_else(unknown)?)
+            .ok_or_else(unknown)?;
+        Ok(Some(indices))
+    }
+    /// Pins default to binary and time to text
+    fn format(
+        &self,
+        column: &Column,
+        nline: i32,
+    ) -> Result<ColumnFormat, ScriptError> {
+        if let Some(format) = column.format {
+            return Ok(format);
+        }
+        let format = match self.width(&column.pin, nline)? {
+            Some(indices) => ColumnFormat {
+                kind: 'B',
+                pad_left: 1,
+                len: indices.len(),
+                pad_right: 1,
+            },
+            None => ColumnFormat {
+                kind: 'S',
+                pad_left: 1,
+                len: 4,
+                pad_right: 1,
+            },
+        };
+        Ok(format)
+    }
+    fn cell(&self, column: &Column, nline: i32) -> Result<String, ScriptError> {
+        let format = self.format(column, nline)?;
+        let indices = match self.width(&column.pin, nline)? {
+            Some(indices) => indices,
+            None => {
+                let time = format!(
+                    "{}{}",
+                    self.time,
+                    if self.ticked { "+" } else { "" }
+                );
+                let text = format!("{:<len$}", time, len = format.len);
+                return Ok(format.pad(last_chars(text, format.len)));
+            }
+        };
+        if let Some(logic) = &self.logic {
+            let pins = logic.probe(&column.pin.name).unwrap();
+            let pins: Vec<Logic> = indices.iter().map(|i| pins[*i]).collect();
+            return Ok(match logic::value(&pins) {
+                Some(value) => format.cell(value, pins.len()),
+                None => format.unknown_cell(&pins),
+            });
+        }
+        let value = column.pin.read(self.chip.as_ref().unwrap()).unwrap();
+        Ok(format.cell(value, indices.len()))
+    }
+    /// `*` in the compare file matches any character
+    fn compare(&mut self) -> Result<(), ScriptError> {
+        let path = match &self.outcome.compare_to {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let expected =
+            std::fs::read_to_string(path).map_err(|e| ScriptError {
+                nline: self.compare_line,
+                kind: ScriptErrorKind::ReadFailed {
+                    file: path.clone(),
+                    reason: e.to_string(),
+                },
+            })?;
+        let expected: Vec<&str> =
+            expected.lines().map(|l| l.trim_end()).collect();
+        let actual = &self.outcome.output;
+        for i in 0..expected.len().max(actual.len()) {
+            let expected = expected.get(i).copied().unwrap_or("");
+            let actual = actual.get(i).map_or("", |l| l.as_str());
+            let matches = expected.len() == actual.len()
+                && expected
+                    .chars()
+                    .zip(actual.chars())
+                    .all(|(e, a)| e == '*' || e == a);
+            if !matches {
+                self.outcome.mismatches.push(Mismatch {
+                    line: i + 1,
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::temp_dir;
+    #[test]
+    fn parse() {
+        let statements = parse_script(
+            "// comment
+            load And.hdl, /* block */ output-list a%B3.1.3 b[0..1]%X1.2.1;
+            set a %B1, set b -1, repeat 2 { tick, tock }
+            echo \"hi\";",
+        )
+        .unwrap();
+        assert_eq!(statements.len(), 6);
+        assert_eq!(statements[0].command, Command::Load("And.hdl".into()));
+        assert_eq!(
+            statements[3].command,
+            Command::Set(
+                PinSelect {
+                    name: "b".to_string(),
+                    range: None
+                },
+                -1
+            )
+        );
+        assert_eq!(statements[4].nline, 3);
+        if let Command::OutputList(columns) = &statements[1].command {
+            assert_eq!(columns[1].pin.to_string(), "b[0..1]");
+            assert_eq!(columns[1].format.unwrap().kind, 'X');
+        } else {
+            panic!("expected output-list");
+        }
+        let err = parse_script("eval,\nwhile out = 0 {}").unwrap_err();
+        assert_eq!(err.nline, 2);
+        assert_eq!(err.kind, ScriptErrorKind::Unsupported("while".to_string()));
+        let err = parse_script("eval tick;").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: expected ',' or ';'");
+    }
+    #[test]
+    fn and_passes() {
+        let outcome = run_file(Path::new("../test-hdl/And.tst")).unwrap();
+        assert!(outcome.passed(), "{:?}", outcome.mismatches);
+        assert_eq!(outcome.output[0], "|   a   |   b   |  out  |");
+        assert_eq!(
+            outcome.output_file,
+            Some(PathBuf::from("../test-hdl/And.out"))
+        );
+    }
+    #[test]
+    fn bit_passes() {
+        let outcome = run_file(Path::new("../test-hdl/Bit.tst")).unwrap();
+        assert!(outcome.passed(), "{:?}", outcome.mismatches);
+    }
+    #[test]
+    fn formats() {
+        let script = "load Not16.hdl,
+            output-list time%S1.4.1 in%D1.6.1 in%X1.4.1 in[0..3]%B1.4.1 out;
+            set in %XFFFE, tick, output, tock, output;";
+        let dir = temp_dir("script-formats");
+        std::fs::write(
+            dir.join("Not16.hdl"),
+            "CHIP Not16 { IN in[16]; OUT out[16]; PARTS:
+            Not(in=in[0], out=out[0]); }",
+        )
+        .unwrap();
+        let outcome = run_script(script, Some(dir.as_path())).unwrap();
+        assert_eq!(
+            outcome.output,
+            vec![
+                "| time |   in   |  in  |in[0..|       out        |"
+                    .to_string(),
+                "| 0+   |     -2 | FFFE | 1110 | 0000000000000001 |"
+                    .to_string(),
+                "| 1    |     -2 | FFFE | 1110 | 0000000000000001 |"
+                    .to_string(),
+            ]
+        );
+    }
+    #[test]
+    fn four_valued() {
+        let dir = temp_dir("script-four-valued");
+        std::fs::write(
+            dir.join("Pair.hdl"),
+            "CHIP Pair { IN in[2], load; OUT out[2], half[8]; PARTS:
+            Bit(in=in[0], load=load, out=out[0]);
+            Bit(in=in[1], load=load, out=out[1]);
+            Not(in=load, out=half[0]); }",
+        )
+        .unwrap();
+        let path = dir.join("Pair.tst");
+        std::fs::write(
+            &path,
+            "load Pair.hdl,
+            output-list out%B1.2.1 out%D1.2.1 half%X1.2.1 half[0] half[1];
+            set in 2, eval, output;
+            set load 1, tick, tock, output;",
+        )
+        .unwrap();
+        let outcome = run_file_with(&path, Mode::FourValued).unwrap();
+        assert_eq!(
+            outcome.output,
+            vec![
+                "|out |out |half|hal|hal|",
+                "| XX |  X | ZX | 1 | Z |",
+                "| 10 |  2 | ZX | 0 | Z |"
+            ]
+        );
+        let outcome = run_file(&path).unwrap();
+        assert_eq!(outcome.output[1], "| 00 |  0 | 01 | 1 | 0 |");
+
+        std::fs::write(
+            &path,
+            "load Pair.hdl, output-list Bit:1/out Bit/load half[1..2]%B1.2.1;
+            set in 2, set load 1, eval, output, tick, tock, output;",
+        )
+        .unwrap();
+        let outcome = run_file_with(&path, Mode::FourValued).unwrap();
+        assert_eq!(
+            outcome.output,
+            vec!["|Bit|Bit|half|", "| X | 1 | ZZ |", "| 1 | 1 | ZZ |"]
+        );
+        let err = run_file(&path).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ScriptErrorKind::UnknownPin("Bit:1/out".to_string())
+        );
+    }
+    #[test]
+    fn mismatches() {
+        let dir = temp_dir("script-mismatches");
+        std::fs::write(
+            dir.join("Inv.hdl"),
+            "CHIP Inv { IN in; OUT out; PARTS: Not(in=in, out=out); }",
+        )
+        .unwrap();
+        std::fs::write(dir.join("Inv.cmp"), "| in  | out |\n|  *  |  0  |\n")
+            .unwrap();
+        let script = "load Inv.hdl, compare-to Inv.cmp, output-list in out;
+            output; set in 1, eval, output;";
+        let outcome = run_script(script, Some(dir.as_path())).unwrap();
+        assert!(!outcome.passed());
+        assert_eq!(
+            outcome.mismatches,
+            vec![
+                Mismatch {
+                    line: 1,
+                    expected: "| in  | out |".to_string(),
+                    actual: "|in |out|".to_string(),
+                },
+                Mismatch {
+                    line: 2,
+                    expected: "|  *  |  0  |".to_string(),
+                    actual: "| 0 | 0 |".to_string(),
+                },
+                Mismatch {
+                    line: 3,
+                    expected: "".to_string(),
+                    actual: "| 1 | 0 |".to_string(),
+                },
+            ]
+        );
+        let err = run_script("set in 1;", None).unwrap_err();
+        assert_eq!(err.kind, ScriptErrorKind::NoChip);
+    }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialise() {
+        let outcome = run_file(Path::new("../test-hdl/And.tst")).unwrap();
+        let json = serde_json::to_value(&outcome).unwrap();
+        assert_eq!(json["compare_to"], "../test-hdl/And.cmp");
+        assert_eq!(json["output"][1], "|   0   |   0   |   0   |");
+        assert_eq!(json["mismatches"], serde_json::json!([]));
+        let err = run_script("eval;", None).unwrap_err();
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({"line": 1, "kind": "no_chip"})
+        );
+    }
+}
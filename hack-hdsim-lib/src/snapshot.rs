@@ -0,0 +1,151 @@
+use crate::chip::{Chip, ChipPinlines, Pinlines};
+
+/// Every pin of a chip tree at one point in a simulation, including the
+/// buffers inside DFF and Bit
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    name: String,
+    pinlines: ChipPinlines,
+    /// Whether each part still has to be evaluated
+    stale: Vec<bool>,
+    parts: Vec<Snapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnapshotError {
+    /// Path of the part, like a probe, that the snapshot doesn't fit
+    DifferentChip(String),
+}
+
+// ============================================================================
+
+impl Chip {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            name: self.name.clone(),
+            pinlines: self.pinlines.clone(),
+            stale: self.parts.iter().map(|part| part.stale).collect(),
+            parts: self.parts.iter().map(|p| p.chip.snapshot()).collect(),
+        }
+    }
+    /// Puts every pin back as it was when the snapshot was taken. Nothing
+    /// changes when the snapshot is of another chip.
+    pub fn restore(
+        &mut self,
+        snapshot: &Snapshot,
+    ) -> Result<(), SnapshotError> {
+        fits(self, snapshot, &self.name)?;
+        self.restore_unchecked(snapshot);
+        Ok(())
+    }
+    fn restore_unchecked(&mut self, snapshot: &Snapshot) {
+        self.pinlines = snapshot.pinlines.clone();
+        for ((part, stale), part_snapshot) in self
+            .parts
+            .iter_mut()
+            .zip(&snapshot.stale)
+            .zip(&snapshot.parts)
+        {
+            part.stale = *stale;
+            part.chip.restore_unchecked(part_snapshot);
+        }
+    }
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SnapshotError::DifferentChip(path) => {
+                write!(f, "snapshot is of a different chip at {}", path)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+fn fits(
+    chip: &Chip,
+    snapshot: &Snapshot,
+    path: &str,
+) -> Result<(), SnapshotError> {
+    let same_pins = |ours: &Pinlines, theirs: &Pinlines| {
+        ours.len() == theirs.len()
+            && ours
+                .iter()
+                .zip(theirs)
+                .all(|(a, b)| a.name == b.name && a.pins.len() == b.pins.len())
+    };
+    if chip.name != snapshot.name
+        || chip.parts.len() != snapshot.parts.len()
+        || !same_pins(&chip.pinlines.input, &snapshot.pinlines.input)
+        || !same_pins(&chip.pinlines.internal, &snapshot.pinlines.internal)
+        || !same_pins(&chip.pinlines.output, &snapshot.pinlines.output)
+    {
+        return Err(SnapshotError::DifferentChip(path.to_string()));
+    }
+    for ((part, selector), part_snapshot) in chip
+        .parts
+        .iter()
+        .zip(chip.part_selectors())
+        .zip(&snapshot.parts)
+    {
+        fits(&part.chip, part_snapshot, &format!("{}/{}", path, selector))?;
+    }
+    Ok(())
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::Pinline;
+    use crate::loader::fixtures::{cycle, load};
+
+    #[test]
+    fn steps_back() {
+        let mut pc = load("PC");
+        cycle(&mut pc, &[("in", 40), ("load", 1)]);
+        let snapshot = pc.snapshot();
+        let counts: Vec<u64> = (0..3)
+            .map(|_| cycle(&mut pc, &[("load", 0), ("inc", 1)]))
+            .collect();
+        assert_eq!(counts, vec![41, 42, 43]);
+        pc.restore(&snapshot).unwrap();
+        assert_eq!(pc.pinlines.output[0].get_value(), 40);
+        assert_eq!(
+            pc.probe("Register/Bit:3/DFF/buffer1").unwrap().pins,
+            vec![true]
+        );
+        assert_eq!(cycle(&mut pc, &[]), 40);
+        assert_eq!(cycle(&mut pc, &[("load", 0), ("inc", 1)]), 41);
+    }
+    #[test]
+    fn before_the_first_evaluation() {
+        // Parts that haven't been evaluated yet get evaluated after restoring
+        let mut xor = load("Xor");
+        let snapshot = xor.snapshot();
+        xor.evaluate();
+        xor.restore(&snapshot).unwrap();
+        assert_eq!(xor.evaluate(), &vec![Pinline::new("out", vec![false])]);
+        xor.pinlines.input[0].set_value(1);
+        assert_eq!(xor.evaluate(), &vec![Pinline::new("out", vec![true])]);
+    }
+    #[test]
+    fn other_chips() {
+        let mut xor = load("Xor");
+        assert_eq!(
+            xor.restore(&load("And").snapshot()),
+            Err(SnapshotError::DifferentChip("Xor".to_string()))
+        );
+        let mut bit = load("Bit");
+        bit.parts[1].chip.name = "Register".to_string();
+        let e = bit.restore(&load("Bit").snapshot()).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "snapshot is of a different chip at Bit/Register"
+        );
+    }
+}
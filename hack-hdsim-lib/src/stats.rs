@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use crate::chip::Chip;
+use crate::netlist::{Gate, Netlist};
+
+/// What a chip costs once expanded down to builtin chips
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChipStats {
+    /// Builtin chips by name
+    pub primitives: BTreeMap<String, usize>,
+    /// Chips built from HDL by name, at any depth, not counting the chip
+    /// itself
+    pub parts: BTreeMap<String, usize>,
+    /// Nand gates the combinational builtins take: one for Nand and Not,
+    /// four for Mux. DFFs and memory parts are not counted.
+    pub nand_count: usize,
+    /// Longest path in Nand gates from an input or DFF output to an output
+    /// or DFF input
+    pub depth: usize,
+}
+
+// ============================================================================
+
+impl Chip {
+    pub fn stats(&self) -> ChipStats {
+        let mut stats = ChipStats {
+            primitives: BTreeMap::new(),
+            parts: BTreeMap::new(),
+            nand_count: 0,
+            depth: 0,
+        };
+        count_parts(self, &mut stats);
+        if self.is_builtin() {
+            stats.primitives.insert(self.name.clone(), 1);
+        }
+
+        let netlist = Netlist::compile_gates(self);
+        stats.nand_count = netlist
+            .gates
+            .iter()
+            .map(|g| match g {
+                Gate::Nand { .. } | Gate::Not { .. } => 1,
+                Gate::Mux { .. } => 4,
+            })
+            .sum();
+        stats.depth = depth(&netlist);
+        stats
+    }
+}
+
+fn count_parts(chip: &Chip, stats: &mut ChipStats) {
+    for part in &chip.parts {
+        let counts = if part.chip.is_builtin() {
+            &mut stats.primitives
+        } else {
+            &mut stats.parts
+        };
+        *counts.entry(part.chip.name.clone()).or_insert(0) += 1;
+        count_parts(&part.chip, stats);
+    }
+}
+
+/// Gates are in evaluation order, so every gate's inputs already have their
+/// depth. A Mux is a Not on `sel` followed by two levels of Nands.
+fn depth(netlist: &Netlist) -> usize {
+    let mut depths = vec![0; netlist.net_count];
+    for gate in &netlist.gates {
+        let (out, depth) = match *gate {
+            Gate::Nand { a, b, out } => (out, depths[a].max(depths[b]) + 1),
+            Gate::Not { input, out } => (out, depths[input] + 1),
+            Gate::Mux { a, b, sel, out } => {
+                (out, (depths[a].max(depths[b]) + 2).max(depths[sel] + 3))
+            }
+        };
+        depths[out] = depth;
+    }
+    netlist
+        .output
+        .iter()
+        .flat_map(|p| p.nets.iter())
+        .chain(netlist.dffs.iter().map(|d| &d.input))
+        .map(|n| depths[*n])
+        .max()
+        .unwrap_or(0)
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::BuiltinChips;
+    use crate::loader::fixtures::load;
+
+    #[test]
+    fn and() {
+        let stats = load("And").stats();
+        assert_eq!(stats.primitives.get("Nand"), Some(&2));
+        assert!(stats.parts.is_empty());
+        assert_eq!(stats.nand_count, 2);
+        assert_eq!(stats.depth, 2);
+    }
+    #[test]
+    fn mux() {
+        let stats = load("Mux").stats();
+        let parts: Vec<(&str, usize)> =
+            stats.parts.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        assert_eq!(parts, vec![("And", 3), ("Or", 2), ("Xor", 1)]);
+        assert_eq!(stats.primitives.get("Nand"), Some(&9));
+        assert_eq!(stats.primitives.get("Not"), Some(&4));
+        assert_eq!(stats.nand_count, 13);
+        // a through Xor (Or, And), And and Or
+        assert_eq!(stats.depth, 8);
+    }
+    #[test]
+    fn clocked() {
+        let stats = Chip::new_builtin(BuiltinChips::Bit).stats();
+        assert_eq!(stats.primitives.get("Bit"), Some(&1));
+        assert_eq!(stats.nand_count, 4);
+        assert_eq!(stats.depth, 3);
+        // Paths end at the DFF inside each Bit
+        assert_eq!(load("Register").stats().depth, 8);
+    }
+}
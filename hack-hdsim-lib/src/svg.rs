@@ -0,0 +1,373 @@
+use crate::chip::{Child, Chip};
+use crate::dot::hdl;
+use std::fmt::Write;
+
+/// Height of a pin row
+const PITCH: usize = 20;
+/// Room for the title above everything else
+const TOP: usize = 40;
+const MARGIN: usize = 20;
+const PART_WIDTH: usize = 120;
+const PORT_WIDTH: usize = 60;
+/// Space between wires running side by side
+const TRACK: usize = 8;
+
+const STYLE: &str = "\
+<style>
+    text { font: 11px sans-serif; }
+    .title { font-size: 14px; font-weight: bold; }
+    .part { fill: white; stroke: black; }
+    .clocked { fill: lightblue; }
+    .wire { fill: none; stroke: #333; }
+    .bus { stroke-width: 2; }
+</style>
+";
+
+/// A column and how far down it
+#[derive(Debug, Clone, Copy)]
+struct End {
+    column: usize,
+    y: usize,
+}
+
+/// A wire leaves the right of its source column and turns down a track in
+/// the gap before its target column. Wires that skip columns or go back
+/// first turn down a track in the next gap to a lane under everything.
+#[derive(Debug)]
+struct Wire {
+    from: End,
+    to: End,
+    title: String,
+    bus: bool,
+    /// Track in the gap after `from` and lane, for wires going round
+    detour: Option<(usize, usize)>,
+    /// Track in the gap before `to`
+    track: usize,
+}
+
+// ============================================================================
+
+impl Chip {
+    /// A standalone SVG schematic. Inputs are on the left, outputs on the
+    /// right and parts in columns between them by combinational depth, with
+    /// wires drawn as horizontal and vertical lines. Hovering a wire shows
+    /// its connection as written in HDL.
+    pub fn to_svg(&self) -> String {
+        let depths = depths(self);
+        let last = depths.iter().max().map_or(1, |depth| depth + 1);
+        let port_y = |k: usize| TOP + PITCH * k + PITCH / 2;
+        let mut bottoms = vec![TOP; last + 1];
+        bottoms[0] = port_y(self.pinlines.input.len());
+        bottoms[last] = port_y(self.pinlines.output.len());
+        let mut tops = vec![0; self.parts.len()];
+        for (i, part) in self.parts.iter().enumerate() {
+            tops[i] = bottoms[depths[i]];
+            bottoms[depths[i]] += height(part) + PITCH;
+        }
+        let pin_y = |i: usize, k: usize| tops[i] + PITCH * (k + 1) + PITCH / 2;
+
+        let width = |name: &str| {
+            self.pinlines
+                .get_pinline(name)
+                .map_or(1, |pinline| pinline.pins.len())
+        };
+        let mut wires = Vec::new();
+        let mut constants = Vec::new();
+        for (i, part) in self.parts.iter().enumerate() {
+            for connection in &part.input_connections {
+                let k =
+                    position(&part.chip.pinlines.input, &connection.own.name);
+                let to = End {
+                    column: depths[i],
+                    y: pin_y(i, k),
+                };
+                let foreign = connection.foreign.name.as_str();
+                if connection.foreign.is_constant() {
+                    constants.push((to, foreign));
+                    continue;
+                }
+                let mut froms = Vec::new();
+                if self.pinlines.input.iter().any(|p| p.name == foreign) {
+                    froms.push(End {
+                        column: 0,
+                        y: port_y(position(&self.pinlines.input, foreign)),
+                    });
+                }
+                for (j, driver) in self.parts.iter().enumerate() {
+                    for output in &driver.output_connections {
+                        if output.foreign.name == foreign {
+                            let pins = &driver.chip.pinlines.output;
+                            froms.push(End {
+                                column: depths[j],
+                                y: pin_y(j, position(pins, &output.own.name)),
+                            });
+                        }
+                    }
+                }
+                for from in froms {
+                    wires.push(Wire::new(
+                        from,
+                        to,
+                        hdl(part, connection, &width),
+                        connection.own.indices.len() > 1,
+                    ));
+                }
+            }
+            for connection in &part.output_connections {
+                let foreign = connection.foreign.name.as_str();
+                if self.pinlines.output.iter().any(|p| p.name == foreign) {
+                    let pins = &part.chip.pinlines.output;
+                    wires.push(Wire::new(
+                        End {
+                            column: depths[i],
+                            y: pin_y(i, position(pins, &connection.own.name)),
+                        },
+                        End {
+                            column: last,
+                            y: port_y(position(&self.pinlines.output, foreign)),
+                        },
+                        hdl(part, connection, &width),
+                        connection.own.indices.len() > 1,
+                    ));
+                }
+            }
+        }
+
+        // Gap `c` is the one before column `c`
+        let mut tracks = vec![0; last + 1];
+        let mut lanes = 0;
+        for wire in &mut wires {
+            if wire.to.column != wire.from.column + 1 {
+                wire.detour = Some((tracks[wire.from.column + 1], lanes));
+                tracks[wire.from.column + 1] += 1;
+                lanes += 1;
+            }
+            wire.track = tracks[wire.to.column];
+            tracks[wire.to.column] += 1;
+        }
+        let gap = |c: usize| (2 * PITCH).max((tracks[c] + 1) * TRACK);
+        let widths: Vec<usize> = (0..=last)
+            .map(|c| match c {
+                0 => PORT_WIDTH,
+                _ if c == last => PORT_WIDTH,
+                _ => PART_WIDTH,
+            })
+            .collect();
+        let mut lefts = vec![MARGIN; last + 1];
+        for c in 1..=last {
+            lefts[c] = lefts[c - 1] + widths[c - 1] + gap(c);
+        }
+        let track_x =
+            |c: usize, track: usize| lefts[c] - gap(c) + (track + 1) * TRACK;
+        let below = bottoms.iter().max().cloned().unwrap_or(TOP);
+        let lane_y = |lane: usize| below + (lane + 1) * TRACK;
+        let total_width = lefts[last] + widths[last] + MARGIN;
+        let total_height = lane_y(lanes) + MARGIN;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" \
+             height=\"{1}\" viewBox=\"0 0 {0} {1}\">\n",
+            total_width, total_height
+        );
+        svg.push_str(STYLE);
+        text(&mut svg, "title", MARGIN, MARGIN + 4, "start", &self.name);
+        for (k, pinline) in self.pinlines.input.iter().enumerate() {
+            let x = lefts[0] + PORT_WIDTH - 4;
+            text(&mut svg, "", x, port_y(k) + 4, "end", &pinline.name);
+        }
+        for (k, pinline) in self.pinlines.output.iter().enumerate() {
+            let x = lefts[last] + 4;
+            text(&mut svg, "", x, port_y(k) + 4, "start", &pinline.name);
+        }
+        for (i, part) in self.parts.iter().enumerate() {
+            let (x, y) = (lefts[depths[i]], tops[i]);
+            let class = if part.chip.clocked {
+                "part clocked"
+            } else {
+                "part"
+            };
+            writeln!(
+                svg,
+                "<rect class=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" \
+                 height=\"{}\"/>",
+                class,
+                x,
+                y,
+                PART_WIDTH,
+                height(part)
+            )
+            .unwrap();
+            let name = &part.chip.name;
+            text(&mut svg, "", x + PART_WIDTH / 2, y + 14, "middle", name);
+            for (k, pin) in part.chip.pinlines.input.iter().enumerate() {
+                text(&mut svg, "", x + 4, pin_y(i, k) + 4, "start", &pin.name);
+            }
+            for (k, pin) in part.chip.pinlines.output.iter().enumerate() {
+                let x = x + PART_WIDTH - 4;
+                text(&mut svg, "", x, pin_y(i, k) + 4, "end", &pin.name);
+            }
+        }
+        for (to, name) in constants {
+            text(&mut svg, "", lefts[to.column] - 4, to.y - 3, "end", name);
+        }
+        for wire in &wires {
+            let (from, to) = (wire.from, wire.to);
+            let mut points =
+                vec![(lefts[from.column] + widths[from.column], from.y)];
+            if let Some((track, lane)) = wire.detour {
+                let x = track_x(from.column + 1, track);
+                points.push((x, from.y));
+                points.push((x, lane_y(lane)));
+                points.push((track_x(to.column, wire.track), lane_y(lane)));
+            } else {
+                points.push((track_x(to.column, wire.track), from.y));
+            }
+            points.push((track_x(to.column, wire.track), to.y));
+            points.push((lefts[to.column], to.y));
+            let points: Vec<String> =
+                points.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+            writeln!(
+                svg,
+                "<polyline class=\"wire{}\" points=\"{}\"><title>{}</title>\
+                 </polyline>",
+                if wire.bus { " bus" } else { "" },
+                points.join(" "),
+                wire.title
+            )
+            .unwrap();
+        }
+        svg + "</svg>\n"
+    }
+}
+
+impl Wire {
+    fn new(from: End, to: End, title: String, bus: bool) -> Self {
+        Self {
+            from,
+            to,
+            title,
+            bus,
+            detour: None,
+            track: 0,
+        }
+    }
+}
+
+/// Column of each part, one more than the deepest part feeding it. Parts
+/// whose outputs only change on the clock don't count as feeding anything.
+fn depths(chip: &Chip) -> Vec<usize> {
+    let mut depths = vec![1; chip.parts.len()];
+    // Going round a combinational loop stops at one column per part
+    for _ in 0..chip.parts.len() {
+        let mut changed = false;
+        for (i, part) in chip.parts.iter().enumerate() {
+            for connection in &part.input_connections {
+                for (j, driver) in chip.parts.iter().enumerate() {
+                    let feeds = driver.chip.has_combinational_path()
+                        && driver
+                            .output_connections
+                            .iter()
+                            .any(|c| c.foreign.name == connection.foreign.name);
+                    let depth = (depths[j] + 1).min(chip.parts.len());
+                    if feeds && depth > depths[i] {
+                        depths[i] = depth;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    depths
+}
+
+fn height(part: &Child) -> usize {
+    let pinlines = &part.chip.pinlines;
+    PITCH * (pinlines.input.len().max(pinlines.output.len()) + 1) + PITCH / 2
+}
+
+fn position(pinlines: &[crate::chip::Pinline], name: &str) -> usize {
+    pinlines.iter().position(|p| p.name == name).unwrap_or(0)
+}
+
+fn text(
+    svg: &mut String,
+    class: &str,
+    x: usize,
+    y: usize,
+    anchor: &str,
+    s: &str,
+) {
+    let class = if class.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"{}\"", class)
+    };
+    writeln!(
+        svg,
+        "<text{} x=\"{}\" y=\"{}\" text-anchor=\"{}\">{}</text>",
+        class, x, y, anchor, s
+    )
+    .unwrap();
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::load;
+
+    fn polylines(svg: &str) -> Vec<Vec<(usize, usize)>> {
+        svg.lines()
+            .filter_map(|l| l.split("points=\"").nth(1))
+            .map(|l| {
+                l[..l.find('"').unwrap()]
+                    .split(' ')
+                    .map(|point| {
+                        let mut xy =
+                            point.split(',').map(|n| n.parse().unwrap());
+                        (xy.next().unwrap(), xy.next().unwrap())
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layers() {
+        assert_eq!(depths(&load("Xor")), vec![1, 1, 2]);
+        // The DFF's output feeds the Mux, but only on the clock
+        assert_eq!(depths(&load("Bit")), vec![1, 2]);
+        assert_eq!(depths(&load("And")), vec![1, 2]);
+    }
+    #[test]
+    fn schematic() {
+        let svg = load("Xor").to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect class=\"part\"").count(), 3);
+        assert!(svg.contains(">a=aorb</title>"));
+        // Four wires from the inputs, two between parts, one to the output
+        assert_eq!(polylines(&svg).len(), 7);
+        let svg = load("Bit").to_svg();
+        assert!(svg.contains("<rect class=\"part clocked\""));
+    }
+    #[test]
+    fn orthogonal_wires() {
+        for name in &["Bit", "ALU", "PC", "CPU"] {
+            let svg = load(name).to_svg();
+            for line in polylines(&svg) {
+                for pair in line.windows(2) {
+                    let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+                    assert!(x1 == x2 || y1 == y2, "{}: {:?}", name, line);
+                }
+            }
+        }
+        let svg = load("ALU").to_svg();
+        assert!(svg.contains("<polyline class=\"wire bus\""));
+        assert!(svg.contains(">false</text>"));
+    }
+}
@@ -19,31 +19,91 @@ pub enum Token {
     Symbol(char),
     Identifier(String),
     Number(i32),
+    /// `..` between the bounds of a sub-bus, the dots written together
+    Range,
+    /// Only produced by `Tokeniser::with_comments`, text includes delimiters.
+    Comment(String),
+    /// Comment that starts on the same line as the previous token.
+    TrailingComment(String),
+    /// One or more empty lines, only produced by `Tokeniser::with_comments`.
+    BlankLine,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct UnexpectedToken {
-    expected: Token,
-    nline: i32,
-    nchar: i32,
+    pub expected: Token,
+    pub nline: i32,
+    pub nchar: i32,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Keyword(s) | Token::Identifier(s) => write!(f, "{}", s),
+            Token::Symbol(ch) => write!(f, "'{}'", ch),
+            Token::Number(_) => write!(f, "number"),
+            Token::Range => write!(f, "'..'"),
+            Token::Comment(s) | Token::TrailingComment(s) => write!(f, "{}", s),
+            Token::BlankLine => write!(f, "blank line"),
+        }
+    }
 }
 
+impl std::fmt::Display for UnexpectedToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "expected {} at line {}, char {}",
+            self.expected, self.nline, self.nchar
+        )
+    }
+}
+
+impl std::error::Error for UnexpectedToken {}
+
 pub struct Tokeniser<'a> {
     tokens: Vec<Token>,
+    /// Line and character where each token starts
+    positions: Vec<(i32, i32)>,
     itr: std::str::Chars<'a>,
     nline: i32,
     nchar: i32,
+    keep_comments: bool,
+    last_token_line: Option<i32>,
 }
 
 impl<'a> Tokeniser<'a> {
     pub fn new(contents: &'a str) -> Self {
         Self {
             tokens: Vec::new(),
+            positions: Vec::new(),
             itr: contents.chars(),
             nline: 1,
             nchar: 1,
+            keep_comments: false,
+            last_token_line: None,
         }
     }
+    /// Like `new` but comments and blank lines are kept as tokens.
+    pub fn with_comments(contents: &'a str) -> Self {
+        let mut tokeniser = Self::new(contents);
+        tokeniser.keep_comments = true;
+        tokeniser
+    }
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+    pub fn into_tokens(self) -> Vec<Token> {
+        self.tokens
+    }
+    pub fn positions(&self) -> &[(i32, i32)] {
+        &self.positions
+    }
+    fn push(&mut self, token: Token, start: (i32, i32)) {
+        self.last_token_line = Some(self.nline);
+        self.tokens.push(token);
+        self.positions.push(start);
+    }
     fn next(&mut self) -> Option<char> {
         if let Some(ch) = self.itr.next() {
             if ch == '\n' {
@@ -84,9 +144,13 @@ impl<'a> Tokeniser<'a> {
         self.tokenise_keyword("IN")?;
         self.tokenise_identifier_list()?;
         self.tokenise_symbol(';')?;
-        self.tokenise_keyword("OUT")?;
-        self.tokenise_identifier_list()?;
-        self.tokenise_symbol(';')?;
+        // Chips without outputs, like Computer, can leave OUT out
+        self.skip_nontokens();
+        if !self.itr.as_str().starts_with("PARTS") {
+            self.tokenise_keyword("OUT")?;
+            self.tokenise_identifier_list()?;
+            self.tokenise_symbol(';')?;
+        }
         self.tokenise_keyword("PARTS")?;
         self.tokenise_symbol(':')?;
         self.tokenise_parts_list()?;
@@ -148,6 +212,7 @@ impl<'a> Tokeniser<'a> {
             nline: self.nline,
             nchar: self.nchar,
         });
+        let start = (self.nline, self.nchar);
         let identifier = self.next_word(
             |first| first.is_alphabetic() || first == '_',
             |ch| !ch.is_alphanumeric() && ch != '_',
@@ -162,12 +227,17 @@ impl<'a> Tokeniser<'a> {
         }
 
         let identifier = identifier.to_string();
-        self.tokens.push(Token::Identifier(identifier));
+        self.push(Token::Identifier(identifier), start);
 
         if let Some(ch) = self.peek() {
             if ch == '[' {
                 self.tokenise_symbol('[')?;
                 self.tokenise_number()?;
+                // Sub-bus ranges look like `[0..14]`
+                if let Some('.') = self.peek() {
+                    self.tokenise_range()?;
+                    self.tokenise_number()?;
+                }
                 self.tokenise_symbol(']')?;
             }
         }
@@ -182,27 +252,48 @@ impl<'a> Tokeniser<'a> {
             nchar: self.nchar,
         });
 
-        let number =
-            self.next_word(|first| first.is_digit(10), |ch| !ch.is_digit(10));
+        let start = (self.nline, self.nchar);
+        let number = self.next_word(
+            |first| first.is_ascii_digit(),
+            |ch| !ch.is_ascii_digit(),
+        );
         if number.is_none() {
             return err;
         }
         let number = number.unwrap().parse().unwrap();
-        self.tokens.push(Token::Number(number));
+        self.push(Token::Number(number), start);
         self.skip_nontokens();
         Ok(())
     }
+    /// Both dots of `..`, with nothing in between
+    fn tokenise_range(&mut self) -> Result<(), UnexpectedToken> {
+        self.skip_nontokens();
+        let start = (self.nline, self.nchar);
+        if self.itr.as_str().starts_with("..") {
+            self.next();
+            self.next();
+            self.push(Token::Range, start);
+            self.skip_nontokens();
+            return Ok(());
+        }
+        Err(UnexpectedToken {
+            expected: Token::Range,
+            nline: self.nline,
+            nchar: self.nchar,
+        })
+    }
     pub fn tokenise_keyword(
         &mut self,
         keyword: &str,
     ) -> Result<(), UnexpectedToken> {
         self.skip_nontokens();
         let token = Token::Keyword(keyword.to_string());
+        let start = (self.nline, self.nchar);
         if self.itr.as_str().starts_with(keyword) {
             for _ in keyword.chars() {
                 self.next();
             }
-            self.tokens.push(token);
+            self.push(token, start);
             self.skip_nontokens();
             return Ok(());
         }
@@ -218,9 +309,10 @@ impl<'a> Tokeniser<'a> {
     ) -> Result<(), UnexpectedToken> {
         self.skip_nontokens();
         let token = Token::Symbol(symbol);
+        let start = (self.nline, self.nchar);
         if let Some(ch) = self.next() {
             if ch == symbol {
-                self.tokens.push(token);
+                self.push(token, start);
                 self.skip_nontokens();
                 return Ok(());
             }
@@ -249,6 +341,17 @@ impl<'a> Tokeniser<'a> {
             }
             break;
         }
+        // A blank line sits between the last token and the current line
+        if let Some(last_token_line) = self.last_token_line {
+            if self.keep_comments
+                && self.nline - last_token_line >= 2
+                && self.tokens.last() != Some(&Token::BlankLine)
+            {
+                let position = (self.nline, self.nchar);
+                self.tokens.push(Token::BlankLine);
+                self.positions.push(position);
+            }
+        }
         moved
     }
     /// Returns `true` if moved the iterator, `false` otherwise.
@@ -256,6 +359,8 @@ impl<'a> Tokeniser<'a> {
         let mut moved = false;
         while self.itr.as_str().starts_with(start) {
             moved = true;
+            let position = (self.nline, self.nchar);
+            let text_start = self.itr.as_str();
             for _ in start.chars() {
                 self.next();
             }
@@ -266,7 +371,24 @@ impl<'a> Tokeniser<'a> {
                     }
                     break;
                 }
-                self.next();
+                // Unterminated comment runs to the end of input
+                if self.next().is_none() {
+                    break;
+                }
+            }
+            if self.keep_comments {
+                let len = text_start.len() - self.itr.as_str().len();
+                let text = text_start[..len].trim_end().to_string();
+                let token = if self.last_token_line == Some(position.0) {
+                    Token::TrailingComment(text)
+                } else {
+                    Token::Comment(text)
+                };
+                self.push(token, position);
+                // Line comments consume the line break
+                if end == "\n" {
+                    self.last_token_line = Some(position.0);
+                }
             }
         }
         moved
@@ -295,10 +417,10 @@ mod tests {
         assert_eq!(no_whitespace, contents_nws_vec);
         let contents = "   a";
         let mut tokeniser = Tokeniser::new(contents);
-        assert_eq!(true, tokeniser.skip_whitespace());
+        assert!(tokeniser.skip_whitespace());
         let contents = "a    ";
         let mut tokeniser = Tokeniser::new(contents);
-        assert_eq!(false, tokeniser.skip_whitespace());
+        assert!(!tokeniser.skip_whitespace());
     }
     #[test]
     fn skip_comment() {
@@ -322,10 +444,10 @@ extra";
         assert_eq!(no_com, contents_nc_vec);
         let contents = "/**/a";
         let mut tokeniser = Tokeniser::new(contents);
-        assert_eq!(true, tokeniser.skip_comment("/*", "*/"));
+        assert!(tokeniser.skip_comment("/*", "*/"));
         let contents = "a/**/";
         let mut tokeniser = Tokeniser::new(contents);
-        assert_eq!(false, tokeniser.skip_comment("/*", "*/"));
+        assert!(!tokeniser.skip_comment("/*", "*/"));
     }
     #[test]
     fn skip_nontokens() {
@@ -420,6 +542,50 @@ c=d
         assert_eq!(tokens_exp, tokeniser.tokens);
     }
     #[test]
+    fn tokenise_range() {
+        let mut tokeniser = Tokeniser::new("out[0..14]");
+        tokeniser.tokenise_identifier().unwrap();
+        let tokens_exp = vec![
+            Token::Identifier("out".to_string()),
+            Token::Symbol('['),
+            Token::Number(0),
+            Token::Range,
+            Token::Number(14),
+            Token::Symbol(']'),
+        ];
+        assert_eq!(tokens_exp, tokeniser.tokens);
+
+        for gap in &["a[0. .14]", "a[0 ./*x*/. 14]"] {
+            let mut tokeniser = Tokeniser::new(gap);
+            let err = tokeniser.tokenise_identifier().unwrap_err();
+            assert_eq!(err.expected, Token::Range);
+        }
+        let mut tokeniser = Tokeniser::new("a[0 .. 14]");
+        tokeniser.tokenise_identifier().unwrap();
+        assert_eq!(tokeniser.tokens[3], Token::Range);
+    }
+    #[test]
+    fn with_comments() {
+        let contents = "// first
+CHIP /* name */ And {
+
+  // unterminated";
+        let mut tokeniser = Tokeniser::with_comments(contents);
+        tokeniser.tokenise_keyword("CHIP").unwrap();
+        tokeniser.tokenise_identifier().unwrap();
+        tokeniser.tokenise_symbol('{').unwrap();
+        let tokens_exp = vec![
+            Token::Comment("// first".to_string()),
+            Token::Keyword("CHIP".to_string()),
+            Token::TrailingComment("/* name */".to_string()),
+            Token::Identifier("And".to_string()),
+            Token::Symbol('{'),
+            Token::BlankLine,
+            Token::Comment("// unterminated".to_string()),
+        ];
+        assert_eq!(tokens_exp, tokeniser.tokens);
+    }
+    #[test]
     fn tokenise_identifier_list() {
         let mut tokeniser = Tokeniser::new("  a, b  ,c,d/**/,e  /*cc*/;  ");
         let exp_vec = vec![
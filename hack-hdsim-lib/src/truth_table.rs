@@ -0,0 +1,272 @@
+use crate::chip::Chip;
+use crate::netlist::{Netlist, ParallelSimulator};
+use crate::script::{Column, ColumnFormat, PinSelect};
+
+/// Chips with more input bits than this have too many rows to list
+pub const MAX_INPUT_BITS: usize = 24;
+
+/// Outputs of an unclocked chip for every combination of its inputs
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TruthTable {
+    /// Names and widths
+    pub inputs: Vec<(String, usize)>,
+    pub outputs: Vec<(String, usize)>,
+    /// Counting up with the inputs read left to right as one binary number
+    pub rows: Vec<TruthRow>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TruthRow {
+    pub inputs: Vec<u64>,
+    pub outputs: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TruthTableError {
+    Clocked,
+    TooManyInputs(usize),
+    /// The name of a ROM32K, RAM16K, Screen or Keyboard part
+    Memory(String),
+}
+
+// ============================================================================
+
+impl Chip {
+    pub fn truth_table(&self) -> Result<TruthTable, TruthTableError> {
+        if self.clocked {
+            return Err(TruthTableError::Clocked);
+        }
+        if let Some(part) = self.memory_part() {
+            return Err(TruthTableError::Memory(part.to_string()));
+        }
+        let netlist = Netlist::compile(self);
+        let width = netlist.input_width();
+        if width > MAX_INPUT_BITS {
+            return Err(TruthTableError::TooManyInputs(width));
+        }
+        let pins = |pinlines: &[crate::netlist::NetPinline]| {
+            pinlines
+                .iter()
+                .map(|p| (p.name.clone(), p.nets.len()))
+                .collect::<Vec<_>>()
+        };
+        let inputs = pins(&netlist.input);
+        let outputs = pins(&netlist.output);
+
+        let mut sim = ParallelSimulator::new(netlist);
+        let mut rows = Vec::with_capacity(1 << width);
+        let mut first = 0;
+        loop {
+            let count = sim.set_combinations(first);
+            if count == 0 {
+                break;
+            }
+            sim.evaluate();
+            let values = |pins: &[(String, usize)]| {
+                pins.iter()
+                    .map(|(name, _)| sim.get_values(name).unwrap())
+                    .collect::<Vec<_>>()
+            };
+            let input_values = values(&inputs);
+            let output_values = values(&outputs);
+            for lane in 0..count {
+                rows.push(TruthRow {
+                    inputs: input_values.iter().map(|v| v[lane]).collect(),
+                    outputs: output_values.iter().map(|v| v[lane]).collect(),
+                });
+            }
+            first += count as u64;
+        }
+        Ok(TruthTable {
+            inputs,
+            outputs,
+            rows,
+        })
+    }
+}
+
+impl TruthTable {
+    /// Binary values under their pin names, inputs and outputs split by `|`
+    pub fn to_text(&self) -> String {
+        let widths: Vec<usize> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|(name, width)| name.len().max(*width))
+            .collect();
+        let line = |cells: Vec<String>| {
+            let cells: Vec<String> = cells
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            let (inputs, outputs) = cells.split_at(self.inputs.len());
+            format!("{} | {}", inputs.join(" "), outputs.join(" "))
+                .trim_end()
+                .to_string()
+        };
+        let mut lines = vec![line(
+            self.inputs
+                .iter()
+                .chain(self.outputs.iter())
+                .map(|(name, _)| name.clone())
+                .collect(),
+        )];
+        for row in &self.rows {
+            lines.push(line(
+                self.values(row)
+                    .map(|(value, width)| format!("{:0w$b}", value, w = width))
+                    .collect(),
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+    /// Header of pin names, then decimal values
+    pub fn to_csv(&self) -> String {
+        let mut text = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>()
+            .join(",");
+        text.push('\n');
+        for row in &self.rows {
+            let values: Vec<String> =
+                self.values(row).map(|(v, _)| v.to_string()).collect();
+            text.push_str(values.join(",").as_str());
+            text.push('\n');
+        }
+        text
+    }
+    /// Compare file laid out like the Nand2Tetris ones, `%B3.1.3` for single
+    /// bits and `%B1.w.1` for buses
+    pub fn to_cmp(&self) -> String {
+        let columns: Vec<(Column, ColumnFormat)> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|(name, width)| {
+                let pad = if *width == 1 { 3 } else { 1 };
+                let format = ColumnFormat {
+                    kind: 'B',
+                    pad_left: pad,
+                    len: *width,
+                    pad_right: pad,
+                };
+                let column = Column {
+                    pin: PinSelect {
+                        name: name.clone(),
+                        range: None,
+                    },
+                    format: Some(format),
+                };
+                (column, format)
+            })
+            .collect();
+        let line = |cells: Vec<String>| format!("|{}|\n", cells.join("|"));
+        let mut text = line(columns.iter().map(|(c, f)| c.header(f)).collect());
+        for row in &self.rows {
+            text.push_str(
+                line(
+                    self.values(row)
+                        .zip(columns.iter())
+                        .map(|((value, width), (_, f))| f.cell(value, width))
+                        .collect(),
+                )
+                .as_str(),
+            );
+        }
+        text
+    }
+    /// Values of a row with the widths of their pins
+    fn values<'a>(
+        &'a self,
+        row: &'a TruthRow,
+    ) -> impl Iterator<Item = (u64, usize)> + 'a {
+        row.inputs
+            .iter()
+            .chain(row.outputs.iter())
+            .cloned()
+            .zip(self.inputs.iter().chain(self.outputs.iter()).map(|p| p.1))
+    }
+}
+
+impl std::fmt::Display for TruthTableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TruthTableError::Clocked => {
+                write!(f, "clocked chips have no truth table")
+            }
+            TruthTableError::TooManyInputs(width) => write!(
+                f,
+                "{} input bits is too many, at most {} are listed",
+                width, MAX_INPUT_BITS
+            ),
+            TruthTableError::Memory(part) => {
+                write!(f, "chips with a {} have no truth table", part)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TruthTableError {}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip::BuiltinChips;
+    use crate::loader::fixtures::load;
+
+    #[test]
+    fn mux() {
+        let table = load("Mux").truth_table().unwrap();
+        assert_eq!(table.rows.len(), 8);
+        assert_eq!(
+            table.rows[5],
+            TruthRow {
+                inputs: vec![1, 0, 1],
+                outputs: vec![0]
+            }
+        );
+        assert_eq!(
+            table.to_text().lines().take(3).collect::<Vec<_>>(),
+            vec!["a b sel | out", "0 0 0   | 0", "0 0 1   | 0"]
+        );
+        assert!(table.to_csv().starts_with("a,b,sel,out\n0,0,0,0\n"));
+        assert_eq!(
+            table.to_cmp().lines().take(2).collect::<Vec<_>>(),
+            vec![
+                "|   a   |   b   |  sel  |  out  |",
+                "|   0   |   0   |   0   |   0   |"
+            ]
+        );
+    }
+    #[test]
+    fn buses() {
+        let chip = load("Or8Way");
+        let table = chip.truth_table().unwrap();
+        assert_eq!(table.rows.len(), 256);
+        assert!(table
+            .rows
+            .iter()
+            .all(|r| r.outputs[0] == (r.inputs[0] != 0) as u64));
+        assert_eq!(
+            table.to_cmp().lines().nth(2).unwrap(),
+            "| 00000001 |   1   |"
+        );
+        assert_eq!(load("Bit").truth_table(), Err(TruthTableError::Clocked));
+        assert_eq!(
+            Chip::new_builtin(BuiltinChips::Keyboard).truth_table(),
+            Err(TruthTableError::Memory("Keyboard".to_string()))
+        );
+        assert_eq!(
+            load("Add16").truth_table(),
+            Err(TruthTableError::TooManyInputs(32))
+        );
+    }
+}
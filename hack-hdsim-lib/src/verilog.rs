@@ -0,0 +1,581 @@
+use crate::chip::{BuiltinChips, Child, ChildConnection, Chip, Pinline};
+use std::collections::{HashMap, HashSet};
+
+/// Where a bit of a part's pin comes from or goes to in the chip around it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Bit<'a> {
+    Pin(&'a str, usize),
+    Constant(bool),
+}
+
+/// Names that would clash with Verilog, written with a `_` after them
+const RESERVED: &[&str] = &[
+    "always",
+    "and",
+    "assign",
+    "begin",
+    "buf",
+    "case",
+    "clk",
+    "default",
+    "else",
+    "end",
+    "endcase",
+    "endmodule",
+    "for",
+    "function",
+    "if",
+    "initial",
+    "inout",
+    "input",
+    "integer",
+    "module",
+    "nand",
+    "negedge",
+    "nor",
+    "not",
+    "or",
+    "output",
+    "parameter",
+    "posedge",
+    "reg",
+    "while",
+    "wire",
+    "xnor",
+    "xor",
+];
+
+// ============================================================================
+
+impl Chip {
+    /// Verilog modules for this chip and every chip it is built from, parts
+    /// first. Clocked modules take a `clk` input and DFF and Bit latch on its
+    /// rising edge.
+    pub fn to_verilog(&self) -> String {
+        chip_types(self)
+            .iter()
+            .map(|chip| module(chip))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Every chip type under `chip`, each once, parts before the chips using
+/// them and `chip` last
+pub(crate) fn chip_types(chip: &Chip) -> Vec<&Chip> {
+    fn visit<'a>(chip: &'a Chip, types: &mut Vec<&'a Chip>) {
+        if types.iter().any(|c| c.name == chip.name) {
+            return;
+        }
+        for part in &chip.parts {
+            visit(&part.chip, types);
+        }
+        types.push(chip);
+    }
+    let mut types = Vec::new();
+    visit(chip, &mut types);
+    types
+}
+
+/// Identifiers for one chip's pins and the names generated inside it, none
+/// clashing with a keyword, `clk` or each other
+pub(crate) struct Names {
+    pins: HashMap<String, String>,
+    taken: HashSet<String>,
+    fold: fn(&str) -> String,
+}
+
+impl Names {
+    /// `escape` renames pins that clash with a keyword and `fold` gives the
+    /// form in which two names clash
+    pub(crate) fn new(
+        chip: &Chip,
+        escape: impl Fn(&str) -> Option<String>,
+        fold: fn(&str) -> String,
+    ) -> Self {
+        let pinlines: Vec<&Pinline> = chip
+            .pinlines
+            .input
+            .iter()
+            .chain(chip.pinlines.output.iter())
+            .chain(chip.pinlines.internal.iter())
+            .collect();
+        let mut names = Names {
+            pins: HashMap::new(),
+            taken: std::iter::once("clk")
+                .chain(pinlines.iter().map(|p| p.name.as_str()))
+                .map(fold)
+                .collect(),
+            fold,
+        };
+        for pinline in pinlines {
+            let name = match escape(&pinline.name) {
+                Some(escaped) => names.fresh(&escaped),
+                None => pinline.name.clone(),
+            };
+            names.pins.insert(pinline.name.clone(), name);
+        }
+        names
+    }
+    /// `base`, or `base` with a number after it if that is taken, which
+    /// then counts as taken
+    pub(crate) fn fresh(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 1;
+        while self.taken.contains(&(self.fold)(&name)) {
+            n += 1;
+            name = format!("{}_{}", base, n);
+        }
+        self.taken.insert((self.fold)(&name));
+        name
+    }
+    /// What to call a pin, or a name from `fresh` as it is
+    pub(crate) fn pin<'a>(&'a self, name: &'a str) -> &'a str {
+        self.pins.get(name).map_or(name, String::as_str)
+    }
+}
+
+/// Bits driving an input pin of a part, bit 0 first. Unconnected bits are
+/// false.
+pub(crate) fn input_bits<'a>(part: &'a Child, pin: &Pinline) -> Vec<Bit<'a>> {
+    let mut bits = vec![Bit::Constant(false); pin.pins.len()];
+    for connection in &part.input_connections {
+        if connection.own.name != pin.name {
+            continue;
+        }
+        for (k, own) in connection.own.indices.iter().enumerate() {
+            bits[*own] = if connection.foreign.is_constant() {
+                Bit::Constant(connection.foreign.name == "true")
+            } else {
+                Bit::Pin(
+                    &connection.foreign.name,
+                    connection.foreign.indices[k],
+                )
+            };
+        }
+    }
+    bits
+}
+
+/// Bits of the chip a connection from a part's output drives, in the order
+/// of the part's bits
+pub(crate) fn foreign_bits(connection: &ChildConnection) -> Vec<Bit<'_>> {
+    connection
+        .foreign
+        .indices
+        .iter()
+        .map(|i| Bit::Pin(&connection.foreign.name, *i))
+        .collect()
+}
+
+/// Whether a part's output pin has a single connection taking all of it, in
+/// order
+pub(crate) fn is_whole(connections: &[&ChildConnection], width: usize) -> bool {
+    connections.len() == 1
+        && connections[0].own.indices.iter().cloned().eq(0..width)
+}
+
+fn module(chip: &Chip) -> String {
+    if let Some(id) = &chip.builtin_id {
+        return builtin(id).to_string();
+    }
+    let width = |name: &str| {
+        chip.pinlines
+            .get_pinline(name)
+            .map_or(1, |pinline| pinline.pins.len())
+    };
+    let mut names = pin_names(chip);
+    let mut ports = Vec::new();
+    if chip.clocked {
+        ports.push("input clk".to_string());
+    }
+    for (direction, pinlines) in &[
+        ("input", &chip.pinlines.input),
+        ("output", &chip.pinlines.output),
+    ] {
+        for pinline in pinlines.iter() {
+            ports.push(format!(
+                "{} {}",
+                direction,
+                declaration(pinline, &names)
+            ));
+        }
+    }
+    let mut text = format!(
+        "module {}(\n    {}\n);\n",
+        ident(&chip.name),
+        ports.join(",\n    ")
+    );
+    for pinline in &chip.pinlines.internal {
+        text.push_str(&format!("    wire {};\n", declaration(pinline, &names)));
+    }
+
+    let mut instances = String::new();
+    let mut assigns = String::new();
+    for (i, part) in chip.parts.iter().enumerate() {
+        let instance = names.fresh(&format!("{}_{}", part.chip.name, i));
+        let part_names = pin_names(&part.chip);
+        let mut connections = Vec::new();
+        if part.chip.clocked {
+            connections.push(".clk(clk)".to_string());
+        }
+        for pin in &part.chip.pinlines.input {
+            let bits = input_bits(part, pin);
+            connections.push(format!(
+                ".{}({})",
+                part_names.pin(&pin.name),
+                expression(&bits, &width, &names)
+            ));
+        }
+        for pin in &part.chip.pinlines.output {
+            let used: Vec<&ChildConnection> = part
+                .output_connections
+                .iter()
+                .filter(|c| c.own.name == pin.name)
+                .collect();
+            let pin_width = pin.pins.len();
+            let target = if used.is_empty() {
+                String::new()
+            } else if is_whole(&used, pin_width) {
+                expression(&foreign_bits(used[0]), &width, &names)
+            } else {
+                // Split between several signals through a wire of its own
+                let wire = names.fresh(&format!("{}_{}", instance, pin.name));
+                text.push_str(&format!(
+                    "    wire {}{};\n",
+                    range(pin_width),
+                    wire
+                ));
+                for connection in used {
+                    let own: Vec<Bit> = connection
+                        .own
+                        .indices
+                        .iter()
+                        .map(|i| Bit::Pin(&wire, *i))
+                        .collect();
+                    assigns.push_str(&format!(
+                        "    assign {} = {};\n",
+                        expression(&foreign_bits(connection), &width, &names),
+                        expression(&own, &|_: &str| pin_width, &names),
+                    ));
+                }
+                wire
+            };
+            connections.push(format!(
+                ".{}({})",
+                part_names.pin(&pin.name),
+                target
+            ));
+        }
+        instances.push_str(&format!(
+            "    {} {}(\n        {}\n    );\n",
+            ident(&part.chip.name),
+            instance,
+            connections.join(",\n        ")
+        ));
+    }
+    text + &instances + &assigns + "endmodule\n"
+}
+
+fn builtin(id: &BuiltinChips) -> &'static str {
+    match id {
+        BuiltinChips::Nand => {
+            "module Nand(
+    input a,
+    input b,
+    output out
+);
+    assign out = ~(a & b);
+endmodule
+"
+        }
+        BuiltinChips::Not => {
+            "module Not(
+    input in,
+    output out
+);
+    assign out = ~in;
+endmodule
+"
+        }
+        BuiltinChips::Mux => {
+            "module Mux(
+    input a,
+    input b,
+    input sel,
+    output out
+);
+    assign out = sel ? b : a;
+endmodule
+"
+        }
+        BuiltinChips::DFF => {
+            "module DFF(
+    input clk,
+    input in,
+    output reg out
+);
+    initial out = 1'b0;
+    always @(posedge clk)
+        out <= in;
+endmodule
+"
+        }
+        BuiltinChips::Bit => {
+            "module Bit(
+    input clk,
+    input in,
+    input load,
+    output reg out
+);
+    initial out = 1'b0;
+    always @(posedge clk)
+        if (load)
+            out <= in;
+endmodule
+"
+        }
+        BuiltinChips::ROM32K => {
+            "module ROM32K(
+    input [14:0] address,
+    output [15:0] out
+);
+    reg [15:0] memory [0:32767];
+    assign out = memory[address];
+endmodule
+"
+        }
+        BuiltinChips::RAM16K => {
+            "module RAM16K(
+    input clk,
+    input [15:0] in,
+    input load,
+    input [13:0] address,
+    output [15:0] out
+);
+    reg [15:0] memory [0:16383];
+    integer i;
+    initial for (i = 0; i < 16384; i = i + 1) memory[i] = 16'b0;
+    always @(posedge clk)
+        if (load)
+            memory[address] <= in;
+    assign out = memory[address];
+endmodule
+"
+        }
+        BuiltinChips::Screen => {
+            "module Screen(
+    input clk,
+    input [15:0] in,
+    input load,
+    input [12:0] address,
+    output [15:0] out
+);
+    reg [15:0] memory [0:8191];
+    integer i;
+    initial for (i = 0; i < 8192; i = i + 1) memory[i] = 16'b0;
+    always @(posedge clk)
+        if (load)
+            memory[address] <= in;
+    assign out = memory[address];
+endmodule
+"
+        }
+        BuiltinChips::Keyboard => {
+            "module Keyboard(
+    output [15:0] out
+);
+    assign out = 16'b0;
+endmodule
+"
+        }
+    }
+}
+
+fn pin_names(chip: &Chip) -> Names {
+    Names::new(
+        chip,
+        |name| {
+            if RESERVED.contains(&name) {
+                Some(format!("{}_", name))
+            } else {
+                None
+            }
+        },
+        str::to_string,
+    )
+}
+
+fn ident(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn range(width: usize) -> String {
+    if width == 1 {
+        String::new()
+    } else {
+        format!("[{}:0] ", width - 1)
+    }
+}
+
+fn declaration(pinline: &Pinline, names: &Names) -> String {
+    format!("{}{}", range(pinline.pins.len()), names.pin(&pinline.name))
+}
+
+/// Runs of consecutive bits of the same signal, most significant first, as
+/// `(first bit, length)`
+pub(crate) fn runs<'a>(bits: &[Bit<'a>]) -> Vec<(Bit<'a>, usize)> {
+    let mut runs: Vec<(Bit, usize)> = Vec::new();
+    for bit in bits.iter().rev() {
+        if let Some((first, length)) = runs.last_mut() {
+            let follows = match (*first, *bit) {
+                (Bit::Pin(a, i), Bit::Pin(b, j)) => {
+                    a == b && i >= *length && j == i - *length
+                }
+                (Bit::Constant(a), Bit::Constant(b)) => a == b,
+                _ => false,
+            };
+            if follows {
+                *length += 1;
+                continue;
+            }
+        }
+        runs.push((*bit, 1));
+    }
+    runs
+}
+
+/// Bits, bit 0 first, as a signal, a slice of one or a concatenation
+fn expression(
+    bits: &[Bit],
+    width: &dyn Fn(&str) -> usize,
+    names: &Names,
+) -> String {
+    let parts: Vec<String> = runs(bits)
+        .into_iter()
+        .map(|(first, length)| match first {
+            Bit::Constant(value) => format!(
+                "{}'b{}",
+                length,
+                if value { "1" } else { "0" }.repeat(length)
+            ),
+            Bit::Pin(name, high)
+                if length == width(name) && high + 1 == length =>
+            {
+                names.pin(name).to_string()
+            }
+            Bit::Pin(name, high) if length == 1 => {
+                format!("{}[{}]", names.pin(name), high)
+            }
+            Bit::Pin(name, high) => {
+                format!("{}[{}:{}]", names.pin(name), high, high + 1 - length)
+            }
+        })
+        .collect();
+    if parts.len() == 1 {
+        parts[0].clone()
+    } else {
+        format!("{{{}}}", parts.join(", "))
+    }
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::fixtures::{load, load_str};
+
+    fn module_names(verilog: &str) -> Vec<&str> {
+        verilog
+            .lines()
+            .filter_map(|l| l.strip_prefix("module "))
+            .map(|l| &l[..l.find('(').unwrap()])
+            .collect()
+    }
+
+    #[test]
+    fn modules_once_parts_first() {
+        let verilog = load("Mux").to_verilog();
+        assert_eq!(
+            module_names(&verilog),
+            vec!["Not", "Nand", "Or", "And", "Xor", "Mux"]
+        );
+        assert!(verilog.contains("    assign out = ~(a & b);\n"));
+    }
+    #[test]
+    fn buses_and_ranges() {
+        let verilog = load("ALU").to_verilog();
+        assert!(verilog.contains(
+            "module ALU(
+    input [15:0] x,
+    input [15:0] y,
+    input zx,"
+        ));
+        // `and` is reserved
+        assert!(verilog.contains("    wire [15:0] and_;\n"));
+        assert!(verilog.contains(
+            "    Mux16 Mux16_0(
+        .a(x),
+        .b(16'b0000000000000000),
+        .sel(zx),
+        .out(zerox)
+    );"
+        ));
+        // One output split four ways
+        assert!(verilog.contains("    wire [15:0] Mux16_10_out;\n"));
+        assert!(verilog.contains("    assign out = Mux16_10_out;\n"));
+        assert!(verilog.contains("    assign ng = Mux16_10_out[15];\n"));
+        assert!(verilog.contains("    assign low = Mux16_10_out[7:0];\n"));
+        assert!(verilog.contains("    assign high = Mux16_10_out[15:8];\n"));
+        let verilog = load("And16").to_verilog();
+        assert!(verilog.contains(".a(a[3]),"));
+    }
+    #[test]
+    fn clocked() {
+        let verilog = load("Register").to_verilog();
+        assert!(verilog.contains("module Register(\n    input clk,\n"));
+        assert!(verilog.contains("    Bit Bit_0(\n        .clk(clk),\n"));
+        let verilog = load("Bit").to_verilog();
+        assert!(
+            verilog.contains("    always @(posedge clk)\n        out <= in;")
+        );
+        assert!(verilog.contains("    Mux Mux_0(\n        .a(checkout),"));
+        let verilog = load("Memory").to_verilog();
+        assert!(verilog.contains("    reg [15:0] memory [0:16383];\n"));
+        assert!(verilog.contains("    Keyboard Keyboard_4(\n        .out("));
+    }
+    #[test]
+    fn concatenations() {
+        let bits = [
+            Bit::Pin("a", 2),
+            Bit::Pin("a", 3),
+            Bit::Constant(true),
+            Bit::Constant(true),
+            Bit::Pin("b", 0),
+        ];
+        let names = pin_names(&load("And"));
+        assert_eq!(expression(&bits, &|_| 8, &names), "{b[0], 2'b11, a[3:2]}");
+        assert_eq!(expression(&bits[4..], &|_| 1, &names), "b");
+    }
+    #[test]
+    fn generated_names_clash() {
+        let chip = load_str(
+            "Clash",
+            "CHIP Clash { IN and, and_; OUT Not_0, Not_0_2_out, x; PARTS:
+            Not(in=and, out=Not_0, out=x);
+            Nand(a=and_, b=and, out=Not_0_2_out); }",
+            &[],
+        );
+        let verilog = chip.to_verilog();
+        assert!(verilog.contains("    input and__2,\n    input and_,\n"));
+        assert!(verilog.contains("    wire Not_0_2_out_2;\n"));
+        assert!(verilog.contains("    Not Not_0_2(\n        .in(and__2),\n"));
+        assert!(verilog.contains("    assign Not_0 = Not_0_2_out_2;\n"));
+        assert!(verilog.contains("    Nand Nand_1(\n        .a(and_),\n"));
+    }
+}
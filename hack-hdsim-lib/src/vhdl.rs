@@ -0,0 +1,557 @@
+use crate::chip::{BuiltinChips, ChildConnection, Chip};
+use crate::verilog::{
+    chip_types, foreign_bits, input_bits, is_whole, runs, Bit, Names,
+};
+
+/// Names VHDL keeps for itself, in any case. Pins that clash get `_pin`
+/// after them and chips `_chip`.
+const RESERVED: &[&str] = &[
+    "abs",
+    "access",
+    "after",
+    "alias",
+    "all",
+    "and",
+    "architecture",
+    "array",
+    "assert",
+    "attribute",
+    "begin",
+    "bit",
+    "block",
+    "body",
+    "boolean",
+    "buffer",
+    "bus",
+    "case",
+    "clk",
+    "component",
+    "configuration",
+    "constant",
+    "disconnect",
+    "downto",
+    "else",
+    "elsif",
+    "end",
+    "entity",
+    "exit",
+    "file",
+    "for",
+    "function",
+    "generate",
+    "generic",
+    "group",
+    "guarded",
+    "if",
+    "impure",
+    "in",
+    "inertial",
+    "inout",
+    "integer",
+    "is",
+    "label",
+    "library",
+    "linkage",
+    "literal",
+    "loop",
+    "map",
+    "mod",
+    "nand",
+    "natural",
+    "new",
+    "next",
+    "nor",
+    "not",
+    "null",
+    "of",
+    "on",
+    "open",
+    "or",
+    "others",
+    "out",
+    "package",
+    "port",
+    "postponed",
+    "procedure",
+    "process",
+    "pure",
+    "range",
+    "record",
+    "register",
+    "reject",
+    "rem",
+    "report",
+    "return",
+    "rol",
+    "ror",
+    "select",
+    "severity",
+    "shared",
+    "signal",
+    "sla",
+    "sll",
+    "sra",
+    "srl",
+    "state",
+    "std_logic",
+    "string",
+    "subtype",
+    "then",
+    "time",
+    "to",
+    "transport",
+    "type",
+    "units",
+    "until",
+    "use",
+    "variable",
+    "wait",
+    "when",
+    "while",
+    "with",
+    "xnor",
+    "xor",
+];
+
+const HEADER: &str = "library ieee;
+use ieee.std_logic_1164.all;
+use ieee.numeric_std.all;
+
+";
+
+// ============================================================================
+
+impl Chip {
+    /// A VHDL entity and architecture for this chip and every chip it is
+    /// built from, parts first. Clocked entities take a `clk` port and DFF
+    /// and Bit latch on its rising edge.
+    pub fn to_vhdl(&self) -> String {
+        chip_types(self)
+            .iter()
+            .map(|chip| HEADER.to_string() + &design(chip))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn design(chip: &Chip) -> String {
+    let mut names = pin_names(chip);
+    let mut ports = Vec::new();
+    if chip.clocked {
+        ports.push("clk : in std_logic".to_string());
+    }
+    for (direction, pinlines) in
+        &[("in", &chip.pinlines.input), ("out", &chip.pinlines.output)]
+    {
+        for pinline in pinlines.iter() {
+            ports.push(format!(
+                "{} : {} {}",
+                names.pin(&pinline.name),
+                direction,
+                logic(pinline.pins.len())
+            ));
+        }
+    }
+    let name = entity(&chip.name);
+    let mut text = format!(
+        "entity {} is\n    port (\n        {}\n    );\nend entity;\n\n",
+        name,
+        ports.join(";\n        ")
+    );
+    let (signals, body) = match &chip.builtin_id {
+        Some(id) => builtin(id),
+        None => structure(chip, &mut names),
+    };
+    let kind = if chip.is_builtin() {
+        "behavioural"
+    } else {
+        "structural"
+    };
+    text.push_str(&format!("architecture {} of {} is\n", kind, name));
+    text.push_str(&signals);
+    text.push_str("begin\n");
+    text.push_str(&body);
+    text.push_str("end architecture;\n");
+    text
+}
+
+/// Signal declarations and statements of a chip built from parts
+fn structure(chip: &Chip, names: &mut Names) -> (String, String) {
+    let width = |name: &str| {
+        chip.pinlines
+            .get_pinline(name)
+            .map_or(1, |pinline| pinline.pins.len())
+    };
+    let mut signals = String::new();
+    for pinline in &chip.pinlines.internal {
+        signals.push_str(&signal(names.pin(&pinline.name), pinline.pins.len()));
+    }
+    let mut instances = String::new();
+    let mut assigns = String::new();
+    for (i, part) in chip.parts.iter().enumerate() {
+        let instance = names.fresh(&format!("{}_{}", part.chip.name, i));
+        let part_names = pin_names(&part.chip);
+        let mut ports = Vec::new();
+        if part.chip.clocked {
+            ports.push("clk => clk".to_string());
+        }
+        for own in &part.chip.pinlines.input {
+            let bits = input_bits(part, own);
+            let actual = match slice(&bits, &width, names) {
+                Some(slice) => slice,
+                // Constants and concatenations go through a signal
+                None => {
+                    let helper =
+                        names.fresh(&format!("{}_{}", instance, own.name));
+                    signals.push_str(&signal(&helper, own.pins.len()));
+                    assigns.push_str(&format!(
+                        "    {} <= {};\n",
+                        helper,
+                        concatenation(&bits, &width, names)
+                    ));
+                    helper
+                }
+            };
+            ports.push(format!("{} => {}", part_names.pin(&own.name), actual));
+        }
+        for own in &part.chip.pinlines.output {
+            let used: Vec<&ChildConnection> = part
+                .output_connections
+                .iter()
+                .filter(|c| c.own.name == own.name)
+                .collect();
+            let own_width = own.pins.len();
+            let whole = if is_whole(&used, own_width) {
+                slice(&foreign_bits(used[0]), &width, names)
+            } else {
+                None
+            };
+            let actual = match whole {
+                _ if used.is_empty() => "open".to_string(),
+                Some(slice) => slice,
+                None => {
+                    let helper =
+                        names.fresh(&format!("{}_{}", instance, own.name));
+                    signals.push_str(&signal(&helper, own_width));
+                    for connection in used {
+                        assigns.push_str(&split(
+                            connection, &helper, own_width, &width, names,
+                        ));
+                    }
+                    helper
+                }
+            };
+            ports.push(format!("{} => {}", part_names.pin(&own.name), actual));
+        }
+        instances.push_str(&format!(
+            "    {}: entity work.{} port map (\n        {}\n    );\n",
+            instance,
+            entity(&part.chip.name),
+            ports.join(",\n        ")
+        ));
+    }
+    (signals, instances + &assigns)
+}
+
+/// Assignments from a part's output, held in `helper`, to what one
+/// connection drives
+fn split(
+    connection: &ChildConnection,
+    helper: &str,
+    helper_width: usize,
+    width: &dyn Fn(&str) -> usize,
+    names: &Names,
+) -> String {
+    let targets = foreign_bits(connection);
+    let sources: Vec<Bit> = connection
+        .own
+        .indices
+        .iter()
+        .map(|i| Bit::Pin(helper, *i))
+        .collect();
+    let helper_width = |_: &str| helper_width;
+    if let (Some(target), Some(source)) = (
+        slice(&targets, width, names),
+        slice(&sources, &helper_width, names),
+    ) {
+        return format!("    {} <= {};\n", target, source);
+    }
+    targets
+        .iter()
+        .zip(sources.iter())
+        .map(|(target, source)| {
+            format!(
+                "    {} <= {};\n",
+                slice(&[*target], width, names).unwrap(),
+                slice(&[*source], &helper_width, names).unwrap()
+            )
+        })
+        .collect()
+}
+
+/// Bits that are all of a signal or one range of it
+fn slice(
+    bits: &[Bit],
+    width: &dyn Fn(&str) -> usize,
+    names: &Names,
+) -> Option<String> {
+    match runs(bits).as_slice() {
+        [(Bit::Pin(name, high), length)] => {
+            Some(range(name, *high, *length, width, names))
+        }
+        _ => None,
+    }
+}
+
+fn range(
+    name: &str,
+    high: usize,
+    length: usize,
+    width: &dyn Fn(&str) -> usize,
+    names: &Names,
+) -> String {
+    let whole = length == width(name) && high + 1 == length;
+    let name = names.pin(name);
+    if whole {
+        name.to_string()
+    } else if length == 1 {
+        format!("{}({})", name, high)
+    } else {
+        format!("{}({} downto {})", name, high, high + 1 - length)
+    }
+}
+
+fn concatenation(
+    bits: &[Bit],
+    width: &dyn Fn(&str) -> usize,
+    names: &Names,
+) -> String {
+    runs(bits)
+        .into_iter()
+        .map(|(first, length)| match first {
+            Bit::Constant(value) => {
+                let digits = if value { "1" } else { "0" }.repeat(length);
+                if length == 1 {
+                    format!("'{}'", digits)
+                } else {
+                    format!("\"{}\"", digits)
+                }
+            }
+            Bit::Pin(name, high) => range(name, high, length, width, names),
+        })
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+fn builtin(id: &BuiltinChips) -> (String, String) {
+    let state = "    signal state : std_logic := '0';\n".to_string();
+    match id {
+        BuiltinChips::Nand => {
+            (String::new(), "    out_pin <= not (a and b);\n".to_string())
+        }
+        BuiltinChips::Not => {
+            (String::new(), "    out_pin <= not in_pin;\n".to_string())
+        }
+        BuiltinChips::Mux => (
+            String::new(),
+            "    out_pin <= b when sel = '1' else a;\n".to_string(),
+        ),
+        BuiltinChips::DFF => (
+            state,
+            "    process (clk)
+    begin
+        if rising_edge(clk) then
+            state <= in_pin;
+        end if;
+    end process;
+    out_pin <= state;
+"
+            .to_string(),
+        ),
+        BuiltinChips::Bit => (
+            state,
+            "    process (clk)
+    begin
+        if rising_edge(clk) and load = '1' then
+            state <= in_pin;
+        end if;
+    end process;
+    out_pin <= state;
+"
+            .to_string(),
+        ),
+        BuiltinChips::ROM32K => memory(32768, false),
+        BuiltinChips::RAM16K => memory(16384, true),
+        BuiltinChips::Screen => memory(8192, true),
+        BuiltinChips::Keyboard => (
+            String::new(),
+            "    out_pin <= (others => '0');\n".to_string(),
+        ),
+    }
+}
+
+/// An array of words read at `address`, written on the rising edge when
+/// `load` is set if it's writable
+fn memory(words: usize, writable: bool) -> (String, String) {
+    let signals = format!(
+        "    type words is array (0 to {}) of std_logic_vector(15 downto 0);
+    signal memory : words := (others => (others => '0'));
+",
+        words - 1
+    );
+    let read = "    out_pin <= memory(to_integer(unsigned(address)));\n";
+    let write = "    process (clk)
+    begin
+        if rising_edge(clk) and load = '1' then
+            memory(to_integer(unsigned(address))) <= in_pin;
+        end if;
+    end process;
+";
+    if writable {
+        (signals, write.to_string() + read)
+    } else {
+        (signals, read.to_string())
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    let name = name.to_lowercase();
+    RESERVED.contains(&name.as_str())
+}
+
+fn pin_names(chip: &Chip) -> Names {
+    Names::new(
+        chip,
+        |name| {
+            if is_reserved(name) {
+                Some(format!("{}_pin", name))
+            } else {
+                None
+            }
+        },
+        str::to_lowercase,
+    )
+}
+
+fn entity(name: &str) -> String {
+    if is_reserved(name) {
+        format!("{}_chip", name)
+    } else {
+        name.to_string()
+    }
+}
+
+fn logic(width: usize) -> String {
+    if width == 1 {
+        "std_logic".to_string()
+    } else {
+        format!("std_logic_vector({} downto 0)", width - 1)
+    }
+}
+
+fn signal(name: &str, width: usize) -> String {
+    format!("    signal {} : {};\n", name, logic(width))
+}
+
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use crate::loader::fixtures::{load, load_str};
+
+    #[test]
+    fn entities_parts_first() {
+        let vhdl = load("Mux").to_vhdl();
+        let entities: Vec<&str> = vhdl
+            .lines()
+            .filter_map(|l| l.strip_prefix("entity "))
+            .collect();
+        assert_eq!(
+            entities,
+            vec![
+                "Not_chip is",
+                "Nand_chip is",
+                "Or_chip is",
+                "And_chip is",
+                "Xor_chip is",
+                "Mux is"
+            ]
+        );
+        assert!(vhdl.contains(
+            "entity Nand_chip is
+    port (
+        a : in std_logic;
+        b : in std_logic;
+        out_pin : out std_logic
+    );
+end entity;"
+        ));
+        assert!(vhdl.contains("entity work.Not_chip port map (\n"));
+    }
+    #[test]
+    fn buses_and_constants() {
+        let vhdl = load("ALU").to_vhdl();
+        assert!(
+            vhdl.contains("        x : in std_logic_vector(15 downto 0);\n")
+        );
+        assert!(vhdl
+            .contains("    signal and_pin : std_logic_vector(15 downto 0);\n"));
+        assert!(vhdl.contains(
+            "    Mux16_0: entity work.Mux16 port map (
+        a => x,
+        b => Mux16_0_b,
+        sel => zx,
+        out_pin => zerox
+    );"
+        ));
+        assert!(vhdl.contains("    Mux16_0_b <= \"0000000000000000\";\n"));
+        assert!(vhdl.contains("    out_pin <= Mux16_10_out;\n"));
+        assert!(vhdl.contains("    ng <= Mux16_10_out(15);\n"));
+        assert!(vhdl.contains("    high <= Mux16_10_out(15 downto 8);\n"));
+        let vhdl = load("And16").to_vhdl();
+        assert!(vhdl.contains("        a => a(3),\n"));
+    }
+    #[test]
+    fn clocked() {
+        let vhdl = load("Register").to_vhdl();
+        assert!(vhdl.contains("entity Register_chip is\n    port (\n        clk : in std_logic;\n"));
+        assert!(vhdl.contains(
+            "entity work.Bit_chip port map (\n        clk => clk,\n"
+        ));
+        let vhdl = load("Bit").to_vhdl();
+        assert!(vhdl.contains(
+            "        if rising_edge(clk) then\n            state <= in_pin;\n"
+        ));
+        let vhdl = load("Memory").to_vhdl();
+        assert!(vhdl.contains(
+            "    out_pin <= memory(to_integer(unsigned(address)));\n"
+        ));
+    }
+    #[test]
+    fn generated_names_clash() {
+        let chip = load_str(
+            "ClashCase",
+            "CHIP ClashCase { IN in, IN_pin, nand_1_b;
+            OUT Not_0, Not_0_2_out, x; PARTS:
+            Not(in=in, out=Not_0, out=x);
+            Nand(a=IN_pin, b=true, out=Not_0_2_out); }",
+            &[],
+        );
+        let vhdl = chip.to_vhdl();
+        assert!(vhdl.contains(
+            "        in_pin_2 : in std_logic;
+        IN_pin : in std_logic;
+        nand_1_b : in std_logic;"
+        ));
+        assert!(vhdl.contains("    signal Not_0_2_out_2 : std_logic;\n"));
+        assert!(vhdl.contains(
+            "    Not_0_2: entity work.Not_chip port map (
+        in_pin => in_pin_2,
+        out_pin => Not_0_2_out_2
+    );"
+        ));
+        assert!(vhdl.contains("    Not_0 <= Not_0_2_out_2;\n"));
+        // VHDL names ignore case
+        assert!(vhdl.contains("        b => Nand_1_b_2,\n"));
+    }
+}